@@ -0,0 +1,35 @@
+struct VertexOutput {
+    [[location(0)]] uv: vec2<f32>;
+    [[location(1)]] color: vec3<f32>;
+    [[builtin(position)]] position: vec4<f32>;
+};
+
+[[group(0), binding(0)]]
+var atlas: texture_2d<f32>;
+[[group(0), binding(1)]]
+var atlas_sampler: sampler;
+
+[[stage(vertex)]]
+fn vs_main(
+    [[location(0)]] in_pos: vec2<f32>,
+    [[location(1)]] in_uv_vs: vec2<f32>,
+    [[location(2)]] in_instance_loc: vec2<f32>,
+    [[location(3)]] in_instance_size: vec2<f32>,
+    [[location(4)]] in_instance_uv_loc: vec2<f32>,
+    [[location(5)]] in_instance_uv_size: vec2<f32>,
+    [[location(6)]] in_instance_color: vec3<f32>,
+    ) -> VertexOutput {
+    var pos2: vec2<f32> = in_pos * in_instance_size + in_instance_loc;
+    var out: VertexOutput;
+    // the rows of the atlas go down
+    out.uv = in_instance_uv_loc + vec2<f32>(in_uv_vs.x, 1.0 - in_uv_vs.y) * in_instance_uv_size;
+    out.color = in_instance_color;
+    out.position = vec4<f32>(pos2, 0.0, 1.0);
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32>{
+    let alpha = textureSample(atlas, atlas_sampler, in.uv).r;
+    return vec4<f32>(in.color, alpha);
+}
@@ -1,10 +1,147 @@
-use rodio::{source::Source, Decoder, OutputStreamHandle, Sample};
+use rodio::{source::Source, Sample};
+use serde::Deserialize;
 use std::{
+    ops::Range,
     path::Path,
-    sync::{Arc, RwLock},
-    time::Duration,
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
 };
 
+pub mod backend;
+pub use backend::{AudioBackend, Output};
+
+mod crossfade;
+use crossfade::Crossfade;
+
+pub mod mixer;
+pub use mixer::{FadeCurve, TrackMixer};
+
+pub mod control;
+use control::{ControlledSource, Garbage, PlayerCommand, PlayerReport, PlayerState};
+
+mod decoder;
+use decoder::AudioDecoder;
+
+pub mod error;
+pub use error::{ExportError, LoadError};
+
+pub mod event;
+pub use event::PlayerEvent;
+
+pub mod export;
+pub use export::{ExportOptions, WavExporter};
+
+pub mod growing;
+pub use growing::GrowingBuffer;
+
+pub mod interpolation;
+pub use interpolation::Interpolation;
+
+pub mod peaks;
+pub use peaks::{Peak, Peaks};
+
+pub mod ramp;
+pub use ramp::RampCurve;
+use ramp::SpeedRamp;
+
+pub mod stretch;
+pub use stretch::TimeStretch;
+
+pub mod streaming;
+pub use streaming::StreamingBuffer;
+
+/// Random access to the decoded frames of an audio track.
+pub trait FrameBuffer<S>: Send + Sync {
+    fn channels(&self) -> u16;
+
+    fn sample_rate(&self) -> u32;
+
+    /// Number of frames of the track, or the number of frames known so far.
+    fn frame_count(&self) -> usize;
+
+    /// Returns `None` if the frame is not decoded yet.
+    fn sample(&self, frame: usize, channel: u16) -> Option<S>;
+
+    /// Copies the frames starting from `frame` into `out`,
+    /// the samples that are not decoded yet are left unchanged.
+    fn read_frames(&self, frame: usize, out: &mut [S]) {
+        let channels = self.channels() as usize;
+        for (i, sample) in out.iter_mut().enumerate() {
+            if let Some(value) = self.sample(frame + i / channels, (i % channels) as u16) {
+                *sample = value;
+            }
+        }
+    }
+
+    /// Tells the buffer which frame is being played, so it can decode around it.
+    fn set_read_frame(&self, _frame: usize) {}
+
+    /// Whether `frame_count` is the length of the whole track,
+    /// and not only the part decoded so far.
+    fn is_complete(&self) -> bool {
+        true
+    }
+
+    fn get_duration(&self) -> Duration {
+        frames_to_duration(self.frame_count(), self.sample_rate())
+    }
+
+    /// The peaks of the decoded frames, for drawing the waveform.
+    /// Buffers that never decode the whole track have none.
+    fn peaks(&self) -> Option<&Peaks> {
+        None
+    }
+
+    /// Why decoding stopped while playing, once after it happened.
+    /// The track ends where the frames that could be decoded end.
+    fn take_error(&self) -> Option<LoadError> {
+        None
+    }
+}
+
+pub type SharedBuffer<S> = Arc<dyn FrameBuffer<S>>;
+
+// events kept by the player until they are taken, more are dropped
+const MAX_EVENTS: usize = 16;
+// commands and reports waiting between the controller and the audio thread
+const QUEUE_CAPACITY: usize = 1024;
+// things the audio thread stopped using, kept by a player until they are sent away
+const GARBAGE_CAPACITY: usize = 16;
+// things waiting to be dropped by the controller
+const GARBAGE_QUEUE_CAPACITY: usize = 64;
+// how often the output is tried again while another one stands in for it
+const OUTPUT_RETRY_TIME: Duration = Duration::from_secs(5);
+
+/// How the decoded samples are kept in memory, they are played as f32 either way.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+pub enum SampleStorage {
+    /// Keeps the headroom and the precision of float and 24 bit files.
+    #[default]
+    F32,
+    /// Takes half the memory of f32, rounding the samples to 16 bits.
+    I16,
+}
+
+#[inline]
+fn convert_samples<S: Sample, T: Sample>(from: &[S], to: &mut [T]) {
+    for (to, from) in to.iter_mut().zip(from) {
+        *to = T::from(from);
+    }
+}
+
+pub fn frames_to_duration(frames: usize, sample_rate: u32) -> Duration {
+    let duration_ns = 1_000_000_000u64.checked_mul(frames as u64).unwrap() / sample_rate as u64;
+    Duration::new(
+        duration_ns / 1_000_000_000,
+        (duration_ns % 1_000_000_000) as u32,
+    )
+}
+
+pub fn duration_to_frames(time: Duration, sample_rate: u32) -> usize {
+    (time.as_secs() * sample_rate as u64
+        + (time.subsec_nanos() as u64 * sample_rate as u64 / 1_000_000_000)) as usize
+}
+
 /// A buffer of samples treated as a source.
 pub struct SamplesBuffer<S> {
     data: Vec<S>,
@@ -52,61 +189,41 @@ where
     }
 }
 
-impl SamplesBuffer<i16> {
-    pub fn load_from_file_async_stoppable<P: AsRef<Path>>(
-        path: P,
-        stop_loading: Arc<RwLock<bool>>,
-        progress: Arc<RwLock<f32>>,
-    ) -> Result<Self, String> {
-        let mut decoder = Decoder::new(std::io::BufReader::new(
-            std::fs::File::open(path).map_err(|e| format!("error opening file: {:?}", e))?,
-        ))
-        .map_err(|e| format!("error decode audio: {:?}", e))?;
-
-        let channels = decoder.channels();
-        let sample_rate = decoder.sample_rate();
-
-        let possible_data_duration = decoder.total_duration();
-
-        let mut data = match decoder.size_hint().1 {
-            Some(size) => Vec::with_capacity(size),
-            None => Vec::new(),
-        };
+impl<S, T> FrameBuffer<T> for SamplesBuffer<S>
+where
+    S: Sample + Send + Sync,
+    T: Sample,
+{
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.channels
+    }
 
-        let mut decoded = Duration::from_secs(0);
-        loop {
-            let frame_len = decoder.current_frame_len();
-            if frame_len == Some(0) {
-                break;
-            }
-            let mut frame = decoder
-                .by_ref()
-                .take(frame_len.unwrap_or(32768).min(32768))
-                .collect::<Vec<_>>();
-            if frame.is_empty() {
-                break;
-            }
-            if *stop_loading.read().unwrap() {
-                return Err("user stopped".to_string());
-            }
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
 
-            if let Some(duration) = possible_data_duration {
-                let duration_ns = 1_000_000_000u64.checked_mul(frame.len() as u64).unwrap()
-                    / sample_rate as u64
-                    / channels as u64;
-                decoded += Duration::new(
-                    duration_ns / 1_000_000_000,
-                    (duration_ns % 1_000_000_000) as u32,
-                );
-                if let Ok(mut progress) = progress.try_write() {
-                    *progress = decoded.as_secs_f32() / duration.as_secs_f32();
-                };
-            }
+    #[inline]
+    fn frame_count(&self) -> usize {
+        self.data.len() / self.channels as usize
+    }
 
-            data.append(&mut frame);
-        }
+    #[inline]
+    fn sample(&self, frame: usize, channel: u16) -> Option<T> {
+        self.data
+            .get(frame * self.channels as usize + channel as usize)
+            .map(T::from)
+    }
+
+    fn read_frames(&self, frame: usize, out: &mut [T]) {
+        let start = (frame * self.channels as usize).min(self.data.len());
+        let end = (start + out.len()).min(self.data.len());
+        convert_samples(&self.data[start..end], &mut out[..end - start]);
+    }
 
-        Ok(Self::new(channels, sample_rate, data))
+    fn get_duration(&self) -> Duration {
+        self.duration
     }
 }
 
@@ -119,106 +236,670 @@ impl SamplesBuffer<i16> {
 //     }
 // }
 
-pub struct AudioBufferLoader<S>(
-    Arc<RwLock<Option<Result<SamplesBuffer<S>, String>>>>,
-    Arc<RwLock<bool>>,
-    Arc<RwLock<f32>>,
-);
+type LoadResult<S> = Arc<RwLock<Option<Result<SharedBuffer<S>, LoadError>>>>;
+
+pub struct AudioBufferLoader<S> {
+    value: LoadResult<S>,
+    partial: Arc<RwLock<Option<SharedBuffer<S>>>>,
+    stop_loading: Arc<RwLock<bool>>,
+    progress: Arc<RwLock<f32>>,
+}
 
 impl<S> AudioBufferLoader<S> {
-    pub fn try_get_value(&mut self) -> Option<Result<SamplesBuffer<S>, String>> {
-        let mut v = self.0.write().unwrap();
+    /// Returns the buffer when loading is done. A corrupt stream gives
+    /// `LoadError::CorruptStream` here, after `try_get_partial` gave what was decoded.
+    pub fn try_get_value(&mut self) -> Option<Result<SharedBuffer<S>, LoadError>> {
+        let mut v = self.value.write().unwrap();
+        v.take()
+    }
+
+    /// Returns the buffer once enough of it is loaded to start playing,
+    /// it keeps growing until loading is done.
+    pub fn try_get_partial(&mut self) -> Option<SharedBuffer<S>> {
+        let mut v = self.partial.write().unwrap();
         v.take()
     }
 
     pub fn stop_loading(&self) {
-        let mut stop = self.1.write().unwrap();
+        let mut stop = self.stop_loading.write().unwrap();
         *stop = true;
     }
 
     pub fn get_progress(&self) -> f32 {
-        *self.2.read().unwrap()
+        *self.progress.read().unwrap()
     }
 }
 
-impl AudioBufferLoader<i16> {
-    pub fn load<P: AsRef<Path> + Send + Sync + 'static>(path: P) -> Self {
-        let value = Arc::new(RwLock::new(None));
-        let value2 = Arc::clone(&value);
-        let stop_loading = Arc::new(RwLock::new(false));
-        let stop_loading2 = Arc::clone(&stop_loading);
-        let progress = Arc::new(RwLock::new(0.0));
-        let progress2 = Arc::clone(&progress);
+impl<S> AudioBufferLoader<S>
+where
+    S: Sample + Send + Sync + 'static,
+{
+    fn empty() -> Self {
+        Self {
+            value: Arc::new(RwLock::new(None)),
+            partial: Arc::new(RwLock::new(None)),
+            stop_loading: Arc::new(RwLock::new(false)),
+            progress: Arc::new(RwLock::new(0.0)),
+        }
+    }
+
+    pub fn load<P: AsRef<Path> + Send + Sync + 'static>(path: P, storage: SampleStorage) -> Self {
+        let loader = Self::empty();
+        let value2 = Arc::clone(&loader.value);
+        let partial2 = Arc::clone(&loader.partial);
+        let stop_loading2 = Arc::clone(&loader.stop_loading);
+        let progress2 = Arc::clone(&loader.progress);
+        std::thread::spawn(move || {
+            let buffer = match storage {
+                SampleStorage::F32 => GrowingBuffer::<f32>::load_from_file_async_stoppable(
+                    path,
+                    stop_loading2,
+                    progress2,
+                    partial2,
+                )
+                .map(|buffer| buffer as SharedBuffer<S>),
+                SampleStorage::I16 => GrowingBuffer::<i16>::load_from_file_async_stoppable(
+                    path,
+                    stop_loading2,
+                    progress2,
+                    partial2,
+                )
+                .map(|buffer| buffer as SharedBuffer<S>),
+            };
+            let mut value = value2.write().unwrap();
+            *value = Some(buffer);
+        });
+        loader
+    }
+
+    /// Opens the file for streaming playback instead of decoding all of it.
+    pub fn load_streaming<P: AsRef<Path> + Send + Sync + 'static>(
+        path: P,
+        storage: SampleStorage,
+    ) -> Self {
+        let loader = Self::empty();
+        let value2 = Arc::clone(&loader.value);
+        let progress2 = Arc::clone(&loader.progress);
         std::thread::spawn(move || {
-            let buffer =
-                SamplesBuffer::load_from_file_async_stoppable(path, stop_loading2, progress2);
+            let buffer = match storage {
+                SampleStorage::F32 => StreamingBuffer::<f32>::open(path)
+                    .map(|buffer| Arc::new(buffer) as SharedBuffer<S>),
+                SampleStorage::I16 => StreamingBuffer::<i16>::open(path)
+                    .map(|buffer| Arc::new(buffer) as SharedBuffer<S>),
+            };
+            *progress2.write().unwrap() = 1.0;
             let mut value = value2.write().unwrap();
             *value = Some(buffer);
         });
-        Self(value, stop_loading, progress)
+        loader
     }
 }
 
 /// A source that plays the SamplesBuffer at any speed.
 pub struct BufferPlayer<S> {
-    buffer: Arc<SamplesBuffer<S>>,
+    buffer: SharedBuffer<S>,
     channel: u16,
     location: usize,
     interval: f32,
     speed: f32,
+    speed_ramp: Option<SpeedRamp>,
     loop_mode: bool,
+    // loop between these seconds instead of the whole buffer
+    loop_region: Option<Range<f32>>,
+    interpolation: Interpolation,
+    // interpolation weights of the frames around the location
+    weights: Vec<f32>,
+    // the frames used to calculate the current output frame
+    block: Vec<S>,
+    frame: Vec<S>,
+    time_stretch: bool,
+    // pitch shift in semitones
+    pitch: f32,
+    stretch: Option<TimeStretch<S>>,
+    stretch_frame: Vec<f32>,
+    // seconds to crossfade over when the playback jumps
+    crossfade_time: f32,
+    crossfade: Option<Crossfade<S>>,
+    // plays right after the end of the buffer, or before its start when reversed
+    next_buffer: Option<SharedBuffer<S>>,
+    started_next: bool,
+    // keeps what happens while playing, for `take_events`
+    reporting: bool,
+    // what happened since the events were taken, the first `event_count` of them
+    events: [PlayerEvent; MAX_EVENTS],
+    event_count: usize,
+    // what is no longer used, to be dropped off the audio thread
+    garbage: Vec<Garbage<S>>,
+    // what was reported when playing ran out of the buffer, until it moves again
+    ran_out: Option<PlayerEvent>,
+    // the frame at the location is not decoded yet
+    starved: bool,
 }
 
 impl<S> BufferPlayer<S> {
-    pub fn new(buffer: Arc<SamplesBuffer<S>>) -> Self {
-        // let end = buffer.data.len();
+    pub fn new(buffer: SharedBuffer<S>) -> Self {
         Self {
             buffer,
             channel: 0,
             location: 0,
             interval: 0.0,
             speed: 1.0,
+            speed_ramp: None,
             loop_mode: false,
+            loop_region: None,
+            interpolation: Interpolation::default(),
+            weights: Vec::new(),
+            block: Vec::new(),
+            frame: Vec::new(),
+            time_stretch: false,
+            pitch: 0.0,
+            stretch: None,
+            stretch_frame: Vec::new(),
+            crossfade_time: 0.01,
+            crossfade: None,
+            next_buffer: None,
+            started_next: false,
+            reporting: false,
+            events: [PlayerEvent::TrackEnded; MAX_EVENTS],
+            event_count: 0,
+            garbage: Vec::new(),
+            ran_out: None,
+            starved: false,
         }
     }
 
-    #[inline]
-    fn current_sample_location(&self) -> usize {
-        (self.location * self.buffer.channels as usize + self.channel as usize)
-            .min(self.buffer.data.len().saturating_sub(1))
+    /// Keeps what happens while playing, for `take_events`.
+    pub fn report_events(&mut self) {
+        self.reporting = true;
+    }
+
+    /// The events since the last call, oldest first.
+    pub fn take_events(&mut self) -> impl Iterator<Item = PlayerEvent> + '_ {
+        let count = std::mem::take(&mut self.event_count);
+        self.events[..count].iter().copied()
+    }
+
+    // nobody taking them is fine, more than `MAX_EVENTS` are dropped
+    fn emit(&mut self, event: PlayerEvent) {
+        if self.reporting && self.event_count < MAX_EVENTS {
+            self.events[self.event_count] = event;
+            self.event_count += 1;
+        }
+    }
+
+    /// Keeps room for what the player stops using, so it can be taken with `take_garbage`
+    /// and dropped on another thread. Without room it is dropped where it was used.
+    pub fn reserve_garbage(&mut self, capacity: usize) {
+        self.garbage.reserve_exact(capacity);
+    }
+
+    /// What the player stopped using since the last call.
+    pub fn take_garbage(&mut self) -> impl Iterator<Item = Garbage<S>> + '_ {
+        self.garbage.drain(..)
+    }
+
+    // never allocates, what does not fit is dropped here
+    fn throw_away(&mut self, garbage: Garbage<S>) {
+        if self.garbage.len() < self.garbage.capacity() {
+            self.garbage.push(garbage);
+        }
+    }
+
+    fn throw_away_stretch(&mut self) {
+        if let Some(stretch) = self.stretch.take() {
+            self.throw_away(Garbage::Stretch(stretch));
+        }
+    }
+
+    fn replace_crossfade(&mut self, crossfade: Option<Crossfade<S>>) {
+        if let Some(old) = std::mem::replace(&mut self.crossfade, crossfade) {
+            self.throw_away(Garbage::Crossfade(old));
+        }
+    }
+
+    // reports running out of the buffer once, as the end of the track,
+    // or as an underrun while the rest of it is still being decoded
+    fn run_out(&mut self) {
+        let event = if self.buffer.is_complete() {
+            PlayerEvent::TrackEnded
+        } else {
+            PlayerEvent::Underrun
+        };
+        if self.ran_out != Some(event) {
+            self.ran_out = Some(event);
+            self.emit(event);
+        }
     }
 
     pub fn get_time(&self) -> Duration {
-        let duration_ns = 1_000_000_000u64.checked_mul(self.location as u64).unwrap()
-            / self.buffer.sample_rate as u64;
-        Duration::new(
-            duration_ns / 1_000_000_000,
-            (duration_ns % 1_000_000_000) as u32,
-        )
+        frames_to_duration(self.location, self.buffer.sample_rate())
     }
 
     pub fn set_time(&mut self, time: Duration) {
-        self.location = ((time.as_secs() * self.buffer.sample_rate as u64
-            + (time.subsec_nanos() as u64 * self.buffer.sample_rate as u64 / 1_000_000_000))
-            as usize)
-            .min((self.buffer.data.len() / self.buffer.channels as usize).saturating_sub(1));
+        self.start_crossfade(None);
+        self.location = duration_to_frames(time, self.buffer.sample_rate())
+            .min(self.buffer.frame_count().saturating_sub(1));
+        self.interval = 0.0;
+        if let Some(stretch) = self.stretch.as_mut() {
+            stretch.set_position(self.location as f64);
+        }
+        self.ran_out = None;
+        self.buffer.set_read_frame(self.location);
     }
 
     pub fn get_speed(&self) -> f32 {
         self.speed
     }
 
+    /// Changes the speed at once, unless it is already gliding to this speed.
     pub fn set_speed(&mut self, speed: f32) {
+        if let Some(ramp) = &self.speed_ramp {
+            if ramp.target() == speed {
+                return;
+            }
+            self.speed_ramp = None;
+        }
+        self.apply_speed(speed);
+    }
+
+    /// Glides to the speed over `ramp_time` seconds.
+    pub fn ramp_speed(&mut self, speed: f32, ramp_time: f32, curve: RampCurve) {
+        let len = (ramp_time.max(0.0) * self.buffer.sample_rate() as f32) as usize;
+        if len == 0 {
+            self.speed_ramp = None;
+            self.apply_speed(speed);
+        } else {
+            self.speed_ramp = Some(SpeedRamp::new(self.speed, speed, len, curve));
+        }
+    }
+
+    fn apply_speed(&mut self, speed: f32) {
+        // fade out while still playing at the old speed, or fade in from silence
+        if self.paused(self.speed) != self.paused(speed) {
+            self.start_crossfade(None);
+        }
         self.speed = speed;
     }
 
-    pub fn set_buffer(&mut self, buffer: Arc<SamplesBuffer<S>>) {
-        self.buffer = buffer;
+    pub fn set_buffer(&mut self, buffer: SharedBuffer<S>) {
+        self.start_crossfade(None);
+        let old = std::mem::replace(&mut self.buffer, buffer);
+        self.throw_away(Garbage::Buffer(old));
+        self.throw_away_stretch();
+        self.set_next_buffer(None);
+        self.started_next = false;
+        self.ran_out = None;
+        self.emit(PlayerEvent::BufferSwapped);
+    }
+
+    // a player of `buffer` that plays the way this one does, for crossfading into it
+    fn successor(&self, buffer: SharedBuffer<S>) -> Self {
+        let mut player = Self::new(buffer);
+        if self.speed < 0.0 {
+            player.location = player.buffer.frame_count().saturating_sub(1);
+        }
+        player.speed = self.speed;
+        player.speed_ramp = self.speed_ramp.clone();
+        player.loop_mode = self.loop_mode;
+        player.interpolation = self.interpolation;
+        player.time_stretch = self.time_stretch;
+        player.pitch = self.pitch;
+        player.crossfade_time = self.crossfade_time;
+        player.reporting = self.reporting;
+        player.buffer.set_read_frame(player.location);
+        player
+    }
+
+    // seconds until playing runs out of the buffer, `None` when looping or paused
+    fn time_left(&self) -> Option<f32> {
+        if self.paused(self.speed) || self.loop_range(self.location).is_some() {
+            return None;
+        }
+        let frames = if self.speed < 0.0 {
+            self.location
+        } else {
+            self.buffer.frame_count().saturating_sub(self.location)
+        };
+        Some(frames as f32 / (self.buffer.sample_rate() as f32 * self.speed.abs()))
+    }
+
+    /// Queues the buffer to go on with, without a gap, when playing runs out of this one.
+    pub fn set_next_buffer(&mut self, buffer: Option<SharedBuffer<S>>) {
+        if let Some(old) = std::mem::replace(&mut self.next_buffer, buffer) {
+            self.throw_away(Garbage::Buffer(old));
+        }
+    }
+
+    /// Returns the queued buffer once after playing went on into it.
+    pub fn take_started_next(&mut self) -> Option<SharedBuffer<S>> {
+        std::mem::take(&mut self.started_next).then(|| Arc::clone(&self.buffer))
+    }
+
+    // switches to the queued buffer, `location` is past the end of the current buffer
+    // or before its start, and is carried over so no frame is lost or repeated
+    fn start_next(&mut self, location: isize) -> bool {
+        let next = match self.next_buffer.take() {
+            Some(next) => next,
+            None => return false,
+        };
+        let next_count = next.frame_count() as isize;
+        let location = if location < 0 {
+            next_count + location
+        } else {
+            location - self.buffer.frame_count() as isize
+        };
+        let old = std::mem::replace(&mut self.buffer, next);
+        self.throw_away(Garbage::Buffer(old));
+        self.location = location.min(next_count - 1).max(0) as usize;
+        if let Some(stretch) = self.stretch.as_mut() {
+            stretch.set_position(self.location as f64);
+        }
+        self.buffer.set_read_frame(self.location);
+        self.started_next = true;
+        self.ran_out = None;
+        self.emit(PlayerEvent::BufferSwapped);
+        true
     }
 
     pub fn set_loop_mode(&mut self, loop_mode: bool) {
         self.loop_mode = loop_mode;
     }
+
+    /// Loops between two points in seconds when loop mode is on.
+    pub fn set_loop_region(&mut self, loop_region: Option<Range<f32>>) {
+        self.loop_region = loop_region;
+    }
+
+    // the frames to loop in when playing at `location`. the region only takes effect
+    // once the location is inside it, so playing from before the region reaches it first.
+    fn loop_range(&self, location: usize) -> Option<Range<usize>> {
+        if !self.loop_mode {
+            return None;
+        }
+        let frame_count = self.buffer.frame_count();
+        if let Some(region) = &self.loop_region {
+            let sample_rate = self.buffer.sample_rate();
+            let to_frames = |time: f32| {
+                duration_to_frames(Duration::from_secs_f32(time.max(0.0)), sample_rate)
+                    .min(frame_count)
+            };
+            let range = to_frames(region.start)..to_frames(region.end);
+            if range.contains(&location) {
+                return Some(range);
+            }
+        }
+        Some(0..frame_count)
+    }
+
+    pub fn set_interpolation(&mut self, interpolation: Interpolation) {
+        self.interpolation = interpolation;
+    }
+
+    /// Keeps the pitch when the speed changes.
+    pub fn set_time_stretch(&mut self, time_stretch: bool) {
+        self.time_stretch = time_stretch;
+    }
+
+    /// Sets how long the crossfade is when the playback jumps, 0 turns it off.
+    pub fn set_crossfade_time(&mut self, crossfade_time: f32) {
+        self.crossfade_time = crossfade_time.max(0.0);
+    }
+
+    #[inline]
+    fn paused(&self, speed: f32) -> bool {
+        (self.buffer.sample_rate() as f32 * speed.abs()) as u32 == 0
+    }
+
+    // fade from what is playing now into what plays after the jump. the old audio
+    // continues from `tail_location`, or from the current location if it is not given.
+    fn start_crossfade(&mut self, tail_location: Option<f64>) {
+        let len = (self.crossfade_time * self.buffer.sample_rate() as f32) as usize;
+        if len == 0 {
+            self.replace_crossfade(None);
+            return;
+        }
+        let location = tail_location.unwrap_or_else(|| match &self.stretch {
+            Some(stretch) => stretch.position(),
+            None => self.location as f64 + self.interval as f64,
+        });
+        let tail = if self.paused(self.speed) || self.buffer.frame_count() == 0 {
+            None
+        } else {
+            Some((Arc::clone(&self.buffer), location, self.speed))
+        };
+        self.replace_crossfade(Some(Crossfade::new(len, tail)));
+    }
+
+    /// Shifts the pitch by `pitch` semitones, on top of the change caused by the speed.
+    pub fn set_pitch(&mut self, pitch: f32) {
+        self.pitch = pitch;
+    }
+
+    #[inline]
+    fn stretching(&self) -> bool {
+        self.time_stretch || self.pitch != 0.0
+    }
+
+    // continue from where the grains were taken when going back to resampling
+    fn leave_stretch(&mut self) {
+        if let Some(position) = self.stretch.as_ref().map(|stretch| stretch.position()) {
+            self.location = (position as usize).min(self.buffer.frame_count().saturating_sub(1));
+            self.interval = position.fract() as f32;
+            self.throw_away_stretch();
+        }
+    }
+
+    #[inline]
+    fn proceed(&mut self, frames: isize) {
+        let max_location = self.buffer.frame_count().saturating_sub(1);
+        match self.loop_range(self.location) {
+            Some(range) if !range.is_empty() => {
+                let unwrapped = self.location as isize + frames;
+                if unwrapped < range.start as isize || unwrapped >= range.end as isize {
+                    // fade from the audio going on past the loop point
+                    self.start_crossfade(Some(unwrapped as f64 + self.interval as f64));
+                    self.emit(PlayerEvent::LoopWrapped);
+                }
+                let v = (unwrapped - range.start as isize).rem_euclid(range.len() as isize);
+                self.location = range.start + v as usize;
+            }
+            _ => {
+                let v = self.location as isize + frames;
+                if (v < 0 || v > max_location as isize) && self.start_next(v) {
+                    return;
+                }
+                if v < 0 || v > max_location as isize {
+                    self.interval = 0.0;
+                    self.run_out();
+                } else {
+                    self.ran_out = None;
+                }
+                self.location = v.min(max_location as isize).max(0) as usize;
+            }
+        }
+        self.buffer.set_read_frame(self.location);
+    }
+}
+
+impl<S> BufferPlayer<S>
+where
+    S: Sample,
+{
+    // fill self.block with `len` frames starting from `start`, frames out of the loop
+    // are wrapped around into it in loop mode, and frames out of the buffer repeat
+    // the first or last frame otherwise.
+    fn read_block(&mut self, start: isize, len: usize, frame_count: usize) {
+        let channels = self.buffer.channels() as usize;
+        self.block.clear();
+        self.block.resize(len * channels, S::zero_value());
+
+        let loop_range = self
+            .loop_range(self.location)
+            .filter(|range| !range.is_empty());
+        let range = loop_range.clone().unwrap_or(0..frame_count);
+        let end = start + len as isize;
+        let inner_start = start.max(range.start as isize).min(range.end as isize);
+        let inner_end = end.min(range.end as isize).max(inner_start);
+        if inner_end > inner_start {
+            let offset = (inner_start - start) as usize * channels;
+            let inner_len = (inner_end - inner_start) as usize * channels;
+            self.buffer.read_frames(
+                inner_start as usize,
+                &mut self.block[offset..offset + inner_len],
+            );
+        }
+        for i in (0..len).filter(|&i| {
+            let frame = start + i as isize;
+            frame < inner_start || frame >= inner_end
+        }) {
+            let frame = start + i as isize;
+            let frame = match &loop_range {
+                Some(range) => {
+                    range.start
+                        + (frame - range.start as isize).rem_euclid(range.len() as isize) as usize
+                }
+                None => frame.max(0).min(frame_count as isize - 1) as usize,
+            };
+            for channel in 0..channels {
+                if let Some(sample) = self.buffer.sample(frame, channel as u16) {
+                    self.block[i * channels + channel] = sample;
+                }
+            }
+        }
+    }
+
+    // calculate all channels of the output frame at the current location
+    fn render_frame(&mut self, frame_count: usize) {
+        let offset = self
+            .interpolation
+            .weights(self.interval, self.speed, &mut self.weights);
+        let len = self.weights.len();
+        self.read_block(self.location as isize + offset, len, frame_count);
+
+        let channels = self.buffer.channels() as usize;
+        self.frame.clear();
+        for channel in 0..channels {
+            let value = self
+                .weights
+                .iter()
+                .enumerate()
+                .map(|(i, weight)| self.block[i * channels + channel].to_f32() * weight)
+                .sum::<f32>();
+            self.frame.push(S::from(&value));
+        }
+    }
+}
+
+impl<S> BufferPlayer<S>
+where
+    S: Sample,
+{
+    fn render_stretched_frame(&mut self, frame_count: usize) {
+        let channels = self.buffer.channels();
+        if self.stretch.as_ref().map(|stretch| stretch.channels()) != Some(channels) {
+            let mut stretch = TimeStretch::new(channels, self.buffer.sample_rate());
+            stretch.set_position(self.location as f64 + self.interval as f64);
+            self.throw_away_stretch();
+            self.stretch = Some(stretch);
+        }
+        // without time stretch the speed changes the pitch as well
+        let mut pitch = 2f32.powf(self.pitch / 12.0);
+        if !self.time_stretch {
+            pitch *= self.speed.abs();
+        }
+        let loop_range = self.loop_range(self.location);
+        let looping = loop_range.is_some();
+        let stretch = self.stretch.as_mut().unwrap();
+        let old_position = stretch.position();
+        stretch.next_frame(
+            self.buffer.as_ref(),
+            self.speed,
+            pitch,
+            loop_range,
+            &mut self.stretch_frame,
+        );
+        // the position stops at the ends of the buffer when not looping
+        let position = stretch.position();
+        let ran_out = if self.speed < 0.0 {
+            (position <= 0.0).then_some(-1)
+        } else {
+            (position >= frame_count as f64).then_some(frame_count as isize)
+        };
+        let started_next = !looping && ran_out.is_some_and(|location| self.start_next(location));
+        if !started_next {
+            self.location = (position as usize).min(frame_count - 1);
+            self.buffer.set_read_frame(self.location);
+            match ran_out {
+                Some(_) if !looping => self.run_out(),
+                _ => self.ran_out = None,
+            }
+        }
+        let wrapped = if self.speed < 0.0 {
+            position > old_position
+        } else {
+            position < old_position
+        };
+        if looping && wrapped {
+            self.emit(PlayerEvent::LoopWrapped);
+        }
+
+        self.frame.clear();
+        self.frame
+            .extend(self.stretch_frame.iter().map(|value| S::from(value)));
+    }
+}
+
+impl<S> BufferPlayer<S>
+where
+    S: Sample,
+{
+    // calculate all channels of the next output frame and move on
+    fn render_output_frame(&mut self) {
+        if let Some(ramp) = self.speed_ramp.as_mut() {
+            let speed = ramp.next_speed();
+            if ramp.is_done() {
+                self.speed_ramp = None;
+            }
+            self.apply_speed(speed);
+        }
+        let frame_count = self.buffer.frame_count();
+        if frame_count == 0 || self.paused(self.speed) {
+            self.frame.clear();
+            self.frame
+                .resize(self.buffer.channels() as usize, S::zero_value());
+        } else if self.stretching() {
+            self.check_starved();
+            self.render_stretched_frame(frame_count);
+        } else {
+            // resample the samples when speed is not integer
+            self.leave_stretch();
+            self.check_starved();
+            self.render_frame(frame_count);
+            let interval = self.interval + self.speed;
+            // keep self.interval positive
+            self.interval = interval - interval.floor();
+            self.proceed(interval.floor() as isize);
+        }
+        if let Some(crossfade) = self.crossfade.as_mut() {
+            if !crossfade.apply(&mut self.frame) {
+                self.replace_crossfade(None);
+            }
+        }
+    }
+}
+
+impl<S> BufferPlayer<S>
+where
+    S: Sample,
+{
+    // reports an underrun once when playing reaches frames that are not decoded yet
+    fn check_starved(&mut self) {
+        let starved = self.buffer.sample(self.location, 0).is_none();
+        if starved && !self.starved {
+            self.emit(PlayerEvent::Underrun);
+        }
+        self.starved = starved;
+    }
 }
 
 impl<S> Iterator for BufferPlayer<S>
@@ -227,80 +908,18 @@ where
 {
     type Item = S;
 
-    // resample the samples when speed is not integer
     #[inline]
     fn next(&mut self) -> Option<S> {
-        if self.buffer.data.is_empty()
-            || (self.buffer.sample_rate as f32 * self.speed.abs()) as u32 == 0
-        {
-            Some(S::zero_value())
-        } else {
-            let fract = self.speed.abs().fract();
-            // check if the fract is too small, which indicates that the speed is integer.
-            let value = if (self.buffer.sample_rate as f32 * fract) as u32 == 0 {
-                let value = self.buffer.data[self.current_sample_location()];
-                if self.channel >= self.channels() - 1 {
-                    let increment = self.speed.round() as isize;
-
-                    let max_location =
-                        (self.buffer.data.len() / self.buffer.channels as usize).saturating_sub(1);
-                    if self.loop_mode {
-                        let v = (self.location as isize + increment) % (max_location + 1) as isize;
-                        self.location = if v < 0 {
-                            (v + (max_location + 1) as isize) as usize
-                        } else {
-                            v as usize
-                        }
-                    } else {
-                        self.location = (self.location as isize + increment)
-                            .min(max_location as isize)
-                            .max(0) as usize;
-                    }
-                }
-                value
-            } else {
-                let value = {
-                    // resample location is aligned based on fractional part of the speed
-                    let denominator = 1.0 / fract;
-                    let numerator = denominator * self.interval;
-                    let value0 = self.buffer.data[self.current_sample_location()];
-                    let value1 = self.buffer.data[(self.current_sample_location()
-                        + self.buffer.channels as usize)
-                        % self.buffer.data.len()];
-                    Sample::lerp(value0, value1, numerator as u32, denominator as u32)
-                };
-
-                if self.channel >= self.channels() - 1 {
-                    let interval = self.interval + self.speed;
-                    // keep self.interval positive
-                    self.interval = if interval < 0.0 {
-                        interval.fract() + 1.0
-                    } else {
-                        interval.fract()
-                    };
-                    let proceed = interval.floor() as isize;
-
-                    let max_location =
-                        (self.buffer.data.len() / self.buffer.channels as usize).saturating_sub(1);
-                    if self.loop_mode {
-                        let v = (self.location as isize + proceed) % (max_location + 1) as isize;
-                        self.location = if v < 0 {
-                            (v + (max_location + 1) as isize) as usize
-                        } else {
-                            v as usize
-                        }
-                    } else {
-                        self.location = (self.location as isize + proceed)
-                            .min(max_location as isize)
-                            .max(0) as usize;
-                    }
-                }
-                value
-            };
-
-            self.channel = (self.channel + 1) % self.channels();
-            Some(value)
+        if self.channel == 0 {
+            self.render_output_frame();
         }
+        let value = self
+            .frame
+            .get(self.channel as usize)
+            .copied()
+            .unwrap_or_else(S::zero_value);
+        self.channel = (self.channel + 1) % self.channels();
+        Some(value)
     }
 }
 
@@ -315,12 +934,12 @@ where
 
     #[inline]
     fn channels(&self) -> u16 {
-        self.buffer.channels
+        self.buffer.channels()
     }
 
     #[inline]
     fn sample_rate(&self) -> u32 {
-        self.buffer.sample_rate
+        self.buffer.sample_rate()
     }
 
     #[inline]
@@ -329,122 +948,696 @@ where
     }
 }
 
+// the track loading in the background to play right after the target buffer
+struct NextTrack<S> {
+    path: String,
+    loader: Option<AudioBufferLoader<S>>,
+    buffer: Option<SharedBuffer<S>>,
+}
+
+// what was last asked of the player, for the getters
+#[derive(Clone)]
+struct Requested {
+    volume: f32,
+    speed: f32,
+    loop_mode: bool,
+    loop_region: Option<Range<f32>>,
+    interpolation: Interpolation,
+    time_stretch: bool,
+    pitch: f32,
+    crossfade_time: f32,
+    // seconds and curve of the crossfades between tracks
+    track_fade: (f32, FadeCurve),
+}
+
+impl Default for Requested {
+    fn default() -> Self {
+        Self {
+            volume: 0.25,
+            speed: 1.0,
+            loop_mode: false,
+            loop_region: None,
+            interpolation: Interpolation::default(),
+            time_stretch: false,
+            pitch: 0.0,
+            crossfade_time: 0.01,
+            track_fade: (0.0, FadeCurve::default()),
+        }
+    }
+}
+
+// what the audio thread reported and was not taken yet
+struct Reports<S> {
+    queue: rtrb::Consumer<PlayerReport<S>>,
+    // what the audio thread stopped using, dropped here
+    garbage: rtrb::Consumer<Garbage<S>>,
+    events: Vec<PlayerEvent>,
+    started_next: Option<SharedBuffer<S>>,
+}
+
+// the output playing a new player, and the queues to it
+struct OpenedOutput<S> {
+    backend: Box<dyn AudioBackend>,
+    commands: rtrb::Producer<PlayerCommand<S>>,
+    reports: rtrb::Consumer<PlayerReport<S>>,
+    garbage: rtrb::Consumer<Garbage<S>>,
+}
+
+/// Controls the playing from other threads.
+///
+/// The changes go to the audio thread through a lock-free queue and are applied at the
+/// start of the next frame, the audio thread never waits for a lock held here.
 pub struct AudioController<S> {
-    sink: rodio::Sink,
-    changed_target_buffer: Arc<RwLock<Option<Arc<SamplesBuffer<S>>>>>,
-    target_buffer: Arc<SamplesBuffer<S>>,
-    changed_time: Arc<RwLock<Option<f32>>>,
-    time: Arc<RwLock<f32>>,
-    speed: Arc<RwLock<f32>>,
-    loop_mode: Arc<RwLock<bool>>,
+    output: Output,
+    // plays the source until the controller is dropped
+    backend: Box<dyn AudioBackend>,
+    // when the output was last tried while another one stands in for it
+    fallback_tried: Option<Instant>,
+    // why the output could not be opened, until it is taken
+    output_error: Option<String>,
+    target_buffer: SharedBuffer<S>,
+    next_track: Option<NextTrack<S>>,
+    // only locked by the threads calling the controller
+    commands: Mutex<rtrb::Producer<PlayerCommand<S>>>,
+    reports: Mutex<Reports<S>>,
+    state: Arc<PlayerState>,
+    requested: Mutex<Requested>,
 }
 impl<S> AudioController<S>
 where
     S: Sample + Send + Sync + 'static,
 {
-    fn new(sink: rodio::Sink, buffer: Arc<SamplesBuffer<S>>) -> Self {
-        sink.set_volume(0.25);
+    /// Starts playing `buffer` on `output`.
+    pub fn new_with_buffer(output: &Output, buffer: SharedBuffer<S>) -> Self {
+        let requested = Requested::default();
+        let state = Arc::new(PlayerState::default());
+        state.set_speed(1.0);
+        let (backend, output_error) = backend::open(output);
+        let opened = Self::open_output(
+            backend,
+            Arc::clone(&buffer),
+            None,
+            0.0,
+            &requested,
+            Arc::clone(&state),
+        );
         Self {
-            sink,
-            changed_target_buffer: Arc::new(RwLock::new(None)),
-            target_buffer: Arc::clone(&buffer),
-            changed_time: Arc::new(RwLock::new(None)),
-            time: Arc::new(RwLock::new(0.0)),
-            speed: Arc::new(RwLock::new(1.0)),
-            loop_mode: Arc::new(RwLock::new(false)),
+            output: output.clone(),
+            backend: opened.backend,
+            fallback_tried: output_error.as_ref().map(|_| Instant::now()),
+            output_error,
+            target_buffer: buffer,
+            next_track: None,
+            commands: Mutex::new(opened.commands),
+            reports: Mutex::new(Reports {
+                queue: opened.reports,
+                garbage: opened.garbage,
+                events: Vec::new(),
+                started_next: None,
+            }),
+            state,
+            requested: Mutex::new(requested),
         }
     }
 
-    pub fn new_with_buffer(
-        audio_device: &OutputStreamHandle,
-        buffer: Arc<SamplesBuffer<S>>,
-    ) -> Self {
-        let sink = rodio::Sink::try_new(&audio_device).unwrap();
-        let controller = Self::new(sink, Arc::clone(&buffer));
-        let (target_buffer2, changed_time2, time2, speed2, loop_mode2) = (
-            Arc::clone(&controller.changed_target_buffer),
-            Arc::clone(&controller.changed_time),
-            Arc::clone(&controller.time),
-            Arc::clone(&controller.speed),
-            Arc::clone(&controller.loop_mode),
+    // a new player for `buffer` from `time` seconds on, playing as `requested` says
+    fn open_output(
+        mut backend: Box<dyn AudioBackend>,
+        buffer: SharedBuffer<S>,
+        next_buffer: Option<SharedBuffer<S>>,
+        time: f32,
+        requested: &Requested,
+        state: Arc<PlayerState>,
+    ) -> OpenedOutput<S> {
+        let (command_sender, commands) = rtrb::RingBuffer::new(QUEUE_CAPACITY);
+        let (report_sender, reports) = rtrb::RingBuffer::new(QUEUE_CAPACITY);
+        let (garbage_sender, garbage) = rtrb::RingBuffer::new(GARBAGE_QUEUE_CAPACITY);
+
+        let mut player = BufferPlayer::new(buffer);
+        player.report_events();
+        player.reserve_garbage(GARBAGE_CAPACITY);
+        player.set_speed(requested.speed);
+        player.set_loop_mode(requested.loop_mode);
+        player.set_loop_region(requested.loop_region.clone());
+        player.set_interpolation(requested.interpolation);
+        player.set_time_stretch(requested.time_stretch);
+        player.set_pitch(requested.pitch);
+        player.set_crossfade_time(requested.crossfade_time);
+        if time > 0.0 {
+            player.set_time(Duration::from_secs_f32(time));
+        }
+        player.set_next_buffer(next_buffer);
+        let mut mixer = TrackMixer::new(player);
+        mixer.reserve_garbage(GARBAGE_CAPACITY);
+        let (fade_time, curve) = requested.track_fade;
+        mixer.set_fade(fade_time, curve);
+        let source = ControlledSource::new(
+            mixer,
+            commands,
+            report_sender,
+            garbage_sender,
+            state,
+            requested.volume,
         );
-        let source = BufferPlayer::new(buffer).periodic_access(
-            std::time::Duration::from_secs_f32(0.001),
-            move |player| {
-                {
-                    let mut target_buffer = target_buffer2.write().unwrap();
-                    if let Some(buffer) = target_buffer.take() {
-                        player.set_buffer(buffer);
-                    }
-                }
-                {
-                    let mut changed_time = changed_time2.write().unwrap();
-                    if let Some(time) = changed_time.take() {
-                        player.set_time(std::time::Duration::from_secs_f32(time.max(0.0)));
-                    }
-                }
-                {
-                    let mut time = time2.write().unwrap();
-                    *time = player.get_time().as_secs_f32();
-                }
-                {
-                    let speed = speed2.read().unwrap();
-                    player.set_speed(*speed);
-                }
-                {
-                    let loop_mode = loop_mode2.read().unwrap();
-                    player.set_loop_mode(*loop_mode);
+        backend.play(Box::new(source.convert_samples()));
+        OpenedOutput {
+            backend,
+            commands: command_sender,
+            reports,
+            garbage,
+        }
+    }
+
+    /// The output asked for, which may not be the one playing, see `is_output_fallback`.
+    pub fn get_output(&self) -> &Output {
+        &self.output
+    }
+
+    /// Whether another output stands in for the one asked for, which could not be opened.
+    pub fn is_output_fallback(&self) -> bool {
+        self.fallback_tried.is_some()
+    }
+
+    /// Why the playing track could not be decoded any further, once after it happened.
+    pub fn take_buffer_error(&self) -> Option<LoadError> {
+        self.target_buffer.take_error()
+    }
+
+    /// Why the output could not be opened, once after it happened.
+    pub fn take_output_error(&mut self) -> Option<String> {
+        self.output_error.take()
+    }
+
+    /// Moves the playing to `output`, it goes on from the same time with the same settings.
+    pub fn set_output(&mut self, output: &Output) {
+        self.output = output.clone();
+        self.reopen_output();
+    }
+
+    /// Opens the output again when it stopped taking samples, e.g. after the sound
+    /// device was unplugged, and tries the output asked for again while another one
+    /// stands in for it. Call it regularly.
+    pub fn check_output(&mut self) {
+        if !self.backend.is_alive() {
+            log::warn!("the audio output stopped, opening it again");
+            self.reopen_output();
+        } else if self
+            .fallback_tried
+            .is_some_and(|tried| tried.elapsed() >= OUTPUT_RETRY_TIME)
+        {
+            match backend::try_open(&self.output) {
+                Ok(backend) => {
+                    log::info!("playing on {:?} again", self.output);
+                    self.fallback_tried = None;
+                    self.play_on(backend);
                 }
-            },
+                Err(_) => self.fallback_tried = Some(Instant::now()),
+            }
+        }
+    }
+
+    fn reopen_output(&mut self) {
+        // the old output lets go of the device or file before it is opened again
+        self.stop_output();
+        let (backend, error) = backend::open(&self.output);
+        self.fallback_tried = error.as_ref().map(|_| Instant::now());
+        if error.is_some() {
+            self.output_error = error;
+        }
+        self.play_on(backend);
+    }
+
+    // waits for the output to stop taking samples
+    fn stop_output(&mut self) {
+        self.backend = Box::new(backend::NullBackend::default());
+    }
+
+    // moves the playing to `backend`
+    fn play_on(&mut self, backend: Box<dyn AudioBackend>) {
+        // the old player stops before its state is taken over
+        self.stop_output();
+        let (buffer, next_buffer) = {
+            let reports = self.receive();
+            match &reports.started_next {
+                // it already went on into the queued buffer
+                Some(buffer) => (Arc::clone(buffer), None),
+                None => (
+                    Arc::clone(&self.target_buffer),
+                    self.next_track
+                        .as_ref()
+                        .and_then(|next| next.buffer.clone()),
+                ),
+            }
+        };
+        let requested = self.requested.lock().unwrap().clone();
+        let opened = Self::open_output(
+            backend,
+            buffer,
+            next_buffer,
+            self.state.get_time(),
+            &requested,
+            Arc::clone(&self.state),
         );
-        controller.sink.append(source);
-        controller
+        self.backend = opened.backend;
+        *self.commands.lock().unwrap() = opened.commands;
+        let mut reports = self.reports.lock().unwrap();
+        reports.queue = opened.reports;
+        reports.garbage = opened.garbage;
+    }
+
+    fn send(&self, command: PlayerCommand<S>) {
+        let mut commands = self.commands.lock().unwrap();
+        if commands.push(command).is_err() {
+            log::warn!("the audio thread is not taking commands");
+        }
+    }
+
+    // moves what the audio thread reported out of the queue
+    fn receive(&self) -> std::sync::MutexGuard<'_, Reports<S>> {
+        let mut reports = self.reports.lock().unwrap();
+        while let Ok(report) = reports.queue.pop() {
+            match report {
+                PlayerReport::Event(event) => reports.events.push(event),
+                PlayerReport::StartedNext(buffer) => reports.started_next = Some(buffer),
+            }
+        }
+        while reports.garbage.pop().is_ok() {}
+        reports
     }
 
     pub fn get_volume(&self) -> f32 {
-        self.sink.volume()
+        self.requested.lock().unwrap().volume
     }
 
     pub fn set_volume(&self, volume: f32) {
-        self.sink.set_volume(volume);
+        self.requested.lock().unwrap().volume = volume;
+        self.send(PlayerCommand::SetVolume(volume));
     }
 
-    pub fn set_target_buffer(&mut self, buffer: Arc<SamplesBuffer<S>>) {
+    pub fn set_target_buffer(&mut self, buffer: SharedBuffer<S>) {
         self.target_buffer = Arc::clone(&buffer);
+        self.send(PlayerCommand::SetBuffer(buffer));
+        // the player forgets the queued buffer when its buffer changes
+        if let Some(buffer) = self
+            .next_track
+            .as_ref()
+            .and_then(|next| next.buffer.clone())
         {
-            let mut target_buffer = self.changed_target_buffer.write().unwrap();
-            *target_buffer = Some(buffer);
+            self.send(PlayerCommand::SetNextBuffer(Some(buffer)));
         }
     }
 
-    pub fn get_target_buffer(&self) -> &Arc<SamplesBuffer<S>> {
+    pub fn get_target_buffer(&self) -> &SharedBuffer<S> {
         &self.target_buffer
     }
 
+    /// Loads the track at `path` with `loader` in the background, to go on with it
+    /// without a gap when the target buffer ends.
+    pub fn preload_next(&mut self, path: String, loader: AudioBufferLoader<S>) {
+        self.clear_next();
+        self.next_track = Some(NextTrack {
+            path,
+            loader: Some(loader),
+            buffer: None,
+        });
+    }
+
+    /// Stops preloading, and plays nothing after the target buffer.
+    pub fn clear_next(&mut self) {
+        if let Some(loader) = self.next_track.take().and_then(|next| next.loader) {
+            loader.stop_loading();
+        }
+        self.send(PlayerCommand::SetNextBuffer(None));
+    }
+
+    /// The path given to `preload_next`, kept after loading it failed.
+    pub fn get_next_path(&self) -> Option<&str> {
+        self.next_track.as_ref().map(|next| next.path.as_str())
+    }
+
+    /// Whether the preloaded track is loading or waiting to be played.
+    pub fn has_next(&self) -> bool {
+        self.next_track
+            .as_ref()
+            .is_some_and(|next| next.loader.is_some() || next.buffer.is_some())
+    }
+
+    /// Queues the preloaded track once enough of it is loaded, call it regularly.
+    pub fn update_next(&mut self) {
+        let next = match self.next_track.as_mut() {
+            Some(next) => next,
+            None => return,
+        };
+        let loader = match next.loader.as_mut() {
+            Some(loader) => loader,
+            None => return,
+        };
+        let mut buffer = loader.try_get_partial();
+        match loader.try_get_value() {
+            Some(Ok(value)) => {
+                buffer = Some(value);
+                next.loader = None;
+            }
+            Some(Err(e)) => {
+                log::error!("error preloading {}: {}", next.path, e);
+                next.loader = None;
+            }
+            None => {}
+        }
+        // the partial buffer grows into the same buffer
+        if let Some(buffer) = buffer {
+            if !next
+                .buffer
+                .as_ref()
+                .is_some_and(|old| Arc::ptr_eq(old, &buffer))
+            {
+                next.buffer = Some(Arc::clone(&buffer));
+                self.send(PlayerCommand::SetNextBuffer(Some(buffer)));
+            }
+        }
+    }
+
+    /// Returns the path of the preloaded track once after playing went on into it,
+    /// which is the target buffer from then on.
+    pub fn take_started_next(&mut self) -> Option<String> {
+        let buffer = self.receive().started_next.take()?;
+        self.target_buffer = buffer;
+        // it keeps loading as the target buffer
+        self.next_track.take().map(|next| next.path)
+    }
+
     pub fn get_speed(&self) -> f32 {
-        *self.speed.read().unwrap()
+        self.requested.lock().unwrap().speed
     }
 
     pub fn set_speed(&self, speed: f32) {
-        let mut dst_speed = self.speed.write().unwrap();
-        *dst_speed = speed;
+        self.requested.lock().unwrap().speed = speed;
+        self.send(PlayerCommand::SetSpeed(speed));
+    }
+
+    /// Glides to the speed over `ramp_time` seconds instead of changing it at once.
+    pub fn set_speed_with_ramp(&self, speed: f32, ramp_time: f32, curve: RampCurve) {
+        self.requested.lock().unwrap().speed = speed;
+        self.send(PlayerCommand::RampSpeed(speed, ramp_time, curve));
+    }
+
+    /// The speed that is playing right now, which differs from `get_speed` while gliding.
+    pub fn get_current_speed(&self) -> f32 {
+        self.state.get_speed()
     }
 
     pub fn get_time(&self) -> f32 {
-        *self.time.read().unwrap()
+        self.state.get_time()
     }
 
     pub fn change_time(&self, time: f32) {
-        *self.changed_time.write().unwrap() = Some(time);
-        *self.time.write().unwrap() = time;
+        self.send(PlayerCommand::SetTime(time));
+        self.state.set_time(time);
+    }
+
+    /// The events the player sent since the last call, oldest first.
+    pub fn take_events(&self) -> Vec<PlayerEvent> {
+        std::mem::take(&mut self.receive().events)
     }
 
     pub fn get_loop_mode(&self) -> bool {
-        *self.loop_mode.read().unwrap()
+        self.requested.lock().unwrap().loop_mode
     }
 
     pub fn set_loop_mode(&self, loop_mode: bool) {
-        let mut dst = self.loop_mode.write().unwrap();
-        *dst = loop_mode;
+        self.requested.lock().unwrap().loop_mode = loop_mode;
+        self.send(PlayerCommand::SetLoopMode(loop_mode));
+    }
+
+    /// The A-B loop region in seconds, used instead of the whole track in loop mode.
+    pub fn get_loop_region(&self) -> Option<Range<f32>> {
+        self.requested.lock().unwrap().loop_region.clone()
+    }
+
+    /// Sets the A-B loop region in seconds, the points are swapped if they are reversed.
+    /// An empty region is the same as `None`.
+    pub fn set_loop_region(&self, loop_region: Option<Range<f32>>) {
+        let loop_region = loop_region
+            .map(|region| region.start.min(region.end)..region.start.max(region.end))
+            .filter(|region| !region.is_empty());
+        self.requested.lock().unwrap().loop_region = loop_region.clone();
+        self.send(PlayerCommand::SetLoopRegion(loop_region));
+    }
+
+    pub fn get_interpolation(&self) -> Interpolation {
+        self.requested.lock().unwrap().interpolation
+    }
+
+    pub fn set_interpolation(&self, interpolation: Interpolation) {
+        self.requested.lock().unwrap().interpolation = interpolation;
+        self.send(PlayerCommand::SetInterpolation(interpolation));
+    }
+
+    pub fn get_time_stretch(&self) -> bool {
+        self.requested.lock().unwrap().time_stretch
+    }
+
+    pub fn set_time_stretch(&self, time_stretch: bool) {
+        self.requested.lock().unwrap().time_stretch = time_stretch;
+        self.send(PlayerCommand::SetTimeStretch(time_stretch));
+    }
+
+    /// Pitch shift in semitones.
+    pub fn get_pitch(&self) -> f32 {
+        self.requested.lock().unwrap().pitch
+    }
+
+    pub fn set_pitch(&self, pitch: f32) {
+        self.requested.lock().unwrap().pitch = pitch;
+        self.send(PlayerCommand::SetPitch(pitch));
+    }
+
+    /// How the audio is playing now, for exporting it the same way.
+    pub fn get_export_options(&self) -> ExportOptions {
+        ExportOptions {
+            speed: self.get_speed(),
+            volume: self.get_volume(),
+            loop_region: if self.get_loop_mode() {
+                self.get_loop_region()
+            } else {
+                None
+            },
+            interpolation: self.get_interpolation(),
+            time_stretch: self.get_time_stretch(),
+            pitch: self.get_pitch(),
+            crossfade_time: self.get_crossfade_time(),
+        }
+    }
+
+    /// Seconds to crossfade over on loop wraps, seeks, buffer swaps and pausing.
+    pub fn get_crossfade_time(&self) -> f32 {
+        self.requested.lock().unwrap().crossfade_time
+    }
+
+    pub fn set_crossfade_time(&self, crossfade_time: f32) {
+        self.requested.lock().unwrap().crossfade_time = crossfade_time;
+        self.send(PlayerCommand::SetCrossfadeTime(crossfade_time));
+    }
+
+    /// Seconds and curve of the crossfades from one track into the next,
+    /// 0 seconds plays the tracks one after another.
+    pub fn get_track_fade(&self) -> (f32, FadeCurve) {
+        self.requested.lock().unwrap().track_fade
+    }
+
+    pub fn set_track_fade(&self, fade_time: f32, curve: FadeCurve) {
+        self.requested.lock().unwrap().track_fade = (fade_time, curve);
+        self.send(PlayerCommand::SetTrackFade(fade_time, curve));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn switching_to_the_same_file_keeps_it_valid() {
+        let path = std::env::temp_dir().join(format!(
+            "yee_player_switch_output_{}.wav",
+            std::process::id()
+        ));
+        let output = Output::File(path.clone());
+        let buffer = Arc::new(SamplesBuffer::new(2, 48000, vec![0.5f32; 96000]));
+        let mut controller = AudioController::<f32>::new_with_buffer(&output, buffer);
+        std::thread::sleep(Duration::from_millis(100));
+        controller.set_output(&output);
+        std::thread::sleep(Duration::from_millis(100));
+        drop(controller);
+
+        // the header tells the length of what is in the file
+        let file_len = std::fs::metadata(&path).unwrap().len();
+        let mut reader = hound::WavReader::open(&path).unwrap();
+        let spec = reader.spec();
+        let data_len = reader.len() as u64 * spec.bits_per_sample as u64 / 8;
+        let read = reader.samples::<f32>().map_while(Result::ok).count();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read, reader.len() as usize);
+        assert!(read > 0);
+        assert!(
+            file_len - data_len < 100,
+            "{} bytes for {}",
+            file_len,
+            data_len
+        );
+    }
+
+    // a ramp inside the loop region at 0.2..0.4 seconds, and loud frames around it
+    // to tell reads out of the region apart
+    fn looping_player(speed: f32, time_stretch: bool) -> BufferPlayer<f32> {
+        let data: Vec<f32> = (0..1000)
+            .map(|i| {
+                if (200..400).contains(&i) {
+                    i as f32 / 1000.0
+                } else {
+                    10.0
+                }
+            })
+            .collect();
+        let mut player = BufferPlayer::<f32>::new(Arc::new(SamplesBuffer::new(1, 1000, data)));
+        player.set_crossfade_time(0.0);
+        player.set_loop_mode(true);
+        player.set_loop_region(Some(0.2..0.4));
+        player.set_time_stretch(time_stretch);
+        player.set_speed(speed);
+        player.set_time(Duration::from_millis(300));
+        player.report_events();
+        player
+    }
+
+    fn loop_wraps(player: &mut BufferPlayer<f32>) -> usize {
+        player
+            .take_events()
+            .filter(|event| matches!(event, PlayerEvent::LoopWrapped))
+            .count()
+    }
+
+    #[test]
+    fn loop_region_wraps_at_b_forward_and_at_a_backward() {
+        let mut player = looping_player(1.0, false);
+        let output: Vec<f32> = player.by_ref().take(200).collect();
+        let expected: Vec<f32> = (300..400)
+            .chain(200..300)
+            .map(|i| i as f32 / 1000.0)
+            .collect();
+        assert_eq!(output, expected);
+        assert_eq!(loop_wraps(&mut player), 1);
+
+        let mut player = looping_player(-1.0, false);
+        let output: Vec<f32> = player.by_ref().take(200).collect();
+        let expected: Vec<f32> = (200..=300)
+            .rev()
+            .chain((301..400).rev())
+            .map(|i| i as f32 / 1000.0)
+            .collect();
+        assert_eq!(output, expected);
+        assert_eq!(loop_wraps(&mut player), 1);
+    }
+
+    #[test]
+    fn loop_region_keeps_reads_inside_it() {
+        for (speed, time_stretch) in [
+            (1.5, false),
+            (-1.5, false),
+            (1.0, true),
+            (-1.0, true),
+            (0.7, true),
+            (-0.7, true),
+        ] {
+            let mut player = looping_player(speed, time_stretch);
+            player.set_interpolation(Interpolation::Linear);
+            let output: Vec<f32> = player.by_ref().take(3000).collect();
+            assert!(
+                loop_wraps(&mut player) >= 3,
+                "speed {} time stretch {}",
+                speed,
+                time_stretch
+            );
+            let location = player.get_time();
+            assert!(
+                (Duration::from_millis(200)..Duration::from_millis(400)).contains(&location),
+                "{:?} at speed {} time stretch {}",
+                location,
+                speed,
+                time_stretch
+            );
+            // skip the first grains fading in
+            for (i, sample) in output.iter().enumerate().skip(100) {
+                assert!(
+                    (0.19..0.41).contains(sample),
+                    "{} at {} with speed {} time stretch {}",
+                    sample,
+                    i,
+                    speed,
+                    time_stretch
+                );
+            }
+        }
+    }
+
+    fn dc_buffer(level: f32) -> SharedBuffer<f32> {
+        Arc::new(SamplesBuffer::new(1, 1000, vec![level; 1000]))
+    }
+
+    // the biggest change between two samples next to each other
+    fn largest_step(samples: &[f32]) -> f32 {
+        samples
+            .windows(2)
+            .map(|pair| (pair[1] - pair[0]).abs())
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn crossfades_keep_jumps_smooth() {
+        // a raised cosine over 100 frames from -0.5 to 0.5 moves at most this much a frame
+        let bound = std::f32::consts::PI / 2.0 / 100.0 + 1e-4;
+        for crossfade_time in [0.1, 0.0] {
+            let data: Vec<f32> = (0..1000)
+                .map(|i| if i < 500 { -0.5 } else { 0.5 })
+                .collect();
+            let mut player = BufferPlayer::<f32>::new(Arc::new(SamplesBuffer::new(1, 1000, data)));
+            player.set_crossfade_time(crossfade_time);
+            let mut seeked: Vec<f32> = player.by_ref().take(100).collect();
+            player.set_time(Duration::from_millis(700));
+            seeked.extend(player.by_ref().take(200));
+
+            let mut player = BufferPlayer::<f32>::new(dc_buffer(-0.5));
+            player.set_crossfade_time(crossfade_time);
+            let mut swapped: Vec<f32> = player.by_ref().take(100).collect();
+            player.set_buffer(dc_buffer(0.5));
+            swapped.extend(player.by_ref().take(200));
+
+            for output in [seeked, swapped] {
+                assert_eq!(output[99], -0.5);
+                // the new level is reached after the crossfade
+                assert!(output[200..].iter().all(|&sample| sample == 0.5));
+                match crossfade_time > 0.0 {
+                    true => assert!(largest_step(&output) <= bound, "{}", largest_step(&output)),
+                    false => assert_eq!(largest_step(&output), 1.0),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn speed_ramps_into_reverse() {
+        for curve in [RampCurve::Linear, RampCurve::Tape] {
+            let mut player = BufferPlayer::<f32>::new(dc_buffer(0.5));
+            player.set_time(Duration::from_millis(500));
+            player.ramp_speed(-1.0, 0.1, curve);
+            let _: Vec<f32> = player.by_ref().take(99).collect();
+            assert!(player.get_speed() > -1.0, "{:?}", curve);
+            player.next();
+            assert_eq!(player.get_speed(), -1.0, "{:?}", curve);
+            // it went forward, stopped and came back about the same way
+            let time = player.get_time().as_millis();
+            assert!((499..=500).contains(&time), "{} ms with {:?}", time, curve);
+            let _: Vec<f32> = player.by_ref().take(100).collect();
+            assert_eq!(player.get_time().as_millis(), time - 100, "{:?}", curve);
+        }
     }
 }
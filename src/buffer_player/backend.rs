@@ -0,0 +1,406 @@
+use rodio::{
+    cpal::traits::{DeviceTrait, HostTrait},
+    source::{Source, UniformSourceIterator},
+};
+use serde::Deserialize;
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc, Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+// how often the stand-ins for a sound device take the samples that are due
+const TICK: Duration = Duration::from_millis(10);
+// the format of the file output, the tracks are converted to it
+const FILE_CHANNELS: u16 = 2;
+const FILE_SAMPLE_RATE: u32 = 48000;
+// a sound device that took no samples for this long is gone
+const STALL_TIME: Duration = Duration::from_secs(2);
+
+/// Where the played audio goes.
+#[derive(Debug, Clone, Default, Deserialize, PartialEq, Eq)]
+pub enum Output {
+    /// The default sound device.
+    #[default]
+    Device,
+    /// The sound device with this name, one of `output_devices`.
+    NamedDevice(String),
+    /// Nowhere, the samples are only taken as fast as a sound device would.
+    Null,
+    /// A 32 bit float WAV file, written as fast as a sound device would play it.
+    File(PathBuf),
+}
+
+impl Output {
+    /// `device`, `device:<name>`, `null`, or the path of a wav file.
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "device" => Output::Device,
+            "null" => Output::Null,
+            _ => match value.strip_prefix("device:") {
+                Some(name) => Output::NamedDevice(name.to_string()),
+                None => Output::File(PathBuf::from(value)),
+            },
+        }
+    }
+}
+
+pub type OutputSource = Box<dyn Source<Item = f32> + Send>;
+
+/// Takes the played samples, like a sound device does.
+pub trait AudioBackend: Send + Sync {
+    /// Keeps taking samples from `source` until the backend is dropped.
+    fn play(&mut self, source: OutputSource);
+
+    /// Whether it is still taking samples.
+    fn is_alive(&mut self) -> bool {
+        true
+    }
+}
+
+/// The names of the sound devices, for `Output::NamedDevice`.
+pub fn output_devices() -> Vec<String> {
+    match rodio::cpal::default_host().output_devices() {
+        Ok(devices) => devices.filter_map(|device| device.name().ok()).collect(),
+        Err(e) => {
+            log::error!("error listing the output devices: {:?}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// Opens the output, or tells why it can not be opened.
+pub fn try_open(output: &Output) -> Result<Box<dyn AudioBackend>, String> {
+    match output {
+        Output::Device => DeviceBackend::open(None).map(|backend| Box::new(backend) as _),
+        Output::NamedDevice(name) => {
+            DeviceBackend::open(Some(name)).map(|backend| Box::new(backend) as _)
+        }
+        Output::Null => Ok(Box::<NullBackend>::default()),
+        Output::File(path) => FileBackend::create(path).map(|backend| Box::new(backend) as _),
+    }
+}
+
+/// Opens the output. The default sound device stands in for a named one that can not be
+/// opened, and the null output stands in for the rest, with the reason it was not opened.
+pub fn open(output: &Output) -> (Box<dyn AudioBackend>, Option<String>) {
+    let error = match try_open(output) {
+        Ok(backend) => return (backend, None),
+        Err(e) => e,
+    };
+    let (backend, error): (Box<dyn AudioBackend>, _) = match output {
+        Output::NamedDevice(_) => match DeviceBackend::open(None) {
+            Ok(backend) => (
+                Box::new(backend),
+                format!("{}, playing on the default device", error),
+            ),
+            Err(_) => (
+                Box::new(NullBackend::default()),
+                format!("{}, playing without sound", error),
+            ),
+        },
+        _ => (
+            Box::new(NullBackend::default()),
+            format!("{}, playing without sound", error),
+        ),
+    };
+    log::error!("{}", error);
+    (backend, Some(error))
+}
+
+/// A sound device through rodio.
+pub struct DeviceBackend {
+    sink: Option<rodio::Sink>,
+    // the stream lives on its own thread until this is dropped
+    _stop: mpsc::Sender<()>,
+    // samples taken so far, and when that last changed
+    taken: Arc<AtomicUsize>,
+    last_taken: (usize, Instant),
+    last_check: Instant,
+}
+
+impl DeviceBackend {
+    /// Opens the device named `name`, or the default one.
+    pub fn open(name: Option<&str>) -> Result<Self, String> {
+        let name = name.map(str::to_string);
+        // use another thread to create the OutputStream of rodio. avoid winit conflict.
+        let (sender, receiver) = mpsc::channel();
+        let (stop, stopped) = mpsc::channel::<()>();
+        std::thread::spawn(move || {
+            let stream = match &name {
+                Some(name) => find_device(name).and_then(|device| {
+                    rodio::OutputStream::try_from_device(&device)
+                        .map_err(|e| format!("error opening output device {:?}: {:?}", name, e))
+                }),
+                None => rodio::OutputStream::try_default()
+                    .map_err(|e| format!("error getting default OutputStream: {:?}", e)),
+            };
+            let (_stream, stream_handle) = match stream {
+                Err(e) => {
+                    let _ = sender.send(Err(e));
+                    return;
+                }
+                Ok(output) => output,
+            };
+            let sink = rodio::Sink::try_new(&stream_handle)
+                .map_err(|e| format!("error creating Sink: {:?}", e));
+            let _ = sender.send(sink);
+            drop(sender);
+            // returns once the backend is dropped
+            let _ = stopped.recv();
+            log::info!("audio_stream_thread end");
+        });
+        let sink = receiver
+            .recv()
+            .map_err(|_| "the audio stream thread stopped".to_string())??;
+        Ok(Self {
+            sink: Some(sink),
+            _stop: stop,
+            taken: Arc::new(AtomicUsize::new(0)),
+            last_taken: (0, Instant::now()),
+            last_check: Instant::now(),
+        })
+    }
+}
+
+fn find_device(name: &str) -> Result<rodio::Device, String> {
+    let mut devices = rodio::cpal::default_host()
+        .output_devices()
+        .map_err(|e| format!("error listing the output devices: {:?}", e))?;
+    devices
+        .find(|device| device.name().is_ok_and(|device_name| device_name == name))
+        .ok_or(format!("no output device named {:?}", name))
+}
+
+impl AudioBackend for DeviceBackend {
+    fn play(&mut self, source: OutputSource) {
+        if let Some(sink) = &self.sink {
+            sink.append(Counted {
+                source,
+                taken: Arc::clone(&self.taken),
+            });
+            self.last_taken = (0, Instant::now());
+        }
+    }
+
+    fn is_alive(&mut self) -> bool {
+        let taken = self.taken.load(Ordering::Relaxed);
+        let now = Instant::now();
+        // after a long time without checking, e.g. when the system was suspended,
+        // the device gets time to start taking samples again
+        if taken != self.last_taken.0 || now.duration_since(self.last_check) >= STALL_TIME {
+            self.last_taken = (taken, now);
+        }
+        self.last_check = now;
+        now.duration_since(self.last_taken.1) < STALL_TIME
+    }
+}
+
+// counts the samples the device takes, the device stops taking them when it is gone
+struct Counted {
+    source: OutputSource,
+    taken: Arc<AtomicUsize>,
+}
+
+impl Iterator for Counted {
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<f32> {
+        self.taken.fetch_add(1, Ordering::Relaxed);
+        self.source.next()
+    }
+}
+
+impl Source for Counted {
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        self.source.current_frame_len()
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
+
+impl Drop for DeviceBackend {
+    fn drop(&mut self) {
+        // the sink has to go before the stream
+        self.sink.take();
+    }
+}
+
+/// Takes the samples in real time on its own thread and throws them away.
+#[derive(Default)]
+pub struct NullBackend {
+    player: Option<PacedPlayer>,
+}
+
+impl AudioBackend for NullBackend {
+    fn play(&mut self, source: OutputSource) {
+        self.player = Some(PacedPlayer::start(source, |_| {}));
+    }
+}
+
+/// Writes the samples in real time into a WAV file.
+pub struct FileBackend {
+    writer: Option<hound::WavWriter<std::io::BufWriter<std::fs::File>>>,
+    path: PathBuf,
+    player: Option<PacedPlayer>,
+}
+
+impl FileBackend {
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref().to_path_buf();
+        let spec = hound::WavSpec {
+            channels: FILE_CHANNELS,
+            sample_rate: FILE_SAMPLE_RATE,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let writer = hound::WavWriter::create(&path, spec)
+            .map_err(|e| format!("error creating {:?}: {:?}", path, e))?;
+        Ok(Self {
+            writer: Some(writer),
+            path,
+            player: None,
+        })
+    }
+}
+
+impl AudioBackend for FileBackend {
+    fn play(&mut self, source: OutputSource) {
+        let mut writer = match self.writer.take() {
+            Some(writer) => writer,
+            None => return,
+        };
+        let path = self.path.clone();
+        let mut written = 0;
+        let source = UniformSourceIterator::new(source, FILE_CHANNELS, FILE_SAMPLE_RATE);
+        self.player = Some(PacedPlayer::start(Box::new(source), move |sample| {
+            if let Err(e) = writer.write_sample(sample) {
+                log::error!("error writing {:?}: {:?}", path, e);
+            }
+            written += 1;
+            // the header is kept up to date, the program may end without dropping the backend
+            if written % (FILE_SAMPLE_RATE as usize * FILE_CHANNELS as usize) == 0 {
+                let _ = writer.flush();
+            }
+        }));
+    }
+}
+
+// pulls samples from a source on its own thread, as fast as a sound device would
+struct PacedPlayer {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl PacedPlayer {
+    // `take` gets every sample, and is dropped on the thread when playing stops
+    fn start<F>(mut source: OutputSource, mut take: F) -> Self
+    where
+        F: FnMut(f32) + Send + 'static,
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop2 = Arc::clone(&stop);
+        let thread = std::thread::spawn(move || {
+            let mut last = Instant::now();
+            // frames that are due but not taken yet
+            let mut due = 0.0;
+            while !stop2.load(Ordering::Relaxed) {
+                std::thread::sleep(TICK);
+                let now = Instant::now();
+                // the format may change with the track
+                due += now.duration_since(last).as_secs_f64() * source.sample_rate() as f64;
+                last = now;
+                while due >= 1.0 {
+                    // whole frames only, a file ends with a complete one
+                    for _ in 0..source.channels() {
+                        match source.next() {
+                            Some(sample) => take(sample),
+                            None => return,
+                        }
+                    }
+                    due -= 1.0;
+                }
+            }
+        });
+        Self {
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for PacedPlayer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_backend_writes_in_real_time() {
+        let path =
+            std::env::temp_dir().join(format!("yee_player_backend_{}.wav", std::process::id()));
+        let source = rodio::source::SineWave::new(440.0).amplify(0.5);
+        let mut backend = FileBackend::create(&path).unwrap();
+        let start = Instant::now();
+        backend.play(Box::new(source));
+        std::thread::sleep(Duration::from_millis(300));
+        let playing = start.elapsed();
+        // waits for the writing thread to stop
+        drop(backend);
+        let stopped = start.elapsed();
+
+        let reader = hound::WavReader::open(&path).unwrap();
+        let spec = reader.spec();
+        let written = Duration::from_secs_f64(reader.duration() as f64 / spec.sample_rate as f64);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(spec.channels, FILE_CHANNELS);
+        // never ahead of the clock, and not far behind it while playing
+        assert!(written <= stopped, "{:?} written in {:?}", written, stopped);
+        assert!(
+            written >= playing / 2,
+            "{:?} written in {:?}",
+            written,
+            playing
+        );
+    }
+
+    #[test]
+    fn parse_output() {
+        assert_eq!(Output::parse("device"), Output::Device);
+        assert_eq!(Output::parse("null"), Output::Null);
+        assert_eq!(
+            Output::parse("device:USB Audio"),
+            Output::NamedDevice("USB Audio".to_string())
+        );
+        assert_eq!(
+            Output::parse("out.wav"),
+            Output::File(PathBuf::from("out.wav"))
+        );
+    }
+}
@@ -0,0 +1,302 @@
+use super::{
+    BufferPlayer, Crossfade, FadeCurve, Interpolation, PlayerEvent, RampCurve, SharedBuffer,
+    TimeStretch, TrackMixer,
+};
+use rodio::{source::Source, Sample};
+use rtrb::{Consumer, Producer};
+use std::{
+    ops::Range,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// What the controller asks of the audio thread, applied at the start of the next frame.
+pub enum PlayerCommand<S> {
+    SetBuffer(SharedBuffer<S>),
+    SetNextBuffer(Option<SharedBuffer<S>>),
+    /// Seconds to jump to.
+    SetTime(f32),
+    SetSpeed(f32),
+    /// Glides to the speed over the seconds with the curve.
+    RampSpeed(f32, f32, RampCurve),
+    SetLoopMode(bool),
+    SetLoopRegion(Option<Range<f32>>),
+    SetInterpolation(Interpolation),
+    SetTimeStretch(bool),
+    SetPitch(f32),
+    SetCrossfadeTime(f32),
+    SetTrackFade(f32, FadeCurve),
+    SetVolume(f32),
+}
+
+/// What the audio thread tells the controller.
+pub enum PlayerReport<S> {
+    Event(PlayerEvent),
+    /// Playing went on into the queued buffer.
+    StartedNext(SharedBuffer<S>),
+}
+
+/// What the audio thread stopped using, sent to the controller to be dropped there,
+/// since freeing memory can take too long for the audio thread.
+// boxing the player would allocate on the audio thread
+#[allow(clippy::large_enum_variant)]
+pub enum Garbage<S> {
+    Buffer(SharedBuffer<S>),
+    Player(BufferPlayer<S>),
+    Crossfade(Crossfade<S>),
+    Stretch(TimeStretch<S>),
+}
+
+/// How the player is doing, written by the audio thread every frame and read without locking.
+#[derive(Default)]
+pub struct PlayerState {
+    // f32 bits
+    time: AtomicU32,
+    speed: AtomicU32,
+}
+
+impl PlayerState {
+    pub fn get_time(&self) -> f32 {
+        f32::from_bits(self.time.load(Ordering::Relaxed))
+    }
+
+    pub fn set_time(&self, time: f32) {
+        self.time.store(time.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn get_speed(&self) -> f32 {
+        f32::from_bits(self.speed.load(Ordering::Relaxed))
+    }
+
+    pub fn set_speed(&self, speed: f32) {
+        self.speed.store(speed.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// A source that plays the mixer as the controller commands, without taking any lock.
+pub struct ControlledSource<S> {
+    mixer: TrackMixer<S>,
+    commands: Consumer<PlayerCommand<S>>,
+    reports: Producer<PlayerReport<S>>,
+    garbage: Producer<Garbage<S>>,
+    state: Arc<PlayerState>,
+    volume: f32,
+}
+
+impl<S> ControlledSource<S> {
+    pub fn new(
+        mixer: TrackMixer<S>,
+        commands: Consumer<PlayerCommand<S>>,
+        reports: Producer<PlayerReport<S>>,
+        garbage: Producer<Garbage<S>>,
+        state: Arc<PlayerState>,
+        volume: f32,
+    ) -> Self {
+        Self {
+            mixer,
+            commands,
+            reports,
+            garbage,
+            state,
+            volume,
+        }
+    }
+
+    fn apply(&mut self, command: PlayerCommand<S>) {
+        let mixer = &mut self.mixer;
+        match command {
+            PlayerCommand::SetBuffer(buffer) => mixer.set_buffer(buffer),
+            PlayerCommand::SetNextBuffer(buffer) => mixer.player_mut().set_next_buffer(buffer),
+            PlayerCommand::SetTime(time) => mixer
+                .player_mut()
+                .set_time(Duration::from_secs_f32(time.max(0.0))),
+            PlayerCommand::SetSpeed(speed) => mixer.set_speed(speed),
+            PlayerCommand::RampSpeed(speed, ramp_time, curve) => {
+                mixer.ramp_speed(speed, ramp_time, curve)
+            }
+            PlayerCommand::SetLoopMode(loop_mode) => mixer.player_mut().set_loop_mode(loop_mode),
+            PlayerCommand::SetLoopRegion(region) => mixer.player_mut().set_loop_region(region),
+            PlayerCommand::SetInterpolation(interpolation) => {
+                mixer.player_mut().set_interpolation(interpolation)
+            }
+            PlayerCommand::SetTimeStretch(time_stretch) => {
+                mixer.player_mut().set_time_stretch(time_stretch)
+            }
+            PlayerCommand::SetPitch(pitch) => mixer.player_mut().set_pitch(pitch),
+            PlayerCommand::SetCrossfadeTime(time) => mixer.player_mut().set_crossfade_time(time),
+            PlayerCommand::SetTrackFade(time, curve) => mixer.set_fade(time, curve),
+            PlayerCommand::SetVolume(volume) => self.volume = volume,
+        }
+    }
+
+    // a full queue means nobody is listening, the report is dropped then
+    fn report(&mut self) {
+        if let Some(buffer) = self.mixer.take_started_next() {
+            let _ = self.reports.push(PlayerReport::StartedNext(buffer));
+        }
+        for event in self.mixer.take_events() {
+            let _ = self.reports.push(PlayerReport::Event(event));
+        }
+        // dropped here only when the controller stopped taking it
+        for garbage in self.mixer.take_garbage() {
+            let _ = self.garbage.push(garbage);
+        }
+        let player = self.mixer.player_mut();
+        self.state.set_time(player.get_time().as_secs_f32());
+        self.state.set_speed(player.get_speed());
+    }
+}
+
+impl<S> Iterator for ControlledSource<S>
+where
+    S: Sample,
+{
+    type Item = S;
+
+    #[inline]
+    fn next(&mut self) -> Option<S> {
+        if self.mixer.is_frame_start() {
+            while let Ok(command) = self.commands.pop() {
+                self.apply(command);
+            }
+        }
+        let value = self.mixer.next();
+        // the mixer has moved on to the next frame
+        if self.mixer.is_frame_start() {
+            self.report();
+        }
+        value.map(|value| value.amplify(self.volume))
+    }
+}
+
+impl<S> Source for ControlledSource<S>
+where
+    S: Sample,
+{
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.mixer.channels()
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.mixer.sample_rate()
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer_player::SamplesBuffer;
+    use rtrb::{Consumer, RingBuffer};
+
+    // mono at 64 Hz, each sample tells the buffer and the frame
+    fn buffer(first: f32) -> SharedBuffer<f32> {
+        let samples: Vec<f32> = (0..10).map(|i| first + i as f32 * 0.01).collect();
+        Arc::new(SamplesBuffer::new(1, 64, samples))
+    }
+
+    type Queues = (
+        Producer<PlayerCommand<f32>>,
+        Consumer<PlayerReport<f32>>,
+        Consumer<Garbage<f32>>,
+    );
+
+    fn source(buffer: SharedBuffer<f32>) -> (ControlledSource<f32>, Queues) {
+        let (command_sender, commands) = RingBuffer::new(16);
+        let (report_sender, reports) = RingBuffer::new(16);
+        let (garbage_sender, garbage) = RingBuffer::new(16);
+        let mut player = BufferPlayer::new(buffer);
+        player.report_events();
+        player.reserve_garbage(4);
+        let mut mixer = TrackMixer::new(player);
+        mixer.reserve_garbage(4);
+        let state = Arc::new(PlayerState::default());
+        let source =
+            ControlledSource::new(mixer, commands, report_sender, garbage_sender, state, 1.0);
+        (source, (command_sender, reports, garbage))
+    }
+
+    #[test]
+    fn next_buffer_set_with_the_buffer_is_kept() {
+        let next = buffer(0.3);
+        let (mut source, (mut commands, mut reports, _)) = source(buffer(0.1));
+        for command in [
+            PlayerCommand::SetCrossfadeTime(0.0),
+            PlayerCommand::SetBuffer(buffer(0.2)),
+            PlayerCommand::SetNextBuffer(Some(Arc::clone(&next))),
+        ] {
+            let _ = commands.push(command);
+        }
+        let output: Vec<f32> = source.by_ref().take(15).collect();
+        assert_eq!(output[0], 0.2);
+        assert_eq!(output[9], 0.2 + 9.0 * 0.01);
+        // playing goes on into the queued buffer without a gap
+        assert_eq!(output[10], 0.3);
+        assert_eq!(output[14], 0.3 + 4.0 * 0.01);
+
+        let mut swapped = 0;
+        let mut started_next = false;
+        while let Ok(report) = reports.pop() {
+            match report {
+                PlayerReport::Event(PlayerEvent::BufferSwapped) => swapped += 1,
+                PlayerReport::Event(event) => panic!("unexpected {:?}", event),
+                PlayerReport::StartedNext(buffer) => {
+                    started_next = Arc::ptr_eq(&buffer, &next);
+                }
+            }
+        }
+        assert_eq!(swapped, 2);
+        assert!(started_next);
+    }
+
+    #[test]
+    fn commands_apply_in_order() {
+        let (mut source, (mut commands, _, _)) = source(buffer(0.1));
+        let _ = commands.push(PlayerCommand::SetCrossfadeTime(0.0));
+        // the time applies to the buffer set before it
+        let _ = commands.push(PlayerCommand::SetBuffer(buffer(0.2)));
+        let _ = commands.push(PlayerCommand::SetTime(0.0625));
+        assert_eq!(source.next(), Some(0.2 + 4.0 * 0.01));
+        // the later time wins
+        let _ = commands.push(PlayerCommand::SetTime(0.125));
+        let _ = commands.push(PlayerCommand::SetTime(0.03125));
+        assert_eq!(source.next(), Some(0.2 + 2.0 * 0.01));
+        assert_eq!(source.state.get_time(), 3.0 / 64.0);
+    }
+
+    #[test]
+    fn replaced_things_are_sent_away() {
+        let old = buffer(0.1);
+        let (mut source, (mut commands, _, mut garbage)) = source(Arc::clone(&old));
+        // a crossfade of one frame
+        let _ = commands.push(PlayerCommand::SetCrossfadeTime(1.0 / 64.0));
+        let _ = commands.push(PlayerCommand::SetBuffer(buffer(0.2)));
+        source.by_ref().take(3).for_each(drop);
+
+        let mut old_buffer = false;
+        let mut crossfade = false;
+        while let Ok(garbage) = garbage.pop() {
+            match garbage {
+                Garbage::Buffer(buffer) => old_buffer |= Arc::ptr_eq(&buffer, &old),
+                Garbage::Crossfade(_) => crossfade = true,
+                _ => {}
+            }
+        }
+        assert!(old_buffer && crossfade);
+        assert_eq!(Arc::strong_count(&old), 1);
+    }
+}
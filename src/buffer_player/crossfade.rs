@@ -0,0 +1,92 @@
+use super::SharedBuffer;
+use rodio::Sample;
+
+/// Fades from the audio that was playing before a jump into the audio after it.
+///
+/// The old audio keeps playing from where it was, at the speed it was played at,
+/// while its volume goes down and the new audio comes up.
+pub struct Crossfade<S> {
+    tail: Option<Tail<S>>,
+    position: usize,
+    len: usize,
+    tail_frame: Vec<f32>,
+}
+
+// the audio before the jump
+struct Tail<S> {
+    buffer: SharedBuffer<S>,
+    location: f64,
+    speed: f32,
+    block: Vec<S>,
+}
+
+impl<S> Crossfade<S> {
+    /// Fades over `len` frames, from the old audio in `buffer` at `location` played at `speed`.
+    /// Without the old audio it fades in from silence.
+    pub fn new(len: usize, tail: Option<(SharedBuffer<S>, f64, f32)>) -> Self {
+        Self {
+            tail: tail.map(|(buffer, location, speed)| Tail {
+                buffer,
+                location,
+                speed,
+                block: Vec::new(),
+            }),
+            position: 0,
+            len: len.max(1),
+            tail_frame: Vec::new(),
+        }
+    }
+}
+
+impl<S> Crossfade<S>
+where
+    S: Sample,
+{
+    /// Mixes the next frame of the old audio into `frame`, returns false once the fade is done.
+    pub fn apply(&mut self, frame: &mut [S]) -> bool {
+        // raised cosine, the gains of both sides always sum to 1
+        let t = self.position as f32 / self.len as f32;
+        let gain = 0.5 - 0.5 * (std::f32::consts::PI * t).cos();
+        self.position += 1;
+
+        self.tail_frame.clear();
+        if let Some(tail) = self.tail.as_mut() {
+            tail.next_frame(&mut self.tail_frame);
+        }
+        for (channel, sample) in frame.iter_mut().enumerate() {
+            // the old buffer may have a different number of channels
+            let old = match self.tail_frame.len() {
+                0 => 0.0,
+                channels => self.tail_frame[channel % channels],
+            };
+            let value = sample.to_f32() * gain + old * (1.0 - gain);
+            *sample = S::from(&value);
+        }
+        self.position < self.len
+    }
+}
+
+impl<S> Tail<S>
+where
+    S: Sample,
+{
+    // linear interpolation is enough for the few milliseconds it is heard
+    fn next_frame(&mut self, frame: &mut Vec<f32>) {
+        let channels = self.buffer.channels() as usize;
+        let index = self.location.floor();
+        let fract = (self.location - index) as f32;
+        self.block.clear();
+        self.block.resize(channels * 2, S::zero_value());
+        if index >= 0.0 {
+            self.buffer.read_frames(index as usize, &mut self.block);
+        } else if index == -1.0 {
+            self.buffer.read_frames(0, &mut self.block[channels..]);
+        }
+        frame.extend((0..channels).map(|channel| {
+            let current = self.block[channel].to_f32();
+            let next = self.block[channels + channel].to_f32();
+            current + (next - current) * fract
+        }));
+        self.location += self.speed as f64;
+    }
+}
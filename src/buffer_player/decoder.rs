@@ -0,0 +1,434 @@
+use super::LoadError;
+use rodio::Sample;
+use std::{
+    fs::File,
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+};
+use symphonia::core::{
+    audio::SampleBuffer,
+    codecs::{Decoder, DecoderOptions, CODEC_TYPE_NULL},
+    errors::Error,
+    formats::{FormatOptions, FormatReader, SeekMode, SeekTo},
+    io::MediaSourceStream,
+    meta::MetadataOptions,
+    probe::Hint,
+    units::TimeBase,
+};
+
+// frames skipped at a time when seeking by decoding, to check whether to stop
+const SKIP_FRAMES: usize = 16384;
+// frames decoded before the one sought to, as some codecs need the packets before a frame
+// to decode it
+const SEEK_PREROLL_FRAMES: usize = 4096;
+
+enum Reader {
+    Wav(hound::WavReader<BufReader<File>>),
+    Other(Box<PacketReader>),
+}
+
+/// Decodes an audio file from any frame, as precise as the file is.
+///
+/// WAV files are read with hound, other formats are decoded to f32 with symphonia.
+pub struct AudioDecoder {
+    path: PathBuf,
+    channels: usize,
+    sample_rate: u32,
+    total_frames: Option<usize>,
+    reader: Reader,
+    // frame index of the next sample coming out of the decoder
+    frame: usize,
+}
+
+impl AudioDecoder {
+    pub fn open(path: &Path) -> Result<Self, LoadError> {
+        let (reader, channels, sample_rate, total_frames) = match hound::WavReader::open(path) {
+            Ok(reader) => {
+                let spec = reader.spec();
+                let total_frames = reader.duration() as usize;
+                (
+                    Reader::Wav(reader),
+                    spec.channels,
+                    spec.sample_rate,
+                    Some(total_frames),
+                )
+            }
+            Err(_) => {
+                let reader = PacketReader::open(path)?;
+                let (channels, sample_rate, total_frames) =
+                    (reader.channels, reader.sample_rate, reader.total_frames);
+                (
+                    Reader::Other(Box::new(reader)),
+                    channels,
+                    sample_rate,
+                    total_frames,
+                )
+            }
+        };
+        if channels == 0 || sample_rate == 0 {
+            return Err(LoadError::UnsupportedFormat(path.to_path_buf()));
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            channels: channels as usize,
+            sample_rate,
+            total_frames,
+            reader,
+            frame: 0,
+        })
+    }
+
+    pub fn channels(&self) -> u16 {
+        self.channels as u16
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// The length the file tells, which may be wrong for a corrupt file.
+    pub fn total_frames(&self) -> Option<usize> {
+        self.total_frames
+    }
+
+    /// The frame the next `read` starts from.
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    /// Moves to `frame`, or to the end of the track if it is shorter.
+    pub fn seek(&mut self, frame: usize, stop: &AtomicBool) -> Result<(), LoadError> {
+        if frame == self.frame {
+            return Ok(());
+        }
+        match &mut self.reader {
+            Reader::Wav(reader) => {
+                let frame = frame.min(reader.duration() as usize);
+                reader
+                    .seek(frame as u32)
+                    .map_err(|e| LoadError::from_io(self.path.clone(), e))?;
+                self.frame = frame;
+            }
+            Reader::Other(reader) => {
+                match reader.seek(frame) {
+                    Ok(sought) => self.frame = sought,
+                    Err(e) if frame < self.frame => {
+                        log::warn!(
+                            "error seeking {:?}, decoding from the start: {}",
+                            self.path,
+                            e
+                        );
+                        **reader = PacketReader::open(&self.path)?;
+                        self.frame = 0;
+                    }
+                    Err(_) => {}
+                }
+                while self.frame < frame && !stop.load(Ordering::Relaxed) {
+                    let count = (frame - self.frame).min(SKIP_FRAMES) * self.channels;
+                    let skipped = match reader.skip(count) {
+                        Ok(skipped) => skipped / self.channels,
+                        Err(e) => return Err(self.error(e)),
+                    };
+                    self.frame += skipped;
+                    if skipped == 0 {
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Decodes up to `frames` frames, fewer at the end of the track.
+    pub fn read<S: Sample>(&mut self, frames: usize) -> Result<Vec<S>, LoadError> {
+        let count = frames * self.channels;
+        let mut samples: Vec<S> = match &mut self.reader {
+            Reader::Wav(reader) => {
+                let spec = reader.spec();
+                // a truncated file ends where the samples can not be read
+                match spec.sample_format {
+                    hound::SampleFormat::Float => reader
+                        .samples::<f32>()
+                        .take(count)
+                        .map_while(Result::ok)
+                        .map(|sample| S::from(&sample))
+                        .collect(),
+                    hound::SampleFormat::Int if spec.bits_per_sample == 16 => reader
+                        .samples::<i16>()
+                        .take(count)
+                        .map_while(Result::ok)
+                        .map(|sample| S::from(&sample))
+                        .collect(),
+                    hound::SampleFormat::Int => {
+                        let scale = 1.0 / (1u32 << (spec.bits_per_sample - 1)) as f32;
+                        reader
+                            .samples::<i32>()
+                            .take(count)
+                            .map_while(Result::ok)
+                            .map(|sample| S::from(&(sample as f32 * scale)))
+                            .collect()
+                    }
+                }
+            }
+            Reader::Other(reader) => {
+                let mut samples = Vec::with_capacity(count);
+                if let Err(e) = reader.read(count, &mut samples) {
+                    return Err(self.error(e));
+                }
+                samples
+            }
+        };
+        samples.truncate(samples.len() / self.channels * self.channels);
+        self.frame += samples.len() / self.channels;
+        Ok(samples)
+    }
+
+    fn error(&self, error: Error) -> LoadError {
+        match error {
+            Error::IoError(e) => LoadError::from_io(self.path.clone(), e),
+            _ => LoadError::CorruptStream {
+                path: self.path.clone(),
+                offset: super::frames_to_duration(self.frame, self.sample_rate),
+            },
+        }
+    }
+}
+
+// the formats hound does not read, decoded a packet at a time
+struct PacketReader {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    time_base: Option<TimeBase>,
+    channels: u16,
+    sample_rate: u32,
+    total_frames: Option<usize>,
+    // decoded samples from `pending_start` on are not read yet
+    pending: Vec<f32>,
+    pending_start: usize,
+    // the frame the pending samples start at
+    pending_frame: usize,
+}
+
+impl PacketReader {
+    fn open(path: &Path) -> Result<Self, LoadError> {
+        let file = File::open(path).map_err(|e| LoadError::from_io(path.to_path_buf(), e))?;
+        let stream = MediaSourceStream::new(Box::new(file), Default::default());
+        let mut hint = Hint::new();
+        if let Some(extension) = path.extension().and_then(|extension| extension.to_str()) {
+            hint.with_extension(extension);
+        }
+        let unsupported = |_| LoadError::UnsupportedFormat(path.to_path_buf());
+        let format = symphonia::default::get_probe()
+            .format(
+                &hint,
+                stream,
+                &FormatOptions::default(),
+                &MetadataOptions::default(),
+            )
+            .map_err(unsupported)?
+            .format;
+        let track = format
+            .tracks()
+            .iter()
+            .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+            .ok_or_else(|| LoadError::UnsupportedFormat(path.to_path_buf()))?;
+        let params = &track.codec_params;
+        let decoder = symphonia::default::get_codecs()
+            .make(params, &DecoderOptions::default())
+            .map_err(unsupported)?;
+        let mut reader = Self {
+            track_id: track.id,
+            time_base: params.time_base,
+            channels: params
+                .channels
+                .map_or(0, |channels| channels.count() as u16),
+            sample_rate: params.sample_rate.unwrap_or(0),
+            total_frames: params.n_frames.map(|frames| frames as usize),
+            format,
+            decoder,
+            pending: Vec::new(),
+            pending_start: 0,
+            pending_frame: 0,
+        };
+        // some formats only tell in the decoded audio
+        if reader.channels == 0 || reader.sample_rate == 0 {
+            reader
+                .decode_packet()
+                .map_err(|_| LoadError::UnsupportedFormat(path.to_path_buf()))?;
+        }
+        Ok(reader)
+    }
+
+    // decodes the next packet into `pending`, false at the end of the track
+    fn decode_packet(&mut self) -> Result<bool, Error> {
+        loop {
+            let packet = match self.format.next_packet() {
+                Ok(packet) => packet,
+                Err(Error::IoError(e)) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    return Ok(false)
+                }
+                // a new stream is chained after this one
+                Err(Error::ResetRequired) => return Ok(false),
+                Err(e) => return Err(e),
+            };
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+            let packet_frame = self.frame(packet.ts());
+            match self.decoder.decode(&packet) {
+                Ok(decoded) => {
+                    if self.pending.is_empty() {
+                        self.pending_frame = packet_frame;
+                    }
+                    let spec = *decoded.spec();
+                    self.channels = spec.channels.count() as u16;
+                    self.sample_rate = spec.rate;
+                    let mut samples = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+                    samples.copy_interleaved_ref(decoded);
+                    self.pending.extend_from_slice(samples.samples());
+                    return Ok(true);
+                }
+                // a damaged packet is left out like a decoder for playing does
+                Err(Error::DecodeError(e)) => log::warn!("skipped a damaged packet: {}", e),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    // moves to `frame` with the format, returns the frame it got to. that is earlier at the
+    // end of the track, and later when the decoder gives nothing for the packets before
+    fn seek(&mut self, frame: usize) -> Result<usize, Error> {
+        let sought = self.format.seek(
+            SeekMode::Accurate,
+            SeekTo::TimeStamp {
+                ts: self.timestamp(frame.saturating_sub(SEEK_PREROLL_FRAMES)),
+                track_id: self.track_id,
+            },
+        )?;
+        self.decoder.reset();
+        let channels = self.channels as usize;
+        let mut end = self.frame(sought.actual_ts);
+        loop {
+            self.pending.clear();
+            self.pending_start = 0;
+            if !self.decode_packet()? {
+                return Ok(end);
+            }
+            end = self.pending_frame + self.pending.len() / channels;
+            if end > frame {
+                self.pending_start = frame.saturating_sub(self.pending_frame) * channels;
+                return Ok(frame.max(self.pending_frame));
+            }
+        }
+    }
+
+    // the timestamps of most formats count frames
+    fn timestamp(&self, frame: usize) -> u64 {
+        match self.time_base {
+            Some(TimeBase { numer, denom }) => {
+                (frame as u128 * denom as u128 / (numer as u128 * self.sample_rate as u128)) as u64
+            }
+            None => frame as u64,
+        }
+    }
+
+    fn frame(&self, timestamp: u64) -> usize {
+        match self.time_base {
+            Some(TimeBase { numer, denom }) => {
+                (timestamp as u128 * numer as u128 * self.sample_rate as u128 / denom as u128)
+                    as usize
+            }
+            None => timestamp as usize,
+        }
+    }
+
+    // makes sure there is something pending, false at the end of the track
+    fn fill(&mut self) -> Result<bool, Error> {
+        while self.pending_start == self.pending.len() {
+            self.pending.clear();
+            self.pending_start = 0;
+            if !self.decode_packet()? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn read<S: Sample>(&mut self, count: usize, out: &mut Vec<S>) -> Result<(), Error> {
+        while out.len() < count && self.fill()? {
+            let end = (self.pending_start + count - out.len()).min(self.pending.len());
+            out.extend(
+                self.pending[self.pending_start..end]
+                    .iter()
+                    .map(|sample| S::from(sample)),
+            );
+            self.pending_start = end;
+        }
+        Ok(())
+    }
+
+    // returns the number of samples skipped
+    fn skip(&mut self, count: usize) -> Result<usize, Error> {
+        let mut skipped = 0;
+        while skipped < count && self.fill()? {
+            let end = (self.pending_start + count - skipped).min(self.pending.len());
+            skipped += end - self.pending_start;
+            self.pending_start = end;
+        }
+        Ok(skipped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example() -> AudioDecoder {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("asset/music/example.ogg");
+        AudioDecoder::open(&path).unwrap()
+    }
+
+    #[test]
+    fn seeking_gives_the_frames_decoding_through_gives() {
+        let stop = AtomicBool::new(false);
+        let mut decoder = example();
+        let channels = decoder.channels() as usize;
+        let total_frames = decoder.total_frames().unwrap();
+        let all = decoder.read::<f32>(total_frames).unwrap();
+        assert_eq!(all.len(), total_frames * channels);
+
+        // backward like reverse playback, then forward
+        let len = 2000;
+        for &frame in &[
+            total_frames - len,
+            total_frames / 2,
+            total_frames / 3,
+            10,
+            0,
+            total_frames / 4 * 3,
+        ] {
+            decoder.seek(frame, &stop).unwrap();
+            assert_eq!(decoder.frame(), frame);
+            let samples = decoder.read::<f32>(len).unwrap();
+            let expected = &all[frame * channels..(frame + len) * channels];
+            let error = samples
+                .iter()
+                .zip(expected)
+                .map(|(a, b)| (a - b).abs())
+                .fold(0.0, f32::max);
+            assert!(
+                error < 1e-4,
+                "{} off by {} at frame {}",
+                samples.len(),
+                error,
+                frame
+            );
+        }
+
+        // past the end it stops at the end
+        decoder.seek(total_frames + 1000, &stop).unwrap();
+        assert!(decoder.read::<f32>(len).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,127 @@
+use std::{fmt, io, path::PathBuf, time::Duration};
+
+/// Why a music or setting file could not be loaded.
+#[derive(Debug)]
+pub enum LoadError {
+    NotFound(PathBuf),
+    PermissionDenied(PathBuf),
+    /// Any other error reading the file.
+    Io(PathBuf, io::Error),
+    /// The file is not audio in a format that can be decoded.
+    UnsupportedFormat(PathBuf),
+    /// Decoding stopped this far into the audio, before its end.
+    CorruptStream {
+        path: PathBuf,
+        offset: Duration,
+    },
+    /// The file was read, but is not a valid setting.
+    InvalidSetting(PathBuf, String),
+    /// Loading was stopped by the user.
+    Cancelled,
+    OutOfMemory,
+}
+
+impl LoadError {
+    pub fn from_io(path: PathBuf, error: io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::NotFound => LoadError::NotFound(path),
+            io::ErrorKind::PermissionDenied => LoadError::PermissionDenied(path),
+            io::ErrorKind::OutOfMemory => LoadError::OutOfMemory,
+            _ => LoadError::Io(path, error),
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self, LoadError::Cancelled)
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::NotFound(path) => write!(f, "{:?} not found", path),
+            LoadError::PermissionDenied(path) => write!(f, "no permission to read {:?}", path),
+            LoadError::Io(path, e) => write!(f, "error reading {:?}: {}", path, e),
+            LoadError::UnsupportedFormat(path) => write!(f, "{:?} is not a supported format", path),
+            LoadError::CorruptStream { path, offset } => write!(
+                f,
+                "{:?} is corrupt after {:.3}s",
+                path,
+                offset.as_secs_f32()
+            ),
+            LoadError::InvalidSetting(path, e) => write!(f, "error parsing {:?}: {}", path, e),
+            LoadError::Cancelled => write!(f, "loading stopped"),
+            LoadError::OutOfMemory => write!(f, "out of memory"),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Io(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<LoadError> for String {
+    fn from(error: LoadError) -> Self {
+        error.to_string()
+    }
+}
+
+/// Why a track could not be exported.
+#[derive(Debug)]
+pub enum ExportError {
+    /// The track to export could not be loaded.
+    Load(LoadError),
+    /// Creating or writing the exported file failed.
+    Write(PathBuf, hound::Error),
+    /// Playing at speed 0 never ends.
+    ZeroSpeed,
+    /// Exporting was stopped by the user.
+    Cancelled,
+}
+
+impl ExportError {
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self, ExportError::Cancelled)
+    }
+}
+
+impl From<LoadError> for ExportError {
+    fn from(error: LoadError) -> Self {
+        match error {
+            LoadError::Cancelled => ExportError::Cancelled,
+            error => ExportError::Load(error),
+        }
+    }
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::Load(e) => e.fmt(f),
+            ExportError::Write(path, e) => write!(f, "error writing {:?}: {}", path, e),
+            ExportError::ZeroSpeed => write!(f, "can not export at speed 0"),
+            ExportError::Cancelled => write!(f, "exporting stopped"),
+        }
+    }
+}
+
+impl std::error::Error for ExportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ExportError::Load(e) => Some(e),
+            ExportError::Write(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<ExportError> for String {
+    fn from(error: ExportError) -> Self {
+        error.to_string()
+    }
+}
@@ -0,0 +1,13 @@
+/// Something that happened while playing, sent from the audio thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlayerEvent {
+    /// Playing ran into the end of the track, or its start when reversed,
+    /// with nothing queued to go on with.
+    TrackEnded,
+    /// Playing jumped from one end of the loop to the other.
+    LoopWrapped,
+    /// Playing went on in another buffer.
+    BufferSwapped,
+    /// The frames to play are not decoded yet.
+    Underrun,
+}
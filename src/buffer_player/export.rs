@@ -0,0 +1,309 @@
+use super::{BufferPlayer, ExportError, GrowingBuffer, Interpolation, SharedBuffer};
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+// frames written between checking whether to stop
+const WRITE_BLOCK_FRAMES: usize = 4096;
+
+/// How the exported audio is played, usually taken from the `AudioController`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportOptions {
+    pub speed: f32,
+    pub volume: f32,
+    /// Only this part in seconds is exported when it is set, the whole track otherwise.
+    pub loop_region: Option<Range<f32>>,
+    pub interpolation: Interpolation,
+    pub time_stretch: bool,
+    pub pitch: f32,
+    pub crossfade_time: f32,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            speed: 1.0,
+            volume: 1.0,
+            loop_region: None,
+            interpolation: Interpolation::default(),
+            time_stretch: false,
+            pitch: 0.0,
+            crossfade_time: 0.01,
+        }
+    }
+}
+
+/// Decodes `source` and writes it played with `options` into a 32 bit float WAV file.
+///
+/// `progress` goes from 0.0 to 1.0 while rendering. The audio is written next to `target`
+/// and only replaces it when it is complete, so stopping keeps an earlier export.
+pub fn export_wav<P: AsRef<Path>, Q: AsRef<Path>>(
+    source: P,
+    target: Q,
+    options: &ExportOptions,
+    stop: Arc<RwLock<bool>>,
+    decode_progress: Arc<RwLock<f32>>,
+    progress: Arc<RwLock<f32>>,
+) -> Result<(), ExportError> {
+    if options.speed == 0.0 {
+        return Err(ExportError::ZeroSpeed);
+    }
+    let buffer: SharedBuffer<f32> = GrowingBuffer::<f32>::load_from_file_async_stoppable(
+        source,
+        Arc::clone(&stop),
+        Arc::clone(&decode_progress),
+        Arc::new(RwLock::new(None::<SharedBuffer<f32>>)),
+    )?;
+    // not every format knows its length while decoding
+    *decode_progress.write().unwrap() = 1.0;
+    let channels = buffer.channels();
+    let sample_rate = buffer.sample_rate();
+    let frame_count = buffer.frame_count();
+
+    // the region may reach past the end
+    let to_frames =
+        |time: f32| ((time.max(0.0) as f64 * sample_rate as f64) as usize).min(frame_count);
+    let range = match &options.loop_region {
+        Some(region) => to_frames(region.start)..to_frames(region.end),
+        None => 0..frame_count,
+    };
+    let output_frames = (range.len() as f64 / options.speed.abs() as f64) as usize;
+
+    let mut player = BufferPlayer::new(Arc::clone(&buffer));
+    player.set_crossfade_time(0.0);
+    player.set_speed(options.speed);
+    player.set_interpolation(options.interpolation);
+    player.set_time_stretch(options.time_stretch);
+    player.set_pitch(options.pitch);
+    let start = if options.speed < 0.0 {
+        range.end.saturating_sub(1)
+    } else {
+        range.start
+    };
+    player.set_time(super::frames_to_duration(start, sample_rate));
+    player.set_crossfade_time(options.crossfade_time);
+
+    let target = target.as_ref();
+    let mut temp_name = target.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(".part");
+    let temp = target.with_file_name(temp_name);
+    let spec = hound::WavSpec {
+        channels,
+        sample_rate,
+        bits_per_sample: 32,
+        sample_format: hound::SampleFormat::Float,
+    };
+    let result = write_wav(
+        &mut player,
+        &temp,
+        spec,
+        output_frames,
+        options.volume,
+        &stop,
+        &progress,
+    )
+    .and_then(|_| {
+        std::fs::rename(&temp, target)
+            .map_err(|e| ExportError::Write(target.to_path_buf(), e.into()))
+    });
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    result
+}
+
+fn write_wav(
+    player: &mut BufferPlayer<f32>,
+    path: &Path,
+    spec: hound::WavSpec,
+    output_frames: usize,
+    volume: f32,
+    stop: &RwLock<bool>,
+    progress: &RwLock<f32>,
+) -> Result<(), ExportError> {
+    let write_error = |e| ExportError::Write(path.to_path_buf(), e);
+    let mut writer = hound::WavWriter::create(path, spec).map_err(write_error)?;
+
+    let mut written = 0;
+    while written < output_frames {
+        if *stop.read().unwrap() {
+            return Err(ExportError::Cancelled);
+        }
+        let block = WRITE_BLOCK_FRAMES.min(output_frames - written);
+        for sample in player.by_ref().take(block * spec.channels as usize) {
+            writer.write_sample(sample * volume).map_err(write_error)?;
+        }
+        written += block;
+        *progress.write().unwrap() = written as f32 / output_frames as f32;
+    }
+    writer.finalize().map_err(write_error)
+}
+
+type ExportResult = Arc<RwLock<Option<Result<PathBuf, ExportError>>>>;
+
+/// Exports on another thread, like `AudioBufferLoader` loads.
+pub struct WavExporter {
+    value: ExportResult,
+    stop: Arc<RwLock<bool>>,
+    decode_progress: Arc<RwLock<f32>>,
+    progress: Arc<RwLock<f32>>,
+}
+
+impl WavExporter {
+    pub fn export(source: PathBuf, target: PathBuf, options: ExportOptions) -> Self {
+        let exporter = Self {
+            value: Arc::new(RwLock::new(None)),
+            stop: Arc::new(RwLock::new(false)),
+            decode_progress: Arc::new(RwLock::new(0.0)),
+            progress: Arc::new(RwLock::new(0.0)),
+        };
+        let value2 = Arc::clone(&exporter.value);
+        let stop2 = Arc::clone(&exporter.stop);
+        let decode_progress2 = Arc::clone(&exporter.decode_progress);
+        let progress2 = Arc::clone(&exporter.progress);
+        std::thread::spawn(move || {
+            let result = export_wav(
+                source,
+                &target,
+                &options,
+                stop2,
+                decode_progress2,
+                progress2,
+            )
+            .map(|_| target);
+            *value2.write().unwrap() = Some(result);
+        });
+        exporter
+    }
+
+    /// Returns the path of the written file when exporting is done.
+    pub fn try_get_value(&mut self) -> Option<Result<PathBuf, ExportError>> {
+        self.value.write().unwrap().take()
+    }
+
+    pub fn stop(&self) {
+        *self.stop.write().unwrap() = true;
+    }
+
+    /// Decoding the source counts as the first half.
+    pub fn get_progress(&self) -> f32 {
+        (*self.decode_progress.read().unwrap() + *self.progress.read().unwrap()) * 0.5
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("yee_player_{}_{}.wav", name, std::process::id()))
+    }
+
+    fn export(
+        source: &Path,
+        target: &Path,
+        options: &ExportOptions,
+        stop: bool,
+    ) -> Result<(), ExportError> {
+        export_wav(
+            source,
+            target,
+            options,
+            Arc::new(RwLock::new(stop)),
+            Arc::new(RwLock::new(0.0)),
+            Arc::new(RwLock::new(0.0)),
+        )
+    }
+
+    #[test]
+    fn stopping_keeps_the_earlier_export() {
+        let source = temp_path("export_source");
+        let target = temp_path("export_target");
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 1000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&source, spec).unwrap();
+        for i in 0..1000 {
+            writer.write_sample(i as i16).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let options = ExportOptions::default();
+        export(&source, &target, &options, false).unwrap();
+        let written = std::fs::read(&target).unwrap();
+        assert_eq!(hound::WavReader::open(&target).unwrap().duration(), 1000);
+
+        let result = export(&source, &target, &options, true);
+        assert!(matches!(result, Err(ExportError::Cancelled)));
+        assert_eq!(std::fs::read(&target).unwrap(), written);
+        let mut temp = target.clone().into_os_string();
+        temp.push(".part");
+        assert!(!Path::new(&temp).exists());
+
+        std::fs::remove_file(source).unwrap();
+        std::fs::remove_file(target).unwrap();
+    }
+
+    #[test]
+    fn exports_follow_speed_reverse_and_region() {
+        let source = temp_path("export_ramp");
+        let target = temp_path("export_played");
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 1000,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let mut writer = hound::WavWriter::create(&source, spec).unwrap();
+        for i in 0..1000 {
+            writer.write_sample(i as f32 / 1000.0).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        // the frames of the source each exported frame is expected to play
+        let cases: [(f32, Option<Range<f32>>, Vec<f32>); 5] = [
+            (1.0, None, (0..1000).map(|i| i as f32).collect()),
+            (2.0, None, (0..500).map(|i| i as f32 * 2.0).collect()),
+            (-1.0, None, (0..1000).rev().map(|i| i as f32).collect()),
+            (1.0, Some(0.2..0.4), (200..400).map(|i| i as f32).collect()),
+            (
+                -0.5,
+                Some(0.2..0.4),
+                (0..400).map(|i| 399.0 - i as f32 * 0.5).collect(),
+            ),
+        ];
+        for (speed, loop_region, frames) in cases {
+            let options = ExportOptions {
+                speed,
+                loop_region: loop_region.clone(),
+                interpolation: Interpolation::Linear,
+                ..ExportOptions::default()
+            };
+            export(&source, &target, &options, false).unwrap();
+            let exported: Vec<f32> = hound::WavReader::open(&target)
+                .unwrap()
+                .samples::<f32>()
+                .map(Result::unwrap)
+                .collect();
+            assert_eq!(exported.len(), frames.len(), "{} {:?}", speed, loop_region);
+            for (i, (sample, frame)) in exported.iter().zip(&frames).enumerate() {
+                assert!(
+                    (sample - frame / 1000.0).abs() < 1e-6,
+                    "{} at {} with {} {:?}",
+                    sample,
+                    i,
+                    speed,
+                    loop_region
+                );
+            }
+        }
+
+        std::fs::remove_file(source).unwrap();
+        std::fs::remove_file(target).unwrap();
+    }
+}
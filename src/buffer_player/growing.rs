@@ -0,0 +1,465 @@
+use super::{AudioDecoder, FrameBuffer, LoadError, Peaks, SharedBuffer};
+use rodio::Sample;
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex, OnceLock, RwLock,
+    },
+    time::Duration,
+};
+
+// frames per chunk, the frames pushed are readable once their chunk is full
+const CHUNK_FRAMES: usize = 16384;
+// chunks in the first segment of the chunk table, each next segment is twice as long
+const FIRST_SEGMENT_CHUNKS: usize = 64;
+const SEGMENTS: usize = 32;
+// frames decoded between checking whether to stop
+const DECODE_FRAMES: usize = 16384;
+// how much audio has to be decoded before the buffer is handed out for playing
+const PLAYABLE_TIME: Duration = Duration::from_secs(3);
+// decoding that ends this much before the length the file tells is a corrupt stream
+const MISSING_TIME: Duration = Duration::from_millis(500);
+
+// chunks that are never changed once they are added, so they can be read while more
+// are added without waiting for a lock. the table grows by segments that never move.
+struct ChunkTable<S> {
+    segments: [OnceLock<Segment<S>>; SEGMENTS],
+}
+
+type Segment<S> = Box<[OnceLock<Box<[S]>>]>;
+
+impl<S> ChunkTable<S> {
+    fn new() -> Self {
+        Self {
+            segments: std::array::from_fn(|_| OnceLock::new()),
+        }
+    }
+
+    // the segment of the chunk and its index in the segment
+    fn locate(index: usize) -> (usize, usize) {
+        let position = index / FIRST_SEGMENT_CHUNKS + 1;
+        let segment = (usize::BITS - 1 - position.leading_zeros()) as usize;
+        (segment, index - FIRST_SEGMENT_CHUNKS * ((1 << segment) - 1))
+    }
+
+    fn get(&self, index: usize) -> Option<&[S]> {
+        let (segment, index) = Self::locate(index);
+        let chunk = self.segments.get(segment)?.get()?.get(index)?.get()?;
+        Some(chunk)
+    }
+
+    // only one thread adds chunks, one after another
+    fn set(&self, index: usize, chunk: Box<[S]>) {
+        let (segment, index) = Self::locate(index);
+        let segment = self.segments[segment].get_or_init(|| {
+            (0..FIRST_SEGMENT_CHUNKS << segment)
+                .map(|_| OnceLock::new())
+                .collect()
+        });
+        let _ = segment[index].set(chunk);
+    }
+}
+
+/// A buffer that can be played while it is still being filled by the loader.
+///
+/// Reading never waits for the loader, which adds the frames a chunk at a time.
+pub struct GrowingBuffer<S> {
+    channels: u16,
+    sample_rate: u32,
+    chunks: ChunkTable<S>,
+    // the samples of the chunk being filled, only used by the loader
+    pending: Mutex<Vec<S>>,
+    frame_count: AtomicUsize,
+    // no more frames are pushed
+    complete: AtomicBool,
+    peaks: Peaks,
+}
+
+impl<S> GrowingBuffer<S>
+where
+    S: Sample,
+{
+    pub fn new(channels: u16, sample_rate: u32) -> Self {
+        assert!(channels != 0);
+        assert!(sample_rate != 0);
+        Self {
+            channels,
+            sample_rate,
+            chunks: ChunkTable::new(),
+            pending: Mutex::new(Vec::new()),
+            frame_count: AtomicUsize::new(0),
+            complete: AtomicBool::new(false),
+            peaks: Peaks::new(channels),
+        }
+    }
+
+    /// Adds samples after the ones pushed before, they can be read once a chunk is full
+    /// or the buffer is finished. Fails when there is no memory left for a new chunk.
+    pub fn push(&self, samples: &[S]) -> Result<(), LoadError> {
+        self.push_samples(samples)?;
+        self.peaks.push(samples);
+        Ok(())
+    }
+
+    fn push_samples(&self, mut samples: &[S]) -> Result<(), LoadError> {
+        let chunk_len = CHUNK_FRAMES * self.channels as usize;
+        let mut pending = self.pending.lock().unwrap();
+        while !samples.is_empty() {
+            if pending.capacity() < chunk_len {
+                pending
+                    .try_reserve_exact(chunk_len)
+                    .map_err(|_| LoadError::OutOfMemory)?;
+            }
+            let count = (chunk_len - pending.len()).min(samples.len());
+            pending.extend_from_slice(&samples[..count]);
+            samples = &samples[count..];
+            if pending.len() == chunk_len {
+                let chunk = std::mem::take(&mut *pending);
+                self.add_chunk(chunk);
+            }
+        }
+        Ok(())
+    }
+
+    // makes the chunk readable after the frames before it
+    fn add_chunk(&self, chunk: Vec<S>) {
+        let frame_count = self.frame_count.load(Ordering::Relaxed);
+        let frames = chunk.len() / self.channels as usize;
+        self.chunks
+            .set(frame_count / CHUNK_FRAMES, chunk.into_boxed_slice());
+        self.frame_count
+            .store(frame_count + frames, Ordering::Release);
+    }
+
+    /// Marks the end of the audio, after the last `push`.
+    pub fn finish(&self) {
+        let chunk = std::mem::take(&mut *self.pending.lock().unwrap());
+        if !chunk.is_empty() {
+            self.add_chunk(chunk);
+        }
+        self.peaks.finish();
+        self.complete.store(true, Ordering::Release);
+    }
+}
+
+impl<S> GrowingBuffer<S>
+where
+    S: Sample + Send + Sync + 'static,
+{
+    /// Decodes the file into a new buffer, which is sent to `partial` as soon as
+    /// the beginning of the audio can be played.
+    ///
+    /// A corrupt stream fails with `LoadError::CorruptStream` after the buffer of
+    /// what was decoded before it is sent to `partial`.
+    pub fn load_from_file_async_stoppable<P: AsRef<Path>, T: Sample>(
+        path: P,
+        stop_loading: Arc<RwLock<bool>>,
+        progress: Arc<RwLock<f32>>,
+        partial: Arc<RwLock<Option<SharedBuffer<T>>>>,
+    ) -> Result<Arc<Self>, LoadError> {
+        let path = path.as_ref().to_path_buf();
+        let mut decoder = AudioDecoder::open(&path)?;
+        let channels = decoder.channels();
+        let sample_rate = decoder.sample_rate();
+        let total_frames = decoder.total_frames();
+        let playable_frames = super::duration_to_frames(PLAYABLE_TIME, sample_rate);
+        let missing_frames = super::duration_to_frames(MISSING_TIME, sample_rate);
+
+        let buffer = Arc::new(Self::new(channels, sample_rate));
+        let publish = || *partial.write().unwrap() = Some(Arc::clone(&buffer) as SharedBuffer<T>);
+        let mut published = false;
+        loop {
+            if *stop_loading.read().unwrap() {
+                // what is loaded may still be playing
+                buffer.finish();
+                return Err(LoadError::Cancelled);
+            }
+            let samples = match decoder.read::<S>(DECODE_FRAMES) {
+                Ok(samples) => samples,
+                Err(e) => {
+                    // a packet that can not be read is the end of what can be played
+                    buffer.finish();
+                    if !published && decoder.frame() > 0 {
+                        publish();
+                    }
+                    return Err(e);
+                }
+            };
+            if samples.is_empty() {
+                break;
+            }
+            if let Err(e) = buffer.push(&samples) {
+                buffer.finish();
+                return Err(e);
+            }
+            let decoded = decoder.frame();
+            if let Some(total_frames) = total_frames {
+                if let Ok(mut progress) = progress.try_write() {
+                    *progress = decoded as f32 / total_frames as f32;
+                };
+            }
+            if !published && decoded >= playable_frames {
+                publish();
+                published = true;
+            }
+        }
+        buffer.finish();
+        let decoded = decoder.frame();
+        if total_frames.is_some_and(|total_frames| decoded + missing_frames < total_frames) {
+            // what was decoded before the corrupt part can still be played,
+            // even when it is shorter than `PLAYABLE_TIME`
+            if !published && decoded > 0 {
+                publish();
+            }
+            return Err(LoadError::CorruptStream {
+                path,
+                offset: super::frames_to_duration(decoded, sample_rate),
+            });
+        }
+        Ok(buffer)
+    }
+}
+
+impl<S, T> FrameBuffer<T> for GrowingBuffer<S>
+where
+    S: Sample + Send + Sync,
+    T: Sample,
+{
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    #[inline]
+    fn frame_count(&self) -> usize {
+        self.frame_count.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    fn is_complete(&self) -> bool {
+        self.complete.load(Ordering::Acquire)
+    }
+
+    fn peaks(&self) -> Option<&Peaks> {
+        Some(&self.peaks)
+    }
+
+    #[inline]
+    fn sample(&self, frame: usize, channel: u16) -> Option<T> {
+        self.chunks
+            .get(frame / CHUNK_FRAMES)?
+            .get((frame % CHUNK_FRAMES) * self.channels as usize + channel as usize)
+            .map(T::from)
+    }
+
+    fn read_frames(&self, frame: usize, out: &mut [T]) {
+        let channels = self.channels as usize;
+        let mut position = frame * channels;
+        let mut out = out;
+        while !out.is_empty() {
+            let chunk_len = CHUNK_FRAMES * channels;
+            let chunk = match self.chunks.get(position / chunk_len) {
+                Some(chunk) => chunk,
+                None => return,
+            };
+            let start = (position % chunk_len).min(chunk.len());
+            let count = (chunk.len() - start).min(out.len());
+            if count == 0 {
+                return;
+            }
+            super::convert_samples(&chunk[start..start + count], &mut out[..count]);
+            out = &mut out[count..];
+            position += count;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn temp_wav(name: &str, spec: hound::WavSpec, samples: &[f32]) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("yee_player_{}_{}.wav", name, std::process::id()));
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for &sample in samples {
+            match spec.sample_format {
+                hound::SampleFormat::Float => writer.write_sample(sample).unwrap(),
+                hound::SampleFormat::Int => writer
+                    .write_sample((sample * (1 << (spec.bits_per_sample - 1)) as f32) as i32)
+                    .unwrap(),
+            }
+        }
+        writer.finalize().unwrap();
+        path
+    }
+
+    fn load<S: Sample + Send + Sync + 'static>(
+        path: &Path,
+    ) -> Result<Arc<GrowingBuffer<S>>, LoadError> {
+        GrowingBuffer::<S>::load_from_file_async_stoppable(
+            path,
+            Arc::new(RwLock::new(false)),
+            Arc::new(RwLock::new(0.0)),
+            Arc::new(RwLock::new(None::<SharedBuffer<f32>>)),
+        )
+    }
+
+    fn samples(buffer: &GrowingBuffer<f32>) -> Vec<f32> {
+        let len = FrameBuffer::<f32>::frame_count(buffer) * buffer.channels as usize;
+        let mut samples = vec![0.0; len];
+        FrameBuffer::<f32>::read_frames(buffer, 0, &mut samples);
+        samples
+    }
+
+    #[test]
+    fn float_and_24_bit_files_keep_their_precision() {
+        // steps far below what 16 bits can tell apart
+        let quiet: Vec<f32> = (0..1000).map(|i| i as f32 / (1 << 23) as f32).collect();
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 1000,
+            bits_per_sample: 24,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let path = temp_wav("growing_24_bit", spec, &quiet);
+        let buffer = load::<f32>(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(samples(&buffer), quiet);
+
+        // float samples keep their headroom above full scale
+        let mut loud = quiet.clone();
+        loud.push(1.5);
+        let spec = hound::WavSpec {
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+            ..spec
+        };
+        let path = temp_wav("growing_float", spec, &loud);
+        let buffer = load::<f32>(&path).unwrap();
+        assert_eq!(samples(&buffer), loud);
+
+        // i16 storage rounds them to 16 bits
+        let buffer = load::<i16>(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(FrameBuffer::<f32>::sample(buffer.as_ref(), 1, 0), Some(0.0));
+    }
+
+    #[test]
+    fn frames_can_be_played_while_pushing() {
+        use crate::buffer_player::BufferPlayer;
+
+        let buffer = Arc::new(GrowingBuffer::<f32>::new(2, 1000));
+        let frames = CHUNK_FRAMES + 100;
+        let samples: Vec<f32> = (0..frames * 2).map(|i| i as f32 / 1e6).collect();
+        let frame_count = |buffer: &GrowingBuffer<f32>| FrameBuffer::<f32>::frame_count(buffer);
+        let sample =
+            |buffer: &GrowingBuffer<f32>, frame| FrameBuffer::<f32>::sample(buffer, frame, 1);
+
+        // a chunk is readable once it is full
+        buffer.push(&samples[..CHUNK_FRAMES]).unwrap();
+        assert_eq!(frame_count(&buffer), 0);
+        buffer.push(&samples[CHUNK_FRAMES..]).unwrap();
+        assert_eq!(frame_count(&buffer), CHUNK_FRAMES);
+        assert!(!FrameBuffer::<f32>::is_complete(buffer.as_ref()));
+        assert_eq!(
+            sample(&buffer, CHUNK_FRAMES - 1),
+            Some(samples[CHUNK_FRAMES * 2 - 1])
+        );
+        assert_eq!(sample(&buffer, CHUNK_FRAMES), None);
+
+        let mut player = BufferPlayer::<f32>::new(Arc::clone(&buffer) as SharedBuffer<f32>);
+        player.set_crossfade_time(0.0);
+        let played: Vec<f32> = player.by_ref().take(2000).collect();
+        assert_eq!(played, samples[..2000]);
+
+        // the rest is readable when the buffer is finished
+        buffer.finish();
+        assert_eq!(frame_count(&buffer), frames);
+        assert!(FrameBuffer::<f32>::is_complete(buffer.as_ref()));
+        assert_eq!(sample(&buffer, frames - 1), samples.last().copied());
+        let played: Vec<f32> = player.take(samples.len() - 2000).collect();
+        assert_eq!(played, samples[2000..]);
+    }
+
+    #[test]
+    fn chunk_table_segments() {
+        assert_eq!(ChunkTable::<f32>::locate(0), (0, 0));
+        assert_eq!(ChunkTable::<f32>::locate(63), (0, 63));
+        assert_eq!(ChunkTable::<f32>::locate(64), (1, 0));
+        assert_eq!(ChunkTable::<f32>::locate(191), (1, 127));
+        assert_eq!(ChunkTable::<f32>::locate(192), (2, 0));
+
+        let table = ChunkTable::new();
+        for index in 0..200 {
+            table.set(index, vec![index as f32].into_boxed_slice());
+        }
+        assert!((0..200).all(|index| table.get(index) == Some(&[index as f32][..])));
+        assert_eq!(table.get(200), None);
+    }
+
+    #[test]
+    fn truncated_files_keep_what_was_decoded() {
+        let audio: Vec<f32> = (0..1000).map(|i| i as f32 / 1000.0).collect();
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 1000,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let path = temp_wav("growing_truncated", spec, &audio);
+        // cut the file after 400 frames, the header still tells 1000
+        let len = std::fs::metadata(&path).unwrap().len();
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(len - 600 * 4)
+            .unwrap();
+
+        let partial = Arc::new(RwLock::new(None::<SharedBuffer<f32>>));
+        let result = GrowingBuffer::<f32>::load_from_file_async_stoppable(
+            &path,
+            Arc::new(RwLock::new(false)),
+            Arc::new(RwLock::new(0.0)),
+            Arc::clone(&partial),
+        );
+        std::fs::remove_file(&path).unwrap();
+        match result {
+            Err(LoadError::CorruptStream { offset, .. }) => {
+                assert_eq!(offset, Duration::from_millis(400))
+            }
+            _ => panic!("a truncated file loads as corrupt"),
+        }
+
+        // the decoded part was given to play even though it is shorter than `PLAYABLE_TIME`
+        let buffer = partial.write().unwrap().take().unwrap();
+        assert!(buffer.is_complete());
+        assert_eq!(buffer.frame_count(), 400);
+        let mut player = crate::buffer_player::BufferPlayer::new(buffer);
+        player.set_crossfade_time(0.0);
+        let played: Vec<f32> = player.by_ref().take(400).collect();
+        assert_eq!(played, audio[..400]);
+    }
+
+    #[test]
+    fn other_formats_decode_to_the_end() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("asset/music/example.ogg");
+        let decoder = AudioDecoder::open(&path).unwrap();
+        let total_frames = decoder.total_frames().unwrap();
+        let buffer = load::<f32>(&path).unwrap();
+        assert!(FrameBuffer::<f32>::is_complete(buffer.as_ref()));
+        assert_eq!(
+            FrameBuffer::<f32>::frame_count(buffer.as_ref()),
+            total_frames
+        );
+        assert!(samples(&buffer).iter().any(|&sample| sample != 0.0));
+    }
+}
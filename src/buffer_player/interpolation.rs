@@ -0,0 +1,248 @@
+use serde::Deserialize;
+use std::sync::OnceLock;
+
+/// How the samples between two frames are calculated when playing at non-integer speed.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+pub enum Interpolation {
+    Nearest,
+    Linear,
+    #[default]
+    Cubic,
+    /// Windowed sinc, also low-pass filters the audio when playing faster than 1x.
+    Sinc,
+}
+
+// zero crossings on each side of the sinc kernel
+const SINC_ZERO_CROSSINGS: usize = 16;
+// the lowest cutoff of the anti-aliasing filter, limits the kernel length at high speed
+const MIN_SINC_CUTOFF: f32 = 0.25;
+const SINC_TABLE_SIZE: usize = 4096;
+
+// one side of the windowed sinc kernel, indexed by distance / kernel half width
+fn sinc_table() -> &'static [f32] {
+    static TABLE: OnceLock<Vec<f32>> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        (0..=SINC_TABLE_SIZE)
+            .map(|i| {
+                let u = i as f64 / SINC_TABLE_SIZE as f64;
+                let x = std::f64::consts::PI * SINC_ZERO_CROSSINGS as f64 * u;
+                let sinc = if x == 0.0 { 1.0 } else { x.sin() / x };
+                let blackman = 0.42
+                    + 0.5 * (std::f64::consts::PI * u).cos()
+                    + 0.08 * (2.0 * std::f64::consts::PI * u).cos();
+                (sinc * blackman) as f32
+            })
+            .collect()
+    })
+}
+
+#[inline]
+fn sinc_kernel(u: f32) -> f32 {
+    let table = sinc_table();
+    let position = u.abs() * SINC_TABLE_SIZE as f32;
+    let index = position as usize;
+    if index >= SINC_TABLE_SIZE {
+        return 0.0;
+    }
+    let fract = position - index as f32;
+    table[index] + (table[index + 1] - table[index]) * fract
+}
+
+impl Interpolation {
+    /// Calculates the weights of the frames around the read position.
+    ///
+    /// `fract` is the position between the current frame and the next one.
+    /// Returns the offset of the first weighted frame from the current frame.
+    pub fn weights(&self, fract: f32, speed: f32, weights: &mut Vec<f32>) -> isize {
+        weights.clear();
+        match self {
+            Interpolation::Nearest => {
+                weights.push(1.0);
+                if fract < 0.5 {
+                    0
+                } else {
+                    1
+                }
+            }
+            Interpolation::Linear => {
+                weights.extend_from_slice(&[1.0 - fract, fract]);
+                0
+            }
+            Interpolation::Cubic => {
+                // Catmull-Rom spline
+                let t = fract;
+                let t2 = t * t;
+                let t3 = t2 * t;
+                weights.extend_from_slice(&[
+                    (-t3 + 2.0 * t2 - t) * 0.5,
+                    (3.0 * t3 - 5.0 * t2 + 2.0) * 0.5,
+                    (-3.0 * t3 + 4.0 * t2 + t) * 0.5,
+                    (t3 - t2) * 0.5,
+                ]);
+                -1
+            }
+            Interpolation::Sinc => {
+                // lower the cutoff when skipping frames, so the result does not alias
+                let cutoff = if speed.abs() > 1.0 {
+                    (1.0 / speed.abs()).max(MIN_SINC_CUTOFF)
+                } else {
+                    1.0
+                };
+                let half_width = SINC_ZERO_CROSSINGS as f32 / cutoff;
+                let first = (fract - half_width).ceil() as isize;
+                let last = (fract + half_width).floor() as isize;
+                let mut sum = 0.0;
+                for offset in first..=last {
+                    let weight = sinc_kernel((offset as f32 - fract) / half_width);
+                    sum += weight;
+                    weights.push(weight);
+                }
+                if sum != 0.0 {
+                    weights.iter_mut().for_each(|weight| *weight /= sum);
+                }
+                first
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Interpolation;
+    use crate::buffer_player::{BufferPlayer, SamplesBuffer};
+    use rodio::cpal::Sample;
+    use std::sync::Arc;
+
+    const SAMPLE_RATE: u32 = 48000;
+
+    fn sine(frequency: f32, amplitude: f32) -> Vec<i16> {
+        (0..SAMPLE_RATE as usize * 2)
+            .map(|i| {
+                let phase = 2.0 * std::f32::consts::PI * frequency * i as f32 / SAMPLE_RATE as f32;
+                (phase.sin() * amplitude * i16::MAX as f32) as i16
+            })
+            .collect()
+    }
+
+    fn play(data: Vec<i16>, interpolation: Interpolation, speed: f32) -> Vec<f32> {
+        // stored as i16 and played as f32, like the player does with the i16 storage
+        let mut player =
+            BufferPlayer::<f32>::new(Arc::new(SamplesBuffer::new(1, SAMPLE_RATE, data)));
+        player.set_interpolation(interpolation);
+        player.set_speed(speed);
+        // skip the start so the kernels are filled with real frames
+        player.skip(1024).take(SAMPLE_RATE as usize / 4).collect()
+    }
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    // total harmonic distortion plus noise in dB, measured by removing the
+    // best fitting sine of the expected frequency
+    fn thd_n(samples: &[f32], frequency: f32) -> f32 {
+        let omega = 2.0 * std::f64::consts::PI * frequency as f64 / SAMPLE_RATE as f64;
+        let (mut ss, mut sc, mut cc, mut ys, mut yc) = (0.0, 0.0, 0.0, 0.0, 0.0);
+        for (i, &y) in samples.iter().enumerate() {
+            let (s, c) = (omega * i as f64).sin_cos();
+            ss += s * s;
+            sc += s * c;
+            cc += c * c;
+            ys += y as f64 * s;
+            yc += y as f64 * c;
+        }
+        let det = ss * cc - sc * sc;
+        let a = (ys * cc - yc * sc) / det;
+        let b = (yc * ss - ys * sc) / det;
+        let residual = samples
+            .iter()
+            .enumerate()
+            .map(|(i, &y)| {
+                let (s, c) = (omega * i as f64).sin_cos();
+                (y as f64 - a * s - b * c) as f32
+            })
+            .collect::<Vec<_>>();
+        20.0 * (rms(&residual) / rms(samples)).log10()
+    }
+
+    #[test]
+    fn weights_sum_to_one() {
+        let modes = [
+            Interpolation::Nearest,
+            Interpolation::Linear,
+            Interpolation::Cubic,
+            Interpolation::Sinc,
+        ];
+        let mut weights = Vec::new();
+        for mode in modes {
+            for &speed in &[0.5, 1.0, 3.0] {
+                for i in 0..10 {
+                    mode.weights(i as f32 / 10.0, speed, &mut weights);
+                    let sum = weights.iter().sum::<f32>();
+                    assert!((sum - 1.0).abs() < 1e-4, "{:?} sums to {}", mode, sum);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn integer_speed_is_lossless() {
+        let data = sine(440.0, 0.5);
+        let expected = data.iter().skip(1024).map(|s| s.to_f32());
+        for (output, expected) in play(data.clone(), Interpolation::Linear, 1.0)
+            .into_iter()
+            .zip(expected)
+        {
+            assert!((output - expected).abs() <= 1.0 / i16::MAX as f32);
+        }
+    }
+
+    #[test]
+    fn distortion_at_fractional_speed() {
+        let speed = 1.37;
+        let data = sine(1000.0, 0.5);
+        let thd = |mode| thd_n(&play(data.clone(), mode, speed), 1000.0 * speed);
+        let nearest = thd(Interpolation::Nearest);
+        let linear = thd(Interpolation::Linear);
+        let cubic = thd(Interpolation::Cubic);
+        let sinc = thd(Interpolation::Sinc);
+        assert!(
+            linear < nearest - 10.0,
+            "nearest {} linear {}",
+            nearest,
+            linear
+        );
+        assert!(cubic < linear - 10.0, "linear {} cubic {}", linear, cubic);
+        assert!(sinc < cubic, "cubic {} sinc {}", cubic, sinc);
+        assert!(sinc < -70.0, "sinc {}", sinc);
+    }
+
+    #[test]
+    fn distortion_at_slow_speed() {
+        let speed = 0.3;
+        let data = sine(3000.0, 0.5);
+        let linear = thd_n(
+            &play(data.clone(), Interpolation::Linear, speed),
+            3000.0 * speed,
+        );
+        let sinc = thd_n(&play(data, Interpolation::Sinc, speed), 3000.0 * speed);
+        assert!(sinc < linear - 20.0, "linear {} sinc {}", linear, sinc);
+        assert!(sinc < -70.0, "sinc {}", sinc);
+    }
+
+    #[test]
+    fn sinc_filters_aliasing() {
+        // 15kHz played at 2.5x is above nyquist and would alias down to 10.5kHz
+        let speed = 2.5;
+        let data = sine(15000.0, 0.5);
+        let input = rms(&play(data.clone(), Interpolation::Nearest, 1.0));
+        let linear = rms(&play(data.clone(), Interpolation::Linear, speed));
+        let sinc = rms(&play(data, Interpolation::Sinc, speed));
+        assert!(20.0 * (linear / input).log10() > -20.0);
+        assert!(
+            20.0 * (sinc / input).log10() < -60.0,
+            "aliasing {}dB",
+            20.0 * (sinc / input).log10()
+        );
+    }
+}
@@ -0,0 +1,315 @@
+use super::{control::Garbage, BufferPlayer, PlayerEvent, RampCurve, SharedBuffer};
+use rodio::{source::Source, Sample};
+use serde::Deserialize;
+use std::{sync::Arc, time::Duration};
+
+/// How the volumes of two tracks change while crossfading from one into the other.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+pub enum FadeCurve {
+    /// Keeps the loudness even in the middle of the fade.
+    #[default]
+    EqualPower,
+    Linear,
+}
+
+impl FadeCurve {
+    // the gains of the outgoing and the incoming track at the progress 0.0 .. 1.0 of the fade
+    fn gains(&self, t: f32) -> (f32, f32) {
+        match self {
+            FadeCurve::EqualPower => {
+                let angle = t * std::f32::consts::FRAC_PI_2;
+                (angle.cos(), angle.sin())
+            }
+            FadeCurve::Linear => (1.0 - t, t),
+        }
+    }
+}
+
+// the player of the previous track while it fades out
+struct Outgoing<S> {
+    player: BufferPlayer<S>,
+    position: usize,
+    len: usize,
+}
+
+/// A source that plays the current track, and mixes the previous track into it
+/// while crossfading from one track into the other.
+pub struct TrackMixer<S> {
+    player: BufferPlayer<S>,
+    outgoing: Option<Outgoing<S>>,
+    // seconds to crossfade between tracks, 0 switches without fading
+    fade_time: f32,
+    curve: FadeCurve,
+    gains: (f32, f32),
+    started_next: bool,
+    // the room for garbage of the outgoing player, taken back when it is done
+    spare_garbage: Vec<Garbage<S>>,
+}
+
+impl<S> TrackMixer<S> {
+    pub fn new(player: BufferPlayer<S>) -> Self {
+        Self {
+            player,
+            outgoing: None,
+            fade_time: 0.0,
+            curve: FadeCurve::default(),
+            gains: (0.0, 1.0),
+            started_next: false,
+            spare_garbage: Vec::new(),
+        }
+    }
+
+    /// Keeps room for what both tracks stop using, see `BufferPlayer::reserve_garbage`.
+    pub fn reserve_garbage(&mut self, capacity: usize) {
+        self.player.reserve_garbage(capacity);
+        self.spare_garbage.reserve_exact(capacity);
+    }
+
+    /// What both tracks stopped using since the last call.
+    pub fn take_garbage(&mut self) -> impl Iterator<Item = Garbage<S>> + '_ {
+        let outgoing = self
+            .outgoing
+            .iter_mut()
+            .flat_map(|outgoing| outgoing.player.take_garbage());
+        self.player.take_garbage().chain(outgoing)
+    }
+
+    /// The player of the current track.
+    pub fn player_mut(&mut self) -> &mut BufferPlayer<S> {
+        &mut self.player
+    }
+
+    pub fn set_fade(&mut self, fade_time: f32, curve: FadeCurve) {
+        self.fade_time = fade_time;
+        self.curve = curve;
+    }
+
+    /// Crossfades into `buffer` when it plays like the current track, switches to it otherwise.
+    pub fn set_buffer(&mut self, buffer: SharedBuffer<S>) {
+        if self.can_fade_into(&buffer) {
+            self.fade_into(buffer);
+        } else {
+            self.player.set_buffer(buffer);
+        }
+    }
+
+    /// Whether the next sample is the first one of a frame.
+    pub fn is_frame_start(&self) -> bool {
+        self.player.channel == 0
+    }
+
+    /// The events of the current track since the last call, oldest first.
+    pub fn take_events(&mut self) -> impl Iterator<Item = PlayerEvent> + '_ {
+        self.player.take_events()
+    }
+
+    /// Returns the queued buffer once after playing went on into it.
+    pub fn take_started_next(&mut self) -> Option<SharedBuffer<S>> {
+        if std::mem::take(&mut self.started_next) {
+            return Some(Arc::clone(&self.player.buffer));
+        }
+        self.player.take_started_next()
+    }
+
+    /// Changes the speed of both tracks, so pausing and reversing work during a crossfade.
+    pub fn set_speed(&mut self, speed: f32) {
+        self.player.set_speed(speed);
+        if let Some(outgoing) = self.outgoing.as_mut() {
+            outgoing.player.set_speed(speed);
+        }
+    }
+
+    pub fn ramp_speed(&mut self, speed: f32, ramp_time: f32, curve: RampCurve) {
+        self.player.ramp_speed(speed, ramp_time, curve);
+        if let Some(outgoing) = self.outgoing.as_mut() {
+            outgoing.player.ramp_speed(speed, ramp_time, curve);
+        }
+    }
+
+    // only one crossfade at a time, and only between buffers of the same format
+    fn can_fade_into(&self, buffer: &SharedBuffer<S>) -> bool {
+        let current = &self.player.buffer;
+        self.fade_time > 0.0
+            && self.outgoing.is_none()
+            && current.frame_count() > 0
+            && !self.player.paused(self.player.speed)
+            && current.channels() == buffer.channels()
+            && current.sample_rate() == buffer.sample_rate()
+    }
+
+    fn fade_into(&mut self, buffer: SharedBuffer<S>) {
+        let len = (self.fade_time * buffer.sample_rate() as f32) as usize;
+        let mut incoming = self.player.successor(buffer);
+        // the room for garbage is made before playing, the outgoing player gets the spare
+        incoming.garbage = std::mem::replace(
+            &mut self.player.garbage,
+            std::mem::take(&mut self.spare_garbage),
+        );
+        let mut player = std::mem::replace(&mut self.player, incoming);
+        // the end of the previous track is not the end of what is playing
+        player.reporting = false;
+        self.player.emit(PlayerEvent::BufferSwapped);
+        self.outgoing = Some(Outgoing {
+            player,
+            position: 0,
+            len: len.max(1),
+        });
+    }
+
+    // starts crossfading into the queued buffer when the current one is about to run out
+    fn check_next(&mut self) {
+        let next = match &self.player.next_buffer {
+            Some(next) if self.can_fade_into(next) => Arc::clone(next),
+            _ => return,
+        };
+        let fading_time = self
+            .player
+            .time_left()
+            .is_some_and(|time_left| time_left <= self.fade_time);
+        if fading_time {
+            self.player.next_buffer = None;
+            self.fade_into(next);
+            self.started_next = true;
+        }
+    }
+
+    // moves the fade one frame forward
+    fn next_gains(&mut self) {
+        self.gains = match self.outgoing.as_mut() {
+            Some(outgoing) if outgoing.position < outgoing.len => {
+                let t = outgoing.position as f32 / outgoing.len as f32;
+                outgoing.position += 1;
+                self.curve.gains(t)
+            }
+            _ => {
+                if let Some(outgoing) = self.outgoing.take() {
+                    let mut player = outgoing.player;
+                    // the garbage is taken after every frame, so its room is empty again
+                    self.spare_garbage = std::mem::take(&mut player.garbage);
+                    self.player.throw_away(Garbage::Player(player));
+                }
+                (0.0, 1.0)
+            }
+        };
+    }
+}
+
+impl<S> Iterator for TrackMixer<S>
+where
+    S: Sample,
+{
+    type Item = S;
+
+    #[inline]
+    fn next(&mut self) -> Option<S> {
+        // both players are at the start of a frame together
+        if self.player.channel == 0 {
+            self.check_next();
+            self.next_gains();
+        }
+        let value = self.player.next()?;
+        let value = match self.outgoing.as_mut() {
+            Some(outgoing) => {
+                let old = outgoing.player.next().map_or(0.0, |old| old.to_f32());
+                let (old_gain, gain) = self.gains;
+                S::from(&(value.to_f32() * gain + old * old_gain))
+            }
+            None => value,
+        };
+        Some(value)
+    }
+}
+
+impl<S> Source for TrackMixer<S>
+where
+    S: Sample,
+{
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.player.channels()
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.player.sample_rate()
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer_player::SamplesBuffer;
+
+    fn dc_buffer(level: f32) -> SharedBuffer<f32> {
+        Arc::new(SamplesBuffer::new(1, 1000, vec![level; 1000]))
+    }
+
+    fn mixer(fade_time: f32, curve: FadeCurve) -> TrackMixer<f32> {
+        let mut player = BufferPlayer::new(dc_buffer(0.25));
+        player.set_crossfade_time(0.0);
+        let mut mixer = TrackMixer::new(player);
+        mixer.reserve_garbage(8);
+        mixer.set_fade(fade_time, curve);
+        mixer
+    }
+
+    fn players_thrown_away(mixer: &mut TrackMixer<f32>) -> usize {
+        mixer
+            .take_garbage()
+            .filter(|garbage| matches!(garbage, Garbage::Player(_)))
+            .count()
+    }
+
+    #[test]
+    fn fade_curve_gains() {
+        for i in 0..=100 {
+            let t = i as f32 / 100.0;
+            let (old, new) = FadeCurve::Linear.gains(t);
+            assert!((old + new - 1.0).abs() < 1e-6, "{}", t);
+            let (old, new) = FadeCurve::EqualPower.gains(t);
+            assert!((old * old + new * new - 1.0).abs() < 1e-6, "{}", t);
+        }
+        assert_eq!(FadeCurve::Linear.gains(0.0), (1.0, 0.0));
+        assert_eq!(FadeCurve::EqualPower.gains(0.0), (1.0, 0.0));
+    }
+
+    #[test]
+    fn crossfades_into_the_next_track() {
+        for curve in [FadeCurve::Linear, FadeCurve::EqualPower] {
+            let mut mixer = mixer(0.1, curve);
+            mixer.next();
+            mixer.set_buffer(dc_buffer(0.5));
+            let output: Vec<f32> = mixer.by_ref().take(100).collect();
+            for (i, sample) in output.iter().enumerate() {
+                let (old, new) = curve.gains(i as f32 / 100.0);
+                let expected = 0.25 * old + 0.5 * new;
+                assert!((sample - expected).abs() < 1e-6, "{} at {}", sample, i);
+            }
+            assert_eq!(players_thrown_away(&mut mixer), 0);
+            // the outgoing player is thrown away once the fade is done
+            assert_eq!(mixer.next(), Some(0.5));
+            assert_eq!(players_thrown_away(&mut mixer), 1);
+            assert!(mixer.outgoing.is_none());
+        }
+    }
+
+    #[test]
+    fn no_fade_time_switches_at_once() {
+        let mut mixer = mixer(0.0, FadeCurve::Linear);
+        mixer.next();
+        mixer.set_buffer(dc_buffer(0.5));
+        assert!(mixer.outgoing.is_none());
+        assert_eq!(mixer.next(), Some(0.5));
+        assert_eq!(players_thrown_away(&mut mixer), 0);
+    }
+}
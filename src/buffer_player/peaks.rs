@@ -0,0 +1,214 @@
+use rodio::Sample;
+use std::{
+    ops::Range,
+    sync::{Mutex, RwLock},
+};
+
+/// Frames summed up by a peak of the finest level.
+pub const BLOCK_FRAMES: usize = 256;
+
+/// Lowest and highest sample of some frames, of all channels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Peak {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl Peak {
+    const EMPTY: Peak = Peak {
+        min: f32::INFINITY,
+        max: f32::NEG_INFINITY,
+    };
+
+    /// The peak of `samples`, `None` if there are none.
+    pub fn of<I: IntoIterator<Item = f32>>(samples: I) -> Option<Peak> {
+        let mut peak = Peak::EMPTY;
+        for sample in samples {
+            peak.add(sample);
+        }
+        (peak.min <= peak.max).then_some(peak)
+    }
+
+    fn add(&mut self, sample: f32) {
+        self.min = self.min.min(sample);
+        self.max = self.max.max(sample);
+    }
+
+    fn merge(self, other: Peak) -> Peak {
+        Peak {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+}
+
+/// The peaks of a track in blocks of `BLOCK_FRAMES` frames, and again with blocks twice
+/// as long on each level above, so drawing the waveform at any width takes a few peaks
+/// for each column.
+///
+/// It grows with the decoded audio, filled by the loader thread.
+pub struct Peaks {
+    channels: usize,
+    levels: RwLock<Vec<Vec<Peak>>>,
+    // the block being summed up, and the samples in it so far
+    pending: Mutex<(Peak, usize)>,
+}
+
+impl Peaks {
+    pub fn new(channels: u16) -> Self {
+        Self {
+            channels: channels as usize,
+            levels: RwLock::new(Vec::new()),
+            pending: Mutex::new((Peak::EMPTY, 0)),
+        }
+    }
+
+    /// Adds the interleaved samples following the ones added before.
+    pub fn push<S: Sample>(&self, samples: &[S]) {
+        let block_len = BLOCK_FRAMES * self.channels;
+        let mut pending = self.pending.lock().unwrap();
+        let mut done = Vec::new();
+        for sample in samples {
+            pending.0.add(sample.to_f32());
+            pending.1 += 1;
+            if pending.1 == block_len {
+                done.push(pending.0);
+                *pending = (Peak::EMPTY, 0);
+            }
+        }
+        if !done.is_empty() {
+            let mut levels = self.levels.write().unwrap();
+            for peak in done {
+                add_block(&mut levels, peak);
+            }
+        }
+    }
+
+    /// Adds the last block, which is shorter than the others, after the last `push`.
+    pub fn finish(&self) {
+        let mut pending = self.pending.lock().unwrap();
+        let mut levels = self.levels.write().unwrap();
+        if pending.1 != 0 {
+            add_block(&mut levels, pending.0);
+            *pending = (Peak::EMPTY, 0);
+        }
+        // a block left without a pair is a shorter block on the levels above,
+        // so every level reaches the end of the track
+        for level in 1..levels.len() {
+            let (lower, upper) = levels.split_at_mut(level);
+            let (lower, upper) = (&lower[level - 1], &mut upper[0]);
+            while upper.len() * 2 < lower.len() {
+                let first = upper.len() * 2;
+                let last = (first + 2).min(lower.len());
+                upper.push(
+                    lower[first..last]
+                        .iter()
+                        .copied()
+                        .fold(Peak::EMPTY, Peak::merge),
+                );
+            }
+        }
+    }
+
+    /// The peaks of `columns` columns splitting `frames` evenly,
+    /// `None` for the columns that are not decoded yet.
+    pub fn get_columns(&self, frames: Range<usize>, columns: usize) -> Vec<Option<Peak>> {
+        let column_frames = frames.len() as f64 / columns as f64;
+        self.get_columns_from(frames.start as f64, column_frames, columns)
+    }
+
+    /// The peaks of `columns` columns `column_frames` long from the frame `start`,
+    /// `None` for the columns before the track or not decoded yet.
+    pub fn get_columns_from(
+        &self,
+        start: f64,
+        column_frames: f64,
+        columns: usize,
+    ) -> Vec<Option<Peak>> {
+        let levels = self.levels.read().unwrap();
+        // the longest blocks that are not longer than a column
+        let mut level = 0;
+        while level + 1 < levels.len() && ((BLOCK_FRAMES << (level + 1)) as f64) <= column_frames {
+            level += 1;
+        }
+        let block_frames = BLOCK_FRAMES << level;
+        let blocks = match levels.get(level) {
+            Some(blocks) => blocks.as_slice(),
+            None => &[],
+        };
+        (0..columns)
+            .map(|column| {
+                let column_start = start + column as f64 * column_frames;
+                let column_end = column_start + column_frames;
+                if column_end <= 0.0 {
+                    return None;
+                }
+                let first = column_start.max(0.0) as usize / block_frames;
+                let last = (column_end as usize).div_ceil(block_frames).max(first + 1);
+                blocks
+                    .get(first..last.min(blocks.len()))
+                    .and_then(|blocks| blocks.iter().copied().reduce(Peak::merge))
+            })
+            .collect()
+    }
+}
+
+fn add_block(levels: &mut Vec<Vec<Peak>>, peak: Peak) {
+    let mut peak = peak;
+    let mut level = 0;
+    loop {
+        if levels.len() == level {
+            levels.push(Vec::new());
+        }
+        let blocks = &mut levels[level];
+        blocks.push(peak);
+        // every second block finishes one of the level above
+        if !blocks.len().is_multiple_of(2) {
+            return;
+        }
+        peak = blocks[blocks.len() - 2].merge(blocks[blocks.len() - 1]);
+        level += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn columns_cover_the_whole_track() {
+        // a stereo track louder in each block, with a shorter block at the end
+        let frames = BLOCK_FRAMES * 37 + 10;
+        let samples: Vec<f32> = (0..frames)
+            .flat_map(|frame| {
+                let value = (frame / BLOCK_FRAMES) as f32 / 100.0;
+                [value, -value]
+            })
+            .collect();
+        let peaks = Peaks::new(2);
+        for part in samples.chunks(1000) {
+            peaks.push(part);
+        }
+        peaks.finish();
+
+        for columns in [1, 3, 10, 37, 100, 1000] {
+            let peaks = peaks.get_columns(0..frames, columns);
+            assert!(peaks.iter().all(Option::is_some), "{} columns", columns);
+            let last = peaks.last().unwrap().unwrap();
+            assert_eq!(
+                last,
+                Peak {
+                    min: -0.37,
+                    max: 0.37
+                },
+                "{} columns",
+                columns
+            );
+        }
+        // the first of four columns is from the quieter start
+        let first = peaks.get_columns(0..frames, 4)[0].unwrap();
+        assert!(first.max < 0.2 && first.min == -first.max);
+        // frames that are not decoded have no peaks
+        assert!(peaks.get_columns(0..frames * 2, 4)[3].is_none());
+    }
+}
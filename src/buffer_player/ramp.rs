@@ -0,0 +1,91 @@
+use serde::Deserialize;
+
+/// How the speed glides from one value to another.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+pub enum RampCurve {
+    Linear,
+    /// Starts and ends slowly like the motor of a tape deck or a turntable.
+    #[default]
+    Tape,
+}
+
+impl RampCurve {
+    // maps the progress of the ramp 0.0 .. 1.0 to how far the speed has changed
+    fn shape(&self, t: f32) -> f32 {
+        match self {
+            RampCurve::Linear => t,
+            RampCurve::Tape => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+/// A speed change spread over a number of frames.
+#[derive(Clone)]
+pub struct SpeedRamp {
+    from: f32,
+    to: f32,
+    curve: RampCurve,
+    position: usize,
+    len: usize,
+}
+
+impl SpeedRamp {
+    pub fn new(from: f32, to: f32, len: usize, curve: RampCurve) -> Self {
+        Self {
+            from,
+            to,
+            curve,
+            position: 0,
+            len: len.max(1),
+        }
+    }
+
+    pub fn target(&self) -> f32 {
+        self.to
+    }
+
+    /// Moves one frame forward and returns the speed of that frame.
+    pub fn next_speed(&mut self) -> f32 {
+        self.position = (self.position + 1).min(self.len);
+        let t = self.curve.shape(self.position as f32 / self.len as f32);
+        self.from + (self.to - self.from) * t
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.position >= self.len
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ramps_reach_the_target_after_their_length() {
+        for curve in [RampCurve::Linear, RampCurve::Tape] {
+            // through a stop into reverse
+            let mut ramp = SpeedRamp::new(1.0, -1.0, 100, curve);
+            let speeds: Vec<f32> = (0..100).map(|_| ramp.next_speed()).collect();
+            assert!(ramp.is_done());
+            assert_eq!(speeds[49], 0.0, "{:?}", curve);
+            assert_eq!(speeds[99], -1.0, "{:?}", curve);
+            assert!(
+                speeds.windows(2).all(|pair| pair[1] < pair[0]),
+                "{:?}",
+                curve
+            );
+            // it stays at the target
+            assert_eq!(ramp.next_speed(), -1.0);
+        }
+    }
+
+    #[test]
+    fn tape_ramps_start_and_end_slowly() {
+        let mut linear = SpeedRamp::new(0.0, 1.0, 100, RampCurve::Linear);
+        let mut tape = SpeedRamp::new(0.0, 1.0, 100, RampCurve::Tape);
+        let linear: Vec<f32> = (0..100).map(|_| linear.next_speed()).collect();
+        let tape: Vec<f32> = (0..100).map(|_| tape.next_speed()).collect();
+        assert!(tape[0] < linear[0]);
+        assert!(tape[98] > linear[98]);
+    }
+}
@@ -0,0 +1,482 @@
+use super::{AudioDecoder, FrameBuffer, LoadError};
+use arc_swap::ArcSwap;
+use rodio::Sample;
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+// frames per decoded chunk
+const CHUNK_FRAMES: usize = 16384;
+// how far the decoder runs ahead of the read position
+const AHEAD_TIME: Duration = Duration::from_secs(10);
+// how much already played audio is kept for reverse playback
+const BEHIND_TIME: Duration = Duration::from_secs(30);
+
+const UNKNOWN_LENGTH: usize = usize::MAX;
+
+/// Decoded frames around the read position, never changed once it is in place.
+#[derive(Clone)]
+struct StreamWindow<S> {
+    start_frame: usize,
+    chunks: VecDeque<Arc<[S]>>,
+}
+
+impl<S> StreamWindow<S> {
+    fn end_frame(&self, channels: usize) -> usize {
+        self.start_frame
+            + self
+                .chunks
+                .iter()
+                .map(|chunk| chunk.len() / channels)
+                .sum::<usize>()
+    }
+}
+
+struct StreamShared<S> {
+    path: PathBuf,
+    channels: u16,
+    sample_rate: u32,
+    ahead_frames: usize,
+    behind_frames: usize,
+    // swapped by the decoder, reading never waits for it
+    window: ArcSwap<StreamWindow<S>>,
+    read_frame: AtomicUsize,
+    // frames decoded so far, used while the total length is unknown
+    known_frames: AtomicUsize,
+    total_frames: AtomicUsize,
+    stop: AtomicBool,
+    // why decoding stopped, until it is taken
+    error: Mutex<Option<LoadError>>,
+}
+
+/// A buffer that decodes the file on a background thread and only keeps
+/// a bounded window of frames around the read position in memory.
+///
+/// Jumping outside of the window decodes a new window from the new position,
+/// the old one is kept for reading until the new one is ready.
+pub struct StreamingBuffer<S> {
+    shared: Arc<StreamShared<S>>,
+}
+
+impl<S> StreamingBuffer<S>
+where
+    S: Sample + Send + Sync + 'static,
+{
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, LoadError> {
+        let path = path.as_ref().to_path_buf();
+        let decoder = AudioDecoder::open(&path)?;
+        let channels = decoder.channels();
+        let sample_rate = decoder.sample_rate();
+        let total_frames = decoder.total_frames().unwrap_or(UNKNOWN_LENGTH);
+
+        let shared = Arc::new(StreamShared {
+            path,
+            channels,
+            sample_rate,
+            ahead_frames: super::duration_to_frames(AHEAD_TIME, sample_rate),
+            behind_frames: super::duration_to_frames(BEHIND_TIME, sample_rate),
+            window: ArcSwap::from_pointee(StreamWindow {
+                start_frame: 0,
+                chunks: VecDeque::new(),
+            }),
+            read_frame: AtomicUsize::new(0),
+            known_frames: AtomicUsize::new(0),
+            total_frames: AtomicUsize::new(total_frames),
+            stop: AtomicBool::new(false),
+            error: Mutex::new(None),
+        });
+
+        if total_frames == UNKNOWN_LENGTH {
+            let shared2 = Arc::clone(&shared);
+            std::thread::spawn(move || scan_length(shared2));
+        }
+        let shared2 = Arc::clone(&shared);
+        std::thread::spawn(move || decode_loop(shared2, decoder));
+
+        Ok(Self { shared })
+    }
+}
+
+impl<S, T> FrameBuffer<T> for StreamingBuffer<S>
+where
+    S: Sample + Send + Sync,
+    T: Sample,
+{
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.shared.channels
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.shared.sample_rate
+    }
+
+    fn frame_count(&self) -> usize {
+        match self.shared.total_frames.load(Ordering::Relaxed) {
+            UNKNOWN_LENGTH => self.shared.known_frames.load(Ordering::Relaxed),
+            total_frames => total_frames,
+        }
+    }
+
+    // the length is known once the scan is done
+    #[inline]
+    fn is_complete(&self) -> bool {
+        self.shared.total_frames.load(Ordering::Relaxed) != UNKNOWN_LENGTH
+    }
+
+    #[inline]
+    fn sample(&self, frame: usize, channel: u16) -> Option<T> {
+        let window = self.shared.window.load();
+        let offset = frame.checked_sub(window.start_frame)?;
+        window
+            .chunks
+            .get(offset / CHUNK_FRAMES)?
+            .get((offset % CHUNK_FRAMES) * self.shared.channels as usize + channel as usize)
+            .map(T::from)
+    }
+
+    fn read_frames(&self, frame: usize, out: &mut [T]) {
+        let channels = self.shared.channels as usize;
+        let window = self.shared.window.load();
+        let mut position = match frame.checked_sub(window.start_frame) {
+            Some(offset) => offset * channels,
+            None => return,
+        };
+        let mut out = out;
+        while !out.is_empty() {
+            let chunk_len = CHUNK_FRAMES * channels;
+            let chunk = match window.chunks.get(position / chunk_len) {
+                Some(chunk) => chunk,
+                None => return,
+            };
+            let start = (position % chunk_len).min(chunk.len());
+            let count = (chunk.len() - start).min(out.len());
+            if count == 0 {
+                return;
+            }
+            super::convert_samples(&chunk[start..start + count], &mut out[..count]);
+            out = &mut out[count..];
+            position += count;
+        }
+    }
+
+    #[inline]
+    fn set_read_frame(&self, frame: usize) {
+        self.shared.read_frame.store(frame, Ordering::Relaxed);
+    }
+
+    fn take_error(&self) -> Option<LoadError> {
+        self.shared.error.lock().unwrap().take()
+    }
+}
+
+impl<S> Drop for StreamingBuffer<S> {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+fn decode_loop<S>(shared: Arc<StreamShared<S>>, mut decoder: AudioDecoder)
+where
+    S: Sample,
+{
+    let channels = shared.channels as usize;
+    let mut last_read_frame = 0;
+    // windows that were swapped out, dropped here once nothing reads them
+    // so the audio thread never frees them
+    let mut retired: Vec<Arc<StreamWindow<S>>> = Vec::new();
+
+    while !shared.stop.load(Ordering::Relaxed) {
+        retired.retain(|window| Arc::strong_count(window) > 1);
+        let read_frame = shared.read_frame.load(Ordering::Relaxed);
+        let moving_back = read_frame < last_read_frame;
+        last_read_frame = read_frame;
+        let window = shared.window.load_full();
+        let start_frame = window.start_frame;
+        let end_frame = window.end_frame(channels);
+
+        let result = if read_frame > end_frame + shared.ahead_frames + shared.behind_frames
+            || read_frame + shared.ahead_frames < start_frame
+        {
+            // the read position left the window, the old window is played
+            // until the new one is decoded. keep some frames before the read
+            // position when jumping backward, for reverse playback
+            let new_start_frame = if read_frame < start_frame {
+                read_frame.saturating_sub(shared.behind_frames)
+            } else {
+                read_frame
+            } / CHUNK_FRAMES
+                * CHUNK_FRAMES;
+            new_window(&shared, &mut decoder, new_start_frame, read_frame).map(Some)
+        } else if start_frame > 0
+            && (read_frame < start_frame
+                || moving_back && read_frame < start_frame + shared.behind_frames / 2)
+        {
+            // playing backward toward the start of the window
+            extend_window_back(&shared, &mut decoder, &window, read_frame).map(Some)
+        } else {
+            let total_frames = shared.total_frames.load(Ordering::Relaxed);
+            if end_frame < read_frame + shared.ahead_frames && end_frame < total_frames {
+                extend_window(&shared, &mut decoder, &window, read_frame).map(Some)
+            } else {
+                std::thread::sleep(Duration::from_millis(5));
+                Ok(None)
+            }
+        };
+        match result {
+            Ok(Some(window)) => retired.push(shared.window.swap(Arc::new(window))),
+            Ok(None) => {}
+            Err(e) => {
+                // the track ends with what could be decoded
+                log::error!("error decoding stream: {}", e);
+                let end_frame = shared.window.load().end_frame(channels);
+                shared.known_frames.store(end_frame, Ordering::Relaxed);
+                shared.total_frames.store(end_frame, Ordering::Relaxed);
+                *shared.error.lock().unwrap() = Some(e);
+                return;
+            }
+        }
+    }
+}
+
+// decodes a new window from `start_frame` through `read_frame`
+fn new_window<S>(
+    shared: &StreamShared<S>,
+    decoder: &mut AudioDecoder,
+    start_frame: usize,
+    read_frame: usize,
+) -> Result<StreamWindow<S>, LoadError>
+where
+    S: Sample,
+{
+    let chunks = decode_chunks(shared, decoder, start_frame, read_frame + 1)?;
+    Ok(StreamWindow {
+        start_frame,
+        chunks,
+    })
+}
+
+// decodes the frames before the window, so it reaches `behind_frames` before `read_frame`
+fn extend_window_back<S>(
+    shared: &StreamShared<S>,
+    decoder: &mut AudioDecoder,
+    window: &StreamWindow<S>,
+    read_frame: usize,
+) -> Result<StreamWindow<S>, LoadError>
+where
+    S: Sample,
+{
+    let channels = shared.channels as usize;
+    let start_frame = window.start_frame;
+    let new_start_frame =
+        read_frame.saturating_sub(shared.behind_frames) / CHUNK_FRAMES * CHUNK_FRAMES;
+    let mut chunks = decode_chunks(shared, decoder, new_start_frame, start_frame)?;
+    let joined = chunks.len() == (start_frame - new_start_frame) / CHUNK_FRAMES
+        && chunks
+            .iter()
+            .all(|chunk| chunk.len() == CHUNK_FRAMES * channels);
+    if joined {
+        chunks.extend(window.chunks.iter().cloned());
+    }
+    let mut window = StreamWindow {
+        start_frame: new_start_frame,
+        chunks,
+    };
+    // drop the chunks that are too far ahead
+    while window.chunks.len() > 1
+        && window.end_frame(channels) > read_frame + CHUNK_FRAMES + shared.behind_frames
+    {
+        window.chunks.pop_back();
+    }
+    Ok(window)
+}
+
+// decodes the next chunk after the end of the window
+fn extend_window<S>(
+    shared: &StreamShared<S>,
+    decoder: &mut AudioDecoder,
+    window: &StreamWindow<S>,
+    read_frame: usize,
+) -> Result<StreamWindow<S>, LoadError>
+where
+    S: Sample,
+{
+    decoder.seek(window.end_frame(shared.channels as usize), &shared.stop)?;
+    let chunk = decode_chunk(shared, decoder)?;
+    let mut window = window.clone();
+    if !chunk.is_empty() {
+        window.chunks.push_back(chunk);
+    }
+    // drop the chunks that are too far behind
+    while window.chunks.len() > 1
+        && window.start_frame + CHUNK_FRAMES + shared.behind_frames < read_frame
+    {
+        window.chunks.pop_front();
+        window.start_frame += CHUNK_FRAMES;
+    }
+    Ok(window)
+}
+
+// the chunks from `start_frame` until they reach `end_frame` or the end of the track
+fn decode_chunks<S>(
+    shared: &StreamShared<S>,
+    decoder: &mut AudioDecoder,
+    start_frame: usize,
+    end_frame: usize,
+) -> Result<VecDeque<Arc<[S]>>, LoadError>
+where
+    S: Sample,
+{
+    decoder.seek(start_frame, &shared.stop)?;
+    let mut chunks = VecDeque::new();
+    while decoder.frame() < end_frame && !shared.stop.load(Ordering::Relaxed) {
+        let chunk = decode_chunk(shared, decoder)?;
+        let full = chunk.len() == CHUNK_FRAMES * shared.channels as usize;
+        if !chunk.is_empty() {
+            chunks.push_back(chunk);
+        }
+        if !full {
+            break;
+        }
+    }
+    Ok(chunks)
+}
+
+fn decode_chunk<S>(
+    shared: &StreamShared<S>,
+    decoder: &mut AudioDecoder,
+) -> Result<Arc<[S]>, LoadError>
+where
+    S: Sample,
+{
+    let chunk = decoder.read(CHUNK_FRAMES)?;
+    shared
+        .known_frames
+        .fetch_max(decoder.frame(), Ordering::Relaxed);
+    if chunk.len() < CHUNK_FRAMES * shared.channels as usize {
+        shared
+            .total_frames
+            .store(decoder.frame(), Ordering::Relaxed);
+    }
+    Ok(chunk.into())
+}
+
+// decoders that do not know their length have to be read through once
+fn scan_length<S>(shared: Arc<StreamShared<S>>) {
+    let mut decoder = match AudioDecoder::open(&shared.path) {
+        Ok(decoder) => decoder,
+        Err(e) => {
+            log::error!("error scanning stream length: {}", e);
+            return;
+        }
+    };
+    loop {
+        if shared.stop.load(Ordering::Relaxed) {
+            return;
+        }
+        let count = match decoder.read::<f32>(CHUNK_FRAMES) {
+            Ok(samples) => samples.len() / shared.channels as usize,
+            Err(e) => {
+                log::error!("error scanning stream length: {}", e);
+                break;
+            }
+        };
+        shared
+            .known_frames
+            .fetch_max(decoder.frame(), Ordering::Relaxed);
+        if count < CHUNK_FRAMES {
+            break;
+        }
+    }
+    shared
+        .total_frames
+        .store(decoder.frame(), Ordering::Relaxed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    // a mono float WAV file where each sample is its frame index in millionths
+    fn write_counting_wav(name: &str, frames: usize) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("yee_player_{}_{}.wav", name, std::process::id()));
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 1000,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for frame in 0..frames {
+            writer.write_sample(frame as f32 / 1e6).unwrap();
+        }
+        writer.finalize().unwrap();
+        path
+    }
+
+    fn sample_at(buffer: &StreamingBuffer<f32>, frame: usize) -> Option<f32> {
+        FrameBuffer::<f32>::sample(buffer, frame, 0)
+    }
+
+    fn wait_for(buffer: &StreamingBuffer<f32>, frame: usize) {
+        let start = Instant::now();
+        while sample_at(buffer, frame).is_none() {
+            assert!(
+                start.elapsed() < Duration::from_secs(10),
+                "frame {} is never decoded",
+                frame
+            );
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(sample_at(buffer, frame), Some(frame as f32 / 1e6));
+    }
+
+    #[test]
+    fn window_follows_the_read_position() {
+        // at 1000 Hz the window keeps 10000 frames ahead and 30000 behind
+        let path = write_counting_wav("window", 200_000);
+        let buffer = StreamingBuffer::<f32>::open(&path).unwrap();
+        assert_eq!(FrameBuffer::<f32>::frame_count(&buffer), 200_000);
+
+        // jumping forward decodes a new window there
+        buffer.shared.read_frame.store(150_000, Ordering::Relaxed);
+        wait_for(&buffer, 150_000);
+        assert_eq!(sample_at(&buffer, 0), None);
+
+        // going a little backward decodes the frames before the window
+        // and keeps the ones after it
+        buffer.shared.read_frame.store(140_000, Ordering::Relaxed);
+        wait_for(&buffer, 140_000);
+        wait_for(&buffer, 110_000);
+        wait_for(&buffer, 150_000);
+
+        // jumping far backward decodes a new window there
+        buffer.shared.read_frame.store(20_000, Ordering::Relaxed);
+        wait_for(&buffer, 20_000);
+        wait_for(&buffer, 0);
+        assert_eq!(sample_at(&buffer, 150_000), None);
+
+        // playing forward decodes ahead and drops what is far behind
+        for read_frame in (20_000..100_000).step_by(1000) {
+            buffer
+                .shared
+                .read_frame
+                .store(read_frame, Ordering::Relaxed);
+            wait_for(&buffer, read_frame + 5000);
+        }
+        assert_eq!(sample_at(&buffer, 0), None);
+        wait_for(&buffer, 70_000);
+
+        drop(buffer);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
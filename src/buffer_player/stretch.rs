@@ -0,0 +1,379 @@
+use super::FrameBuffer;
+use rodio::Sample;
+use std::ops::Range;
+
+// length of each grain
+const GRAIN_TIME: f32 = 0.04;
+// how far a grain can be moved to match the previous one
+const TOLERANCE_TIME: f32 = 0.006;
+
+/// Changes the tempo without changing the pitch, by overlapping grains of the audio
+/// that are moved slightly to match the waveform of the previous grain (WSOLA).
+///
+/// Grains are read backward when the speed is negative,
+/// which plays the audio reversed at its original pitch.
+///
+/// Grains can also be resampled while being read, which shifts the pitch
+/// without changing the tempo.
+pub struct TimeStretch<S> {
+    channels: usize,
+    grain_len: usize,
+    hop: usize,
+    tolerance: usize,
+    window: Vec<f32>,
+    // where the next grain is taken from
+    position: f64,
+    // where the last grain was actually taken from
+    previous: Option<isize>,
+    overlap: Vec<f32>,
+    output: Vec<f32>,
+    output_index: usize,
+    block: Vec<S>,
+    grain: Vec<f32>,
+    reference: Vec<f32>,
+    candidates: Vec<f32>,
+}
+
+impl<S> TimeStretch<S> {
+    pub fn channels(&self) -> u16 {
+        self.channels as u16
+    }
+
+    pub fn position(&self) -> f64 {
+        self.position
+    }
+
+    /// Jumps to another frame, the audio still left in the overlap fades out smoothly.
+    pub fn set_position(&mut self, position: f64) {
+        self.position = position;
+        self.previous = None;
+    }
+}
+
+impl<S> TimeStretch<S>
+where
+    S: Sample,
+{
+    pub fn new(channels: u16, sample_rate: u32) -> Self {
+        let hop = ((sample_rate as f32 * GRAIN_TIME) as usize / 2).max(1);
+        let grain_len = hop * 2;
+        // periodic hann window, overlapping at half of its length sums to 1
+        let window = (0..grain_len)
+            .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / grain_len as f32).cos())
+            .collect();
+        Self {
+            channels: channels as usize,
+            grain_len,
+            hop,
+            tolerance: (sample_rate as f32 * TOLERANCE_TIME) as usize,
+            window,
+            position: 0.0,
+            previous: None,
+            overlap: vec![0.0; grain_len * channels as usize],
+            output: Vec::new(),
+            output_index: 0,
+            block: Vec::new(),
+            grain: Vec::new(),
+            reference: Vec::new(),
+            candidates: Vec::new(),
+        }
+    }
+
+    /// Writes the next output frame into `frame`.
+    ///
+    /// `speed` is the tempo, `pitch` is the ratio of the output frequency to the original one.
+    /// The position wraps around inside `loop_range` when it is set.
+    pub fn next_frame(
+        &mut self,
+        buffer: &dyn FrameBuffer<S>,
+        speed: f32,
+        pitch: f32,
+        loop_range: Option<Range<usize>>,
+        frame: &mut Vec<f32>,
+    ) {
+        if self.output_index * self.channels >= self.output.len() {
+            self.process_grain(buffer, speed, pitch, loop_range);
+            self.output_index = 0;
+        }
+        let start = self.output_index * self.channels;
+        frame.clear();
+        frame.extend_from_slice(&self.output[start..start + self.channels]);
+        self.output_index += 1;
+    }
+
+    fn process_grain(
+        &mut self,
+        buffer: &dyn FrameBuffer<S>,
+        speed: f32,
+        pitch: f32,
+        loop_range: Option<Range<usize>>,
+    ) {
+        let frame_count = buffer.frame_count();
+        let loop_range = loop_range.filter(|range| !range.is_empty());
+        let direction = if speed < 0.0 { -1 } else { 1 };
+        let target = self.position.round() as isize;
+
+        // find the grain that continues the waveform of the previous one best
+        let start = match self.previous {
+            Some(previous) => {
+                // offsets are counted in output frames, which cover `pitch` input frames each
+                let input_frames = |frames: usize| (frames as f32 * pitch).round() as isize;
+                let natural = previous + direction * input_frames(self.hop);
+                self.read_mono(buffer, natural, direction, self.hop, pitch, &loop_range);
+                std::mem::swap(&mut self.reference, &mut self.candidates);
+                let first = target - direction * input_frames(self.tolerance);
+                self.read_mono(
+                    buffer,
+                    first,
+                    direction,
+                    self.tolerance * 2 + self.hop,
+                    pitch,
+                    &loop_range,
+                );
+                let mut best = (f32::MIN, self.tolerance);
+                for offset in 0..=self.tolerance * 2 {
+                    let correlation = self
+                        .reference
+                        .iter()
+                        .zip(&self.candidates[offset..])
+                        .step_by(2)
+                        .map(|(a, b)| a * b)
+                        .sum::<f32>();
+                    if correlation > best.0 {
+                        best = (correlation, offset);
+                    }
+                }
+                first + direction * input_frames(best.1)
+            }
+            None => target,
+        };
+
+        // overlap-add the windowed grain
+        self.read(buffer, start, direction, self.grain_len, pitch, &loop_range);
+        for (i, value) in self.grain.iter().enumerate() {
+            self.overlap[i] += value * self.window[i / self.channels];
+        }
+        let hop_len = self.hop * self.channels;
+        self.output.clear();
+        self.output.extend_from_slice(&self.overlap[..hop_len]);
+        self.overlap.copy_within(hop_len.., 0);
+        let len = self.overlap.len();
+        self.overlap[len - hop_len..].fill(0.0);
+
+        self.previous = Some(start);
+        self.position += speed as f64 * self.hop as f64;
+        match loop_range {
+            Some(range) => {
+                self.position = range.start as f64
+                    + (self.position - range.start as f64).rem_euclid(range.len() as f64);
+            }
+            _ => self.position = self.position.max(0.0).min(frame_count as f64),
+        }
+    }
+
+    // read `len` frames resampled by `rate` into self.grain, going backward from `start`
+    // if `direction` is negative. frames out of `loop_range` are wrapped around into it,
+    // and frames out of the buffer are silent without it.
+    fn read(
+        &mut self,
+        buffer: &dyn FrameBuffer<S>,
+        start: isize,
+        direction: isize,
+        len: usize,
+        rate: f32,
+        loop_range: &Option<Range<usize>>,
+    ) {
+        let channels = self.channels;
+        let frame_count = buffer.frame_count() as isize;
+        // one more frame for the linear interpolation of the last output frame
+        let raw_len = ((len - 1) as f32 * rate) as usize + 2;
+        let first = if direction < 0 {
+            start - raw_len as isize + 1
+        } else {
+            start
+        };
+        self.block.clear();
+        self.block.resize(raw_len * channels, S::zero_value());
+        if let Some(range) = loop_range {
+            // split the frames at the end of the loop
+            let mut i = 0;
+            while i < raw_len {
+                let offset = (first + i as isize - range.start as isize)
+                    .rem_euclid(range.len() as isize) as usize;
+                let frame = range.start + offset;
+                let count = (range.end - frame).min(raw_len - i);
+                buffer.read_frames(frame, &mut self.block[i * channels..(i + count) * channels]);
+                i += count;
+            }
+        } else {
+            let inner_start = first.max(0).min(frame_count);
+            let inner_end = (first + raw_len as isize).min(frame_count).max(inner_start);
+            if inner_end > inner_start {
+                let offset = (inner_start - first) as usize * channels;
+                let inner_len = (inner_end - inner_start) as usize * channels;
+                buffer.read_frames(
+                    inner_start as usize,
+                    &mut self.block[offset..offset + inner_len],
+                );
+            }
+        }
+
+        let block = &self.block;
+        let sample = |frame: usize, channel: usize| {
+            let frame = if direction < 0 {
+                raw_len - 1 - frame
+            } else {
+                frame
+            };
+            block[frame * channels + channel].to_f32()
+        };
+        self.grain.clear();
+        for i in 0..len {
+            let position = i as f32 * rate;
+            let frame = position as usize;
+            let fract = position - frame as f32;
+            for channel in 0..channels {
+                let current = sample(frame, channel);
+                let next = sample(frame + 1, channel);
+                self.grain.push(current + (next - current) * fract);
+            }
+        }
+    }
+
+    // read the frames mixed to mono into self.candidates
+    fn read_mono(
+        &mut self,
+        buffer: &dyn FrameBuffer<S>,
+        start: isize,
+        direction: isize,
+        len: usize,
+        rate: f32,
+        loop_range: &Option<Range<usize>>,
+    ) {
+        self.read(buffer, start, direction, len, rate, loop_range);
+        self.candidates.clear();
+        self.candidates.extend(
+            self.grain
+                .chunks(self.channels)
+                .map(|frame| frame.iter().sum::<f32>()),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer_player::{BufferPlayer, PlayerEvent, SamplesBuffer};
+    use std::sync::Arc;
+
+    const SAMPLE_RATE: u32 = 48000;
+
+    fn sine(frequency: f32, seconds: f32) -> Vec<i16> {
+        (0..(SAMPLE_RATE as f32 * seconds) as usize)
+            .map(|i| {
+                let phase = 2.0 * std::f32::consts::PI * frequency * i as f32 / SAMPLE_RATE as f32;
+                (phase.sin() * 0.5 * i16::MAX as f32) as i16
+            })
+            .collect()
+    }
+
+    fn player(data: Vec<i16>, speed: f32, pitch: f32) -> BufferPlayer<f32> {
+        let mut player =
+            BufferPlayer::<f32>::new(Arc::new(SamplesBuffer::new(1, SAMPLE_RATE, data)));
+        player.report_events();
+        player.set_time_stretch(true);
+        player.set_speed(speed);
+        player.set_pitch(pitch);
+        player
+    }
+
+    // frames played until the track ends
+    fn output_len(mut player: BufferPlayer<f32>) -> usize {
+        let mut len = 0;
+        while !player
+            .take_events()
+            .any(|event| event == PlayerEvent::TrackEnded)
+        {
+            player.next();
+            len += 1;
+            assert!(len < SAMPLE_RATE as usize * 10, "the track never ended");
+        }
+        len
+    }
+
+    // frequency from the number of upward zero crossings
+    fn frequency(samples: &[f32]) -> f32 {
+        let crossings = samples
+            .windows(2)
+            .filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0)
+            .count();
+        crossings as f32 * SAMPLE_RATE as f32 / samples.len() as f32
+    }
+
+    fn assert_close(value: f32, expected: f32, tolerance: f32) {
+        assert!(
+            (value - expected).abs() <= expected * tolerance,
+            "{} is not close to {}",
+            value,
+            expected
+        );
+    }
+
+    #[test]
+    fn tempo_changes_the_length() {
+        let hop = SAMPLE_RATE as f32 * super::GRAIN_TIME / 2.0;
+        for &speed in &[0.5, 2.0] {
+            let len = output_len(player(sine(440.0, 1.0), speed, 0.0));
+            let expected = SAMPLE_RATE as f32 / speed;
+            assert!(
+                (len as f32 - expected).abs() <= hop,
+                "speed {} played {} frames",
+                speed,
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn tempo_keeps_the_pitch() {
+        for &speed in &[0.5, 2.0] {
+            let output = player(sine(440.0, 4.0), speed, 0.0)
+                .skip(4096)
+                .take(SAMPLE_RATE as usize / 2)
+                .collect::<Vec<_>>();
+            assert_close(frequency(&output), 440.0, 0.02);
+        }
+    }
+
+    #[test]
+    fn pitch_shifts_by_semitones() {
+        for &(pitch, expected) in &[(12.0, 880.0), (-12.0, 220.0)] {
+            let output = player(sine(440.0, 2.0), 1.0, pitch)
+                .skip(4096)
+                .take(SAMPLE_RATE as usize / 2)
+                .collect::<Vec<_>>();
+            assert_close(frequency(&output), expected, 0.02);
+        }
+    }
+
+    #[test]
+    fn reverse_plays_backward_at_the_same_pitch() {
+        // a low half followed by a high half, so the order shows in the output
+        let mut data = sine(440.0, 0.5);
+        data.extend(sine(880.0, 0.5));
+        let mut reversed = player(data.clone(), -1.0, 0.0);
+        reversed.set_time(std::time::Duration::from_secs(1));
+        let output = reversed
+            .by_ref()
+            .take(SAMPLE_RATE as usize)
+            .collect::<Vec<_>>();
+        let quarter = SAMPLE_RATE as usize / 4;
+        assert_close(frequency(&output[4096..quarter]), 880.0, 0.02);
+        assert_close(frequency(&output[quarter * 3..]), 440.0, 0.02);
+
+        let mut reversed = player(data, -1.0, 0.0);
+        reversed.set_time(std::time::Duration::from_secs(1));
+        let len = output_len(reversed);
+        let hop = SAMPLE_RATE as f32 * super::GRAIN_TIME / 2.0;
+        assert!((len as f32 - SAMPLE_RATE as f32).abs() <= hop, "{}", len);
+    }
+}
@@ -0,0 +1,316 @@
+use crate::buffer_player::Output;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+usage: yee_player [options] [music files...]
+
+options:
+    --speed <speed>      playback speed, bigger than 0
+    --volume <volume>    volume from 0 to 1
+    --loop               loop the track
+    --start <time>       start at seconds, minutes:seconds or hours:minutes:seconds
+    --reverse            play backwards
+    --setting <path>     setting file instead of ./asset/setting/setting.ron
+    --shader <path>      shader file instead of ./asset/shader/shader.wgsl
+    --headless           play without the window and the GPU
+    --output <device, null or wav file>
+                         where the sound goes instead of the output in the setting,
+                         device:<name> plays on the sound device with that name
+    --list-devices       print the names of the sound devices
+    --export <wav file>  export the music file to a wav file without the window
+    --save-playlist <m3u, m3u8 or pls file>
+                         save the music files as a playlist without the window
+    -h, --help           print this help
+    -V, --version        print the version
+
+more than one music file makes a playlist, m3u, m3u8 and pls playlists can be opened too";
+
+/// What the command line asks for.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Run(CommandLine),
+    Help,
+    Version,
+    ListDevices,
+}
+
+/// Options given on the command line, the ones not given are taken from the setting.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CommandLine {
+    pub files: Vec<String>,
+    pub speed: Option<f32>,
+    pub volume: Option<f32>,
+    pub loop_mode: bool,
+    /// In seconds.
+    pub start: Option<f32>,
+    pub reverse: bool,
+    pub setting_path: Option<String>,
+    pub shader_path: Option<String>,
+    pub headless: bool,
+    pub output: Option<Output>,
+    pub export: Option<PathBuf>,
+    pub save_playlist: Option<PathBuf>,
+}
+
+impl CommandLine {
+    /// The speed with the direction from `--reverse`.
+    pub fn signed_speed(&self) -> Option<f32> {
+        match (self.speed, self.reverse) {
+            (speed, false) => speed,
+            (speed, true) => Some(-speed.unwrap_or(1.0)),
+        }
+    }
+
+    /// `--export` and `--save-playlist` never need the window.
+    pub fn is_headless(&self) -> bool {
+        self.headless || self.export.is_some() || self.save_playlist.is_some()
+    }
+}
+
+/// Reads `std::env::args`, the first argument is the program itself.
+pub fn parse(args: &[String]) -> Result<Command, String> {
+    let mut command_line = CommandLine::default();
+    let mut args = args.iter().skip(1);
+    let mut only_files = false;
+    while let Some(arg) = args.next() {
+        if only_files || !arg.starts_with('-') || arg == "-" {
+            command_line.files.push(arg.clone());
+            continue;
+        }
+        // `--speed=2` is the same as `--speed 2`
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) => (flag, Some(value.to_string())),
+            None => (arg.as_str(), None),
+        };
+        let takes_value = matches!(
+            flag,
+            "--speed"
+                | "--volume"
+                | "--start"
+                | "--setting"
+                | "--shader"
+                | "--output"
+                | "--export"
+                | "--save-playlist"
+        );
+        if inline_value.is_some() && !takes_value {
+            return Err(format!("{} does not take a value", flag));
+        }
+        let mut value = |name: &str| {
+            inline_value
+                .clone()
+                .or_else(|| args.next().cloned())
+                .ok_or(format!("{} needs a value", name))
+        };
+        match flag {
+            "--" => only_files = true,
+            "-h" | "--help" => return Ok(Command::Help),
+            "-V" | "--version" => return Ok(Command::Version),
+            "--list-devices" => return Ok(Command::ListDevices),
+            "--headless" => command_line.headless = true,
+            "--loop" => command_line.loop_mode = true,
+            "--reverse" => command_line.reverse = true,
+            "--speed" => {
+                let speed = parse_number(flag, &value(flag)?)?;
+                if speed <= 0.0 {
+                    return Err(format!(
+                        "--speed must be bigger than 0, got {}, use --reverse to play backwards",
+                        speed
+                    ));
+                }
+                command_line.speed = Some(speed);
+            }
+            "--volume" => {
+                let volume = parse_number(flag, &value(flag)?)?;
+                if !(0.0..=1.0).contains(&volume) {
+                    return Err(format!("--volume must be from 0 to 1, got {}", volume));
+                }
+                command_line.volume = Some(volume);
+            }
+            "--start" => command_line.start = Some(parse_time(&value(flag)?)?),
+            "--setting" => command_line.setting_path = Some(value(flag)?),
+            "--shader" => command_line.shader_path = Some(value(flag)?),
+            "--output" => command_line.output = Some(Output::parse(&value(flag)?)),
+            "--export" => command_line.export = Some(PathBuf::from(value(flag)?)),
+            "--save-playlist" => {
+                command_line.save_playlist = Some(PathBuf::from(value(flag)?));
+            }
+            _ => return Err(format!("unknown option {:?}", arg)),
+        }
+    }
+    if command_line.export.is_some() && command_line.files.len() > 1 {
+        return Err("--export takes only one music file".to_string());
+    }
+    if command_line.export.is_some() && command_line.loop_mode {
+        return Err("--export can not --loop, the exported track has to end".to_string());
+    }
+    Ok(Command::Run(command_line))
+}
+
+fn parse_number(flag: &str, value: &str) -> Result<f32, String> {
+    value
+        .parse::<f32>()
+        .ok()
+        .filter(|number| number.is_finite())
+        .ok_or(format!("{} needs a number, got {:?}", flag, value))
+}
+
+// `90`, `1:30` and `0:01:30` are all 90 seconds
+fn parse_time(value: &str) -> Result<f32, String> {
+    let error = || {
+        format!(
+            "--start needs seconds, minutes:seconds or hours:minutes:seconds, got {:?}",
+            value
+        )
+    };
+    let parts: Vec<&str> = value.split(':').collect();
+    if parts.len() > 3 {
+        return Err(error());
+    }
+    let mut seconds = 0.0;
+    for (i, part) in parts.iter().enumerate() {
+        let number = part
+            .parse::<f32>()
+            .ok()
+            .filter(|number| number.is_finite() && *number >= 0.0)
+            .ok_or_else(error)?;
+        // only the first part may go over 60
+        if i > 0 && number >= 60.0 {
+            return Err(error());
+        }
+        seconds = seconds * 60.0 + number;
+    }
+    Ok(seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(args: &[&str]) -> Result<CommandLine, String> {
+        let args: Vec<String> = std::iter::once("yee_player")
+            .chain(args.iter().copied())
+            .map(String::from)
+            .collect();
+        match parse(&args)? {
+            Command::Run(command_line) => Ok(command_line),
+            command => panic!("{:?} for {:?}", command, args),
+        }
+    }
+
+    #[test]
+    fn no_arguments() {
+        assert_eq!(run(&[]), Ok(CommandLine::default()));
+    }
+
+    #[test]
+    fn each_flag() {
+        let command_line = run(&[
+            "--speed",
+            "1.5",
+            "--volume=0.5",
+            "--loop",
+            "--start",
+            "1:30",
+            "--reverse",
+            "--setting",
+            "my.ron",
+            "--shader=my.wgsl",
+            "--headless",
+            "--output",
+            "device:Speakers",
+            "--save-playlist",
+            "list.m3u8",
+        ])
+        .unwrap();
+        assert_eq!(
+            command_line,
+            CommandLine {
+                files: vec![],
+                speed: Some(1.5),
+                volume: Some(0.5),
+                loop_mode: true,
+                start: Some(90.0),
+                reverse: true,
+                setting_path: Some("my.ron".to_string()),
+                shader_path: Some("my.wgsl".to_string()),
+                headless: true,
+                output: Some(Output::NamedDevice("Speakers".to_string())),
+                export: None,
+                save_playlist: Some(PathBuf::from("list.m3u8")),
+            }
+        );
+        assert_eq!(command_line.signed_speed(), Some(-1.5));
+        assert_eq!(
+            run(&["--export", "out.wav"]).unwrap().export,
+            Some(PathBuf::from("out.wav"))
+        );
+        assert!(run(&["--export", "out.wav"]).unwrap().is_headless());
+        assert_eq!(
+            run(&["--output", "null"]).unwrap().output,
+            Some(Output::Null)
+        );
+
+        let parse = |arg: &str| parse(&["yee_player".to_string(), arg.to_string()]);
+        assert_eq!(parse("-h"), Ok(Command::Help));
+        assert_eq!(parse("--help"), Ok(Command::Help));
+        assert_eq!(parse("-V"), Ok(Command::Version));
+        assert_eq!(parse("--version"), Ok(Command::Version));
+        assert_eq!(parse("--list-devices"), Ok(Command::ListDevices));
+    }
+
+    #[test]
+    fn missing_and_invalid_values() {
+        let error = |args: &[&str]| run(args).unwrap_err();
+        assert_eq!(error(&["--speed"]), "--speed needs a value");
+        assert_eq!(error(&["a.ogg", "--export"]), "--export needs a value");
+        assert!(error(&["--speed", "fast"]).starts_with("--speed needs a number"));
+        assert!(error(&["--speed", "0"]).contains("use --reverse"));
+        assert!(error(&["--speed=-1"]).starts_with("--speed must be bigger than 0"));
+        assert!(error(&["--speed", "inf"]).starts_with("--speed needs a number"));
+        assert!(error(&["--volume", "1.5"]).starts_with("--volume must be from 0 to 1"));
+        assert!(error(&["--start", "1:60"]).starts_with("--start needs seconds"));
+        assert_eq!(error(&["--loop=yes"]), "--loop does not take a value");
+        assert_eq!(error(&["--louder"]), "unknown option \"--louder\"");
+        assert_eq!(
+            error(&["--export", "out.wav", "a.ogg", "b.ogg"]),
+            "--export takes only one music file"
+        );
+        assert!(error(&["--export", "out.wav", "--loop"]).starts_with("--export can not --loop"));
+    }
+
+    #[test]
+    fn files_among_flags() {
+        let command_line =
+            run(&["a.ogg", "--loop", "b.mp3", "-", "--speed", "2", "c.flac"]).unwrap();
+        assert_eq!(command_line.files, ["a.ogg", "b.mp3", "-", "c.flac"]);
+        assert!(command_line.loop_mode);
+        assert_eq!(command_line.speed, Some(2.0));
+        // a value is taken even when it looks like a flag
+        assert_eq!(
+            run(&["--setting", "--loop"]).unwrap().setting_path,
+            Some("--loop".to_string())
+        );
+    }
+
+    #[test]
+    fn everything_after_double_dash_is_a_file() {
+        let command_line = run(&["--loop", "--", "--reverse", "-h", "a.ogg"]).unwrap();
+        assert_eq!(command_line.files, ["--reverse", "-h", "a.ogg"]);
+        assert!(command_line.loop_mode);
+        assert!(!command_line.reverse);
+    }
+
+    #[test]
+    fn time_formats() {
+        assert_eq!(parse_time("90"), Ok(90.0));
+        assert_eq!(parse_time("2.5"), Ok(2.5));
+        assert_eq!(parse_time("1:30"), Ok(90.0));
+        assert_eq!(parse_time("90:00"), Ok(5400.0));
+        assert_eq!(parse_time("0:01:30"), Ok(90.0));
+        assert_eq!(parse_time("1:02:03.5"), Ok(3723.5));
+        for invalid in ["", "-1", "1:", "1:60", "1:2:3:4", "1:-5", "a:30", "NaN"] {
+            assert!(parse_time(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+}
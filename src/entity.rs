@@ -2,7 +2,13 @@ pub mod button;
 
 pub mod slider;
 
+pub mod playlist;
 
+pub mod notification;
+
+pub mod text;
+
+pub mod waveform;
 
 pub mod render {
     pub use crate::renderer::Transform;
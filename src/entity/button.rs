@@ -25,17 +25,25 @@ impl StateButton {
             response: None,
         }
     }
+}
+
+impl Default for StateButton {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StateButton {
     pub fn update_with_input(&mut self, hovering: bool, pressing: bool) {
         use ButtonState::*;
         self.response = None;
         match self.state {
-            Unhover => match (hovering, pressing) {
-                (true, false) => {
+            Unhover => {
+                if let (true, false) = (hovering, pressing) {
                     self.response = Some(ButtonResponse::Hover);
                     self.state = Hover;
                 }
-                (_, _) => {}
-            },
+            }
             Hover => match (hovering, pressing) {
                 (false, _) => {
                     self.response = Some(ButtonResponse::Unhover);
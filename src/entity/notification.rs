@@ -0,0 +1,126 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+// the oldest message goes away when there are more
+const MAX_SHOWN: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Info,
+    Error,
+}
+
+impl Level {
+    fn show_time(&self) -> Duration {
+        match self {
+            Level::Info => Duration::from_secs(4),
+            Level::Error => Duration::from_secs(10),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Notification {
+    pub level: Level,
+    pub text: String,
+    until: Instant,
+}
+
+/// Messages shown in the window for a while, so they are seen without a console.
+/// They are logged too.
+///
+/// Clones share the messages, so systems and threads can keep one to push to.
+#[derive(Clone, Default)]
+pub struct Notifications {
+    shown: Arc<Mutex<VecDeque<Notification>>>,
+}
+
+impl Notifications {
+    pub fn info<T: Into<String>>(&self, text: T) {
+        let text = text.into();
+        log::info!("{}", text);
+        self.push(Level::Info, text);
+    }
+
+    pub fn error<T: Into<String>>(&self, text: T) {
+        let text = text.into();
+        log::error!("{}", text);
+        self.push(Level::Error, text);
+    }
+
+    fn push(&self, level: Level, text: String) {
+        let mut shown = self.shown.lock().unwrap();
+        // the same message again is shown longer instead of twice
+        shown.retain(|notification| notification.text != text);
+        if shown.len() >= MAX_SHOWN {
+            shown.pop_front();
+        }
+        shown.push_back(Notification {
+            level,
+            text,
+            until: Instant::now() + level.show_time(),
+        });
+    }
+
+    pub fn remove_expired(&self) {
+        self.remove_expired_at(Instant::now());
+    }
+
+    fn remove_expired_at(&self, now: Instant) {
+        self.shown
+            .lock()
+            .unwrap()
+            .retain(|notification| notification.until > now);
+    }
+
+    /// The messages being shown, oldest first.
+    pub fn get_shown(&self) -> Vec<Notification> {
+        self.shown.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(notifications: &Notifications) -> Vec<String> {
+        notifications
+            .get_shown()
+            .into_iter()
+            .map(|notification| notification.text)
+            .collect()
+    }
+
+    #[test]
+    fn shown_oldest_first() {
+        let notifications = Notifications::default();
+        for text in ["a", "b", "c", "d"] {
+            notifications.info(text);
+        }
+        assert_eq!(texts(&notifications), ["a", "b", "c", "d"]);
+        // a repeated message moves to the end
+        notifications.error("b");
+        assert_eq!(texts(&notifications), ["a", "c", "d", "b"]);
+        assert_eq!(notifications.get_shown()[3].level, Level::Error);
+        // the oldest goes away for a new one
+        notifications.info("e");
+        assert_eq!(texts(&notifications), ["c", "d", "b", "e"]);
+    }
+
+    #[test]
+    fn errors_are_shown_longer() {
+        let notifications = Notifications::default();
+        notifications.error("error");
+        notifications.info("info");
+        let now = Instant::now();
+        notifications.remove_expired_at(now);
+        assert_eq!(texts(&notifications), ["error", "info"]);
+        notifications.remove_expired_at(now + Level::Info.show_time());
+        assert_eq!(texts(&notifications), ["error"]);
+        notifications.remove_expired_at(now + Level::Error.show_time());
+        assert!(notifications.get_shown().is_empty());
+    }
+}
@@ -0,0 +1,285 @@
+use serde::Deserialize;
+
+pub mod file;
+
+/// What plays after a track ends.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+pub enum PlaylistMode {
+    /// Plays the tracks in order and stops after the last one.
+    #[default]
+    Order,
+    RepeatOne,
+    RepeatAll,
+    Shuffle,
+}
+
+/// One music file of the playlist.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Track {
+    pub path: String,
+    /// Only kept for saving the playlist again.
+    pub title: Option<String>,
+    /// In seconds, known after the track was loaded or read from a playlist file.
+    pub duration: Option<f32>,
+}
+
+impl Track {
+    pub fn new(path: String) -> Self {
+        Self {
+            path,
+            ..Default::default()
+        }
+    }
+}
+
+/// The music files to play one after another.
+pub struct Playlist {
+    tracks: Vec<Track>,
+    current: usize,
+    mode: PlaylistMode,
+    // indices played before the current one in shuffle mode, so previous goes back to them
+    history: Vec<usize>,
+    // the index picked to follow the current one, kept so preloading and moving on agree
+    following: Option<usize>,
+    random_state: u64,
+}
+
+impl Playlist {
+    pub fn new(tracks: Vec<Track>, mode: PlaylistMode) -> Self {
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|time| time.as_nanos() as u64)
+            .unwrap_or_default();
+        Self {
+            tracks,
+            current: 0,
+            mode,
+            history: Vec::new(),
+            following: None,
+            // xorshift gets stuck at 0
+            random_state: seed | 1,
+        }
+    }
+
+    /// Replaces the tracks and starts from the first one.
+    pub fn set_tracks(&mut self, tracks: Vec<Track>) {
+        self.tracks = tracks;
+        self.current = 0;
+        self.history.clear();
+        self.following = None;
+    }
+
+    pub fn get_tracks(&self) -> &[Track] {
+        &self.tracks
+    }
+
+    pub fn get_current_index(&self) -> usize {
+        self.current
+    }
+
+    /// The path of the current track.
+    pub fn get_current(&self) -> Option<&str> {
+        self.tracks
+            .get(self.current)
+            .map(|track| track.path.as_str())
+    }
+
+    /// Remembers the duration of the current track once it is loaded from `path`.
+    pub fn set_duration(&mut self, path: &str, duration: f32) {
+        if let Some(track) = self.tracks.get_mut(self.current) {
+            if track.path == path {
+                track.duration = Some(duration);
+            }
+        }
+    }
+
+    pub fn get_mode(&self) -> PlaylistMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: PlaylistMode) {
+        self.mode = mode;
+        self.history.clear();
+        self.following = None;
+    }
+
+    /// The other track `advance` moves to, `None` when it stops or repeats the current one.
+    pub fn peek_advance(&mut self) -> Option<&str> {
+        match self.mode {
+            PlaylistMode::RepeatOne => None,
+            PlaylistMode::Order if self.current + 1 >= self.tracks.len() => None,
+            _ if self.tracks.len() < 2 => None,
+            _ => {
+                let index = self.following();
+                Some(self.tracks[index].path.as_str())
+            }
+        }
+    }
+
+    /// Moves on after the current track ended, `None` when nothing should play next.
+    pub fn advance(&mut self) -> Option<&str> {
+        if self.tracks.is_empty() {
+            return None;
+        }
+        match self.mode {
+            PlaylistMode::RepeatOne => {}
+            PlaylistMode::Order if self.current + 1 >= self.tracks.len() => return None,
+            _ => self.move_on(),
+        }
+        self.get_current()
+    }
+
+    /// Skips to the next track, wrapping around at the end.
+    pub fn skip_next(&mut self) -> Option<&str> {
+        if self.tracks.is_empty() {
+            return None;
+        }
+        self.move_on();
+        self.get_current()
+    }
+
+    /// Goes back to the track played before, wrapping around at the start.
+    pub fn skip_previous(&mut self) -> Option<&str> {
+        if self.tracks.is_empty() {
+            return None;
+        }
+        self.current = match self.history.pop() {
+            Some(index) => index,
+            None => (self.current + self.tracks.len() - 1) % self.tracks.len(),
+        };
+        self.following = None;
+        self.get_current()
+    }
+
+    // the index after the current one, a random other one in shuffle mode
+    fn following(&mut self) -> usize {
+        if let Some(index) = self.following {
+            return index;
+        }
+        let len = self.tracks.len();
+        let index = if self.mode == PlaylistMode::Shuffle && len > 1 {
+            let offset = 1 + self.random() as usize % (len - 1);
+            (self.current + offset) % len
+        } else {
+            (self.current + 1) % len
+        };
+        self.following = Some(index);
+        index
+    }
+
+    fn move_on(&mut self) {
+        let index = self.following();
+        if self.mode == PlaylistMode::Shuffle {
+            self.history.push(self.current);
+        }
+        self.current = index;
+        self.following = None;
+    }
+
+    // xorshift64
+    fn random(&mut self) -> u64 {
+        let mut x = self.random_state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.random_state = x;
+        x
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn playlist(len: usize, mode: PlaylistMode) -> Playlist {
+        let tracks = (0..len).map(|i| Track::new(i.to_string())).collect();
+        Playlist::new(tracks, mode)
+    }
+
+    fn index(path: Option<&str>) -> Option<usize> {
+        path.map(|path| path.parse().unwrap())
+    }
+
+    #[test]
+    fn order_stops_after_the_last_track() {
+        let mut playlist = playlist(3, PlaylistMode::Order);
+        assert_eq!(index(playlist.peek_advance()), Some(1));
+        assert_eq!(index(playlist.advance()), Some(1));
+        assert_eq!(index(playlist.advance()), Some(2));
+        assert_eq!(index(playlist.peek_advance()), None);
+        assert_eq!(index(playlist.advance()), None);
+        assert_eq!(playlist.get_current_index(), 2);
+        // skipping wraps around
+        assert_eq!(index(playlist.skip_next()), Some(0));
+        assert_eq!(index(playlist.skip_previous()), Some(2));
+        assert_eq!(index(playlist.skip_previous()), Some(1));
+        assert!(playlist.history.is_empty());
+    }
+
+    #[test]
+    fn repeat_one_stays_on_the_track() {
+        let mut playlist = playlist(3, PlaylistMode::RepeatOne);
+        assert_eq!(index(playlist.peek_advance()), None);
+        assert_eq!(index(playlist.advance()), Some(0));
+        assert_eq!(index(playlist.advance()), Some(0));
+        // skipping still moves
+        assert_eq!(index(playlist.skip_next()), Some(1));
+        assert_eq!(index(playlist.skip_previous()), Some(0));
+        assert_eq!(index(playlist.skip_previous()), Some(2));
+        assert!(playlist.history.is_empty());
+    }
+
+    #[test]
+    fn repeat_all_wraps_around() {
+        let mut playlist = playlist(3, PlaylistMode::RepeatAll);
+        assert_eq!(index(playlist.advance()), Some(1));
+        assert_eq!(index(playlist.advance()), Some(2));
+        assert_eq!(index(playlist.peek_advance()), Some(0));
+        assert_eq!(index(playlist.advance()), Some(0));
+        assert_eq!(index(playlist.skip_next()), Some(1));
+        assert_eq!(index(playlist.skip_previous()), Some(0));
+        assert_eq!(index(playlist.skip_previous()), Some(2));
+        assert!(playlist.history.is_empty());
+    }
+
+    #[test]
+    fn shuffle_goes_back_the_way_it_came() {
+        let mut playlist = playlist(5, PlaylistMode::Shuffle);
+        let mut played = vec![0];
+        for i in 0..20 {
+            let peeked = index(playlist.peek_advance());
+            let current = playlist.get_current_index();
+            let next = if i % 2 == 0 {
+                index(playlist.advance())
+            } else {
+                index(playlist.skip_next())
+            };
+            // moving on picks what preloading was told, never the same track
+            assert_eq!(next, peeked);
+            assert_ne!(next, Some(current));
+            played.push(next.unwrap());
+        }
+        played.pop();
+        while let Some(previous) = played.pop() {
+            assert_eq!(index(playlist.skip_previous()), Some(previous));
+        }
+        // without history it goes to the track before
+        assert_eq!(index(playlist.skip_previous()), Some(4));
+
+        // a single track has nothing to shuffle to
+        let mut single = self::playlist(1, PlaylistMode::Shuffle);
+        assert_eq!(index(single.peek_advance()), None);
+        assert_eq!(index(single.advance()), Some(0));
+    }
+
+    #[test]
+    fn changing_the_mode_forgets_the_history() {
+        let mut playlist = playlist(5, PlaylistMode::Shuffle);
+        playlist.advance();
+        playlist.advance();
+        let current = playlist.get_current_index();
+        playlist.set_mode(PlaylistMode::Order);
+        assert!(playlist.history.is_empty());
+        assert_eq!(index(playlist.skip_previous()), Some((current + 4) % 5));
+    }
+}
@@ -0,0 +1,367 @@
+use super::Track;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+enum Format {
+    M3u,
+    Pls,
+}
+
+impl Format {
+    fn of(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "m3u" | "m3u8" => Some(Format::M3u),
+            "pls" => Some(Format::Pls),
+            _ => None,
+        }
+    }
+}
+
+/// Whether `path` looks like a M3U, M3U8 or PLS playlist.
+pub fn is_playlist_file<P: AsRef<Path>>(path: P) -> bool {
+    Format::of(path.as_ref()).is_some()
+}
+
+/// Reads a M3U, M3U8 or PLS playlist, the relative paths in it are relative to the playlist file.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<Track>, String> {
+    let path = path.as_ref();
+    let format = Format::of(path).ok_or(format!("{:?} is not a playlist file", path))?;
+    let bytes = std::fs::read(path).map_err(|e| format!("error opening {:?}: {:?}", path, e))?;
+    // old M3U files are not always UTF-8
+    let text = String::from_utf8_lossy(&bytes);
+    let text = text.trim_start_matches('\u{feff}');
+    let base = folder_of(path);
+    let tracks = match format {
+        Format::M3u => parse_m3u(text, base),
+        Format::Pls => parse_pls(text, base),
+    };
+    log::info!("read {} tracks from {:?}", tracks.len(), path);
+    Ok(tracks)
+}
+
+/// Writes the tracks into a playlist, the format is chosen by the extension of `path`.
+pub fn save<P: AsRef<Path>>(path: P, tracks: &[Track]) -> Result<(), String> {
+    let path = path.as_ref();
+    let format =
+        Format::of(path).ok_or(format!("{:?} needs to end with .m3u, .m3u8 or .pls", path))?;
+    let base = folder_of(path);
+    let text = match format {
+        Format::M3u => write_m3u(tracks, base),
+        Format::Pls => write_pls(tracks, base),
+    };
+    std::fs::write(path, text).map_err(|e| format!("error writing {:?}: {:?}", path, e))
+}
+
+/// Music files stay as they are, playlist files are replaced by their tracks.
+pub fn expand(paths: &[String]) -> Result<Vec<Track>, String> {
+    let mut tracks = Vec::new();
+    for path in paths {
+        if !is_playlist_file(path) {
+            tracks.push(Track::new(path.clone()));
+            continue;
+        }
+        let loaded = crate::function::execute_or_relative_path(path)
+            .and_then(load)
+            .map_err(|e| format!("error reading playlist: {}", e))?;
+        tracks.extend(loaded);
+    }
+    Ok(tracks)
+}
+
+fn parse_m3u(text: &str, base: &Path) -> Vec<Track> {
+    let mut tracks = Vec::new();
+    // `#EXTINF:<seconds>,<title>` describes the path on the next line
+    let mut info = None;
+    for line in text.lines().map(str::trim) {
+        if let Some(extinf) = line.strip_prefix("#EXTINF:") {
+            let (duration, title) = extinf.split_once(',').unwrap_or((extinf, ""));
+            // the duration may be followed by attributes like `tvg-id="..."`
+            let duration = duration.split_whitespace().next().unwrap_or_default();
+            info = Some((parse_duration(duration), non_empty(title)));
+        } else if line.is_empty() || line.starts_with('#') {
+            continue;
+        } else if let Some(path) = resolve(base, line) {
+            let (duration, title) = info.take().unwrap_or_default();
+            tracks.push(Track {
+                path,
+                title,
+                duration,
+            });
+        } else {
+            info = None;
+        }
+    }
+    tracks
+}
+
+fn parse_pls(text: &str, base: &Path) -> Vec<Track> {
+    // the entries are numbered and may come in any order
+    let mut entries: BTreeMap<u32, Track> = BTreeMap::new();
+    for line in text.lines().map(str::trim) {
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim().to_ascii_lowercase(), value.trim()),
+            None => continue,
+        };
+        // `File12` is the key `file` of entry 12
+        let name = key.trim_end_matches(|c: char| c.is_ascii_digit());
+        let number: u32 = match key[name.len()..].parse() {
+            Ok(number) => number,
+            Err(_) => continue,
+        };
+        let entry = entries.entry(number).or_default();
+        match name {
+            "file" => entry.path = resolve(base, value).unwrap_or_default(),
+            "title" => entry.title = non_empty(value),
+            "length" => entry.duration = parse_duration(value),
+            _ => {}
+        }
+    }
+    entries
+        .into_values()
+        .filter(|track| !track.path.is_empty())
+        .collect()
+}
+
+fn write_m3u(tracks: &[Track], base: &Path) -> String {
+    let mut text = String::from("#EXTM3U\n");
+    for track in tracks {
+        let duration = track.duration.map(|d| d.round() as i64).unwrap_or(-1);
+        let title = track.title.clone().unwrap_or_else(|| title_of(&track.path));
+        text += &format!("#EXTINF:{},{}\n", duration, title);
+        text += &relative_to(base, &track.path);
+        text += "\n";
+    }
+    text
+}
+
+fn write_pls(tracks: &[Track], base: &Path) -> String {
+    let mut text = String::from("[playlist]\n");
+    for (i, track) in tracks.iter().enumerate() {
+        let number = i + 1;
+        let duration = track.duration.map(|d| d.round() as i64).unwrap_or(-1);
+        let title = track.title.clone().unwrap_or_else(|| title_of(&track.path));
+        text += &format!("File{}={}\n", number, relative_to(base, &track.path));
+        text += &format!("Title{}={}\n", number, title);
+        text += &format!("Length{}={}\n", number, duration);
+    }
+    text += &format!("NumberOfEntries={}\nVersion=2\n", tracks.len());
+    text
+}
+
+// -1 means unknown
+fn parse_duration(value: &str) -> Option<f32> {
+    value
+        .parse::<f32>()
+        .ok()
+        .filter(|d| d.is_finite() && *d >= 0.0)
+}
+
+fn non_empty(value: &str) -> Option<String> {
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+// relative paths are looked up next to the playlist first, like
+// `execute_or_relative_path` looks next to the executable first
+fn resolve(base: &Path, entry: &str) -> Option<String> {
+    let entry = entry.strip_prefix("file://").unwrap_or(entry);
+    if entry.contains("://") {
+        log::warn!("skipping {:?}, only local files can be played", entry);
+        return None;
+    }
+    let path = PathBuf::from(entry);
+    if path.is_relative() {
+        let joined = base.join(&path);
+        if joined.exists() {
+            return Some(joined.to_string_lossy().into_owned());
+        }
+    }
+    Some(entry.to_string())
+}
+
+// paths inside the folder of the playlist are written relative to it, others are absolute
+fn relative_to(base: &Path, path: &str) -> String {
+    let absolute = |path: &Path| std::fs::canonicalize(path).ok();
+    match (absolute(base), absolute(Path::new(path))) {
+        (Some(base), Some(path)) => match path.strip_prefix(&base) {
+            Ok(relative) => relative.to_string_lossy().into_owned(),
+            Err(_) => path.to_string_lossy().into_owned(),
+        },
+        _ => path.to_string(),
+    }
+}
+
+fn folder_of(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+fn title_of(path: &str) -> String {
+    Path::new(path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a folder with `a.ogg` and `sub/b.flac`, removed when dropped
+    struct Folder(PathBuf);
+
+    impl Folder {
+        fn new(name: &str) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("yee_player_{}_{}", name, std::process::id()));
+            std::fs::create_dir_all(path.join("sub")).unwrap();
+            std::fs::write(path.join("a.ogg"), b"").unwrap();
+            std::fs::write(path.join("sub").join("b.flac"), b"").unwrap();
+            Self(path)
+        }
+
+        fn file(&self, name: &str) -> String {
+            self.0.join(name).to_string_lossy().into_owned()
+        }
+    }
+
+    impl Drop for Folder {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn parse_m3u_with_extinf_and_relative_paths() {
+        let folder = Folder::new("m3u");
+        let text = "#EXTM3U\n\
+                    #EXTINF:123 tvg-id=\"x\",First Song\n\
+                    a.ogg\n\
+                    \n\
+                    # a comment\n\
+                    sub/b.flac\n\
+                    #EXTINF:-1,Stream\n\
+                    http://example.com/stream\n\
+                    /missing/c.mp3\n";
+        let tracks = parse_m3u(text, &folder.0);
+        assert_eq!(
+            tracks,
+            vec![
+                Track {
+                    path: folder.file("a.ogg"),
+                    title: Some("First Song".to_string()),
+                    duration: Some(123.0),
+                },
+                Track::new(folder.0.join("sub/b.flac").to_string_lossy().into_owned()),
+                // the info of the skipped stream is not given to the next path
+                Track::new("/missing/c.mp3".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_pls_in_any_order() {
+        let folder = Folder::new("pls");
+        let text = "[playlist]\n\
+                    Title2=Second\n\
+                    File2=sub/b.flac\n\
+                    File1=a.ogg\n\
+                    Length1=-1\n\
+                    length2 = 61.5\n\
+                    Title3=no file\n\
+                    NumberOfEntries=3\n\
+                    Version=2\n";
+        let tracks = parse_pls(text, &folder.0);
+        assert_eq!(
+            tracks,
+            vec![
+                Track::new(folder.file("a.ogg")),
+                Track {
+                    path: folder.0.join("sub/b.flac").to_string_lossy().into_owned(),
+                    title: Some("Second".to_string()),
+                    duration: Some(61.5),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn resolve_paths() {
+        let folder = Folder::new("resolve");
+        assert_eq!(resolve(&folder.0, "a.ogg"), Some(folder.file("a.ogg")));
+        // relative paths not next to the playlist are kept as they are
+        assert_eq!(resolve(&folder.0, "c.ogg"), Some("c.ogg".to_string()));
+        assert_eq!(
+            resolve(&folder.0, "file:///music/a.ogg"),
+            Some("/music/a.ogg".to_string())
+        );
+        assert_eq!(resolve(&folder.0, "https://example.com/a.ogg"), None);
+    }
+
+    #[test]
+    fn relative_to_the_playlist_folder() {
+        let folder = Folder::new("relative");
+        let base = std::fs::canonicalize(&folder.0).unwrap();
+        assert_eq!(relative_to(&folder.0, &folder.file("a.ogg")), "a.ogg");
+        assert_eq!(
+            relative_to(&folder.0, &folder.file("sub/b.flac")),
+            Path::new("sub").join("b.flac").to_string_lossy()
+        );
+        // outside of the folder it is absolute
+        assert_eq!(
+            relative_to(&folder.0.join("sub"), &folder.file("a.ogg")),
+            base.join("a.ogg").to_string_lossy()
+        );
+        // paths that are not there are kept as they are
+        assert_eq!(relative_to(&folder.0, "missing.ogg"), "missing.ogg");
+    }
+
+    #[test]
+    fn expand_playlists_or_give_the_error() {
+        let folder = Folder::new("expand");
+        let list = folder.file("list.m3u");
+        save(&list, &[Track::new(folder.file("sub/b.flac"))]).unwrap();
+        let tracks = expand(&[folder.file("a.ogg"), list]).unwrap();
+        assert_eq!(
+            tracks,
+            vec![
+                Track::new(folder.file("a.ogg")),
+                Track {
+                    title: Some("b".to_string()),
+                    ..Track::new(folder.file("sub/b.flac"))
+                },
+            ]
+        );
+        assert!(expand(&[folder.file("missing.pls")]).is_err());
+    }
+
+    #[test]
+    fn saved_playlists_read_back_the_same() {
+        let folder = Folder::new("round_trip");
+        let tracks = vec![
+            Track {
+                path: folder.file("a.ogg"),
+                title: Some("First".to_string()),
+                duration: Some(12.0),
+            },
+            Track {
+                path: folder.file("sub/b.flac"),
+                title: Some("b".to_string()),
+                duration: None,
+            },
+        ];
+        for name in ["list.m3u8", "list.pls"] {
+            let path = folder.0.join(name);
+            save(&path, &tracks).unwrap();
+            let text = std::fs::read_to_string(&path).unwrap();
+            assert!(text.contains("sub"), "{}", name);
+            assert!(!text.contains(&*folder.0.to_string_lossy()), "{}", name);
+            assert_eq!(load(&path).unwrap(), tracks, "{}", name);
+        }
+    }
+}
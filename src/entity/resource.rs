@@ -4,22 +4,40 @@ use std::path::PathBuf;
 pub struct Input {
     pub mouse_location: Option<(f32, f32)>,
     pub mouse_pressing: bool,
+    // lines scrolled up by the mouse wheel since they were last taken
+    pub mouse_wheel: f32,
     pub ctrl_pressing: bool,
     pub hover_file: bool,
-    pub drop_file: Option<PathBuf>,
+    // all the files dropped at once
+    pub drop_files: Vec<PathBuf>,
     pub exit: bool,
 }
 
 pub struct SettingPath(pub PathBuf);
 
+use super::playlist::PlaylistMode;
+use crate::buffer_player::{FadeCurve, Interpolation, LoadError, Output, RampCurve, SampleStorage};
 use serde::Deserialize;
 #[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(default)]
 pub struct Setting {
     pub music_path: String,
     pub window_width: f32,
     pub window_height: f32,
     pub max_speed: f32,
     pub min_speed: f32,
+    pub streaming: bool,
+    pub sample_storage: SampleStorage,
+    pub interpolation: Interpolation,
+    pub preserve_pitch: bool,
+    pub pitch: f32,
+    pub crossfade_ms: f32,
+    pub speed_ramp_time: f32,
+    pub speed_ramp_curve: RampCurve,
+    pub playlist_mode: PlaylistMode,
+    pub track_crossfade_secs: f32,
+    pub track_crossfade_curve: FadeCurve,
+    pub output: Output,
 }
 
 impl Default for Setting {
@@ -27,24 +45,30 @@ impl Default for Setting {
         Self {
             music_path: "./asset/music/example.ogg".to_string(),
             window_width: 512.0,
-            window_height: 512.0,
+            window_height: 640.0,
             max_speed: 2.0,
             min_speed: -2.0,
+            streaming: false,
+            sample_storage: SampleStorage::default(),
+            interpolation: Interpolation::default(),
+            preserve_pitch: false,
+            pitch: 0.0,
+            crossfade_ms: 10.0,
+            speed_ramp_time: 0.3,
+            speed_ramp_curve: RampCurve::default(),
+            playlist_mode: PlaylistMode::default(),
+            track_crossfade_secs: 0.0,
+            track_crossfade_curve: FadeCurve::default(),
+            output: Output::default(),
         }
     }
 }
 impl Setting {
-    pub fn load(path: &PathBuf) -> Result<Self, String> {
-        let string = std::fs::read_to_string(path).map_err(|e| {
-            let err = format!("error opening {:?}: {:?}", path, e);
-            log::error!("{}", err);
-            err
-        })?;
-        ron::de::from_str(string.as_str()).map_err(|e| {
-            let err = format!("error parsing {:?}: {:?}", path, e);
-            log::error!("{}", err);
-            err
-        })
+    pub fn load(path: &PathBuf) -> Result<Self, LoadError> {
+        let string =
+            std::fs::read_to_string(path).map_err(|e| LoadError::from_io(path.clone(), e))?;
+        ron::de::from_str(string.as_str())
+            .map_err(|e| LoadError::InvalidSetting(path.clone(), e.to_string()))
     }
 }
 
@@ -55,25 +79,53 @@ pub struct ButtonFunctions {
     pub play_fn: ButtonFn,
     pub loop_fn: ButtonFn,
     pub unloop_fn: ButtonFn,
+    pub stretch_fn: ButtonFn,
+    pub unstretch_fn: ButtonFn,
     pub load_fn: ButtonFn,
     pub stop_load_fn: ButtonFn,
+    pub export_fn: ButtonFn,
+    pub stop_export_fn: ButtonFn,
 }
 
 pub mod audio {
-    pub use crate::buffer_player::{AudioBufferLoader, AudioController};
+    pub use crate::buffer_player::{AudioBufferLoader, AudioController, WavExporter};
     use legion::Entity;
     pub struct AudioLoader {
-        pub loader: AudioBufferLoader<i16>,
+        pub loader: AudioBufferLoader<f32>,
         pub path: String,
         pub load_button_entity: Entity,
+        // the beginning of the audio is playing while the rest is loading
+        pub partial_loaded: bool,
     }
     pub type AudioLoaderRes = Option<AudioLoader>;
+    pub struct AudioExporter {
+        pub exporter: WavExporter,
+        pub export_button_entity: Entity,
+    }
+    pub type AudioExporterRes = Option<AudioExporter>;
 }
 pub struct PlayingSpeed(pub f32);
+// seconds to jump to once that much of the loading music is there
+pub struct StartTime(pub Option<f32>);
+// what the player reported since the last frame
+pub struct PlayerEvents(pub Vec<crate::buffer_player::PlayerEvent>);
 
 use legion::Entity;
 pub struct ControlledSliders {
     pub time_slider: Entity,
     pub speed_slider: Entity,
     pub volume_slider: Entity,
+    pub pitch_slider: Entity,
+}
+/// One of the two points of the A-B loop region.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoopMarker {
+    Start,
+    End,
+}
+/// The loop marker being dragged on the time slider.
+pub struct DraggingLoopMarker(pub Option<LoopMarker>);
+pub struct ControlledButtons {
+    pub stretch_button: Entity,
+    pub load_button: Entity,
 }
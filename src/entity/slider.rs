@@ -6,17 +6,27 @@ pub struct Slider {
     input_value: Option<f32>,
     // mapped value range
     value_range: Range<f32>,
+    // the real value snaps to multiples of it when ctrl is pressed
+    snap_step: f32,
 }
 
 impl Slider {
     pub fn new(value: f32, value_range: Range<f32>) -> Self {
-        let value = map_value(value, &value_range).min(1.0).max(0.0);
+        let value = map_value(value, &value_range).clamp(0.0, 1.0);
         Self {
             value,
             input_value: None,
             value_range,
+            snap_step: 0.05,
         }
     }
+    pub fn with_snap_step(mut self, snap_step: f32) -> Self {
+        self.snap_step = snap_step;
+        self
+    }
+    pub fn snap(&self, value: f32) -> f32 {
+        (value / self.snap_step).round() * self.snap_step
+    }
     pub fn take_input_value(&mut self) -> Option<f32> {
         self.input_value.take()
     }
@@ -30,7 +40,7 @@ impl Slider {
         self.value
     }
     pub fn set_value(&mut self, value: f32) {
-        self.value = self.map_value(value).min(1.0).max(0.0);
+        self.value = self.map_value(value).clamp(0.0, 1.0);
     }
     pub fn set_range(&mut self, value_range: Range<f32>) {
         self.value_range = value_range;
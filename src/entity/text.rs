@@ -0,0 +1,567 @@
+/// Width and height of a glyph in font pixels.
+pub const GLYPH_SIZE: [usize; 2] = [5, 7];
+// font pixels from the start of one glyph to the next
+const ADVANCE: usize = GLYPH_SIZE[0] + 1;
+
+// rows of the printable ascii characters from the top, the highest of the 5 bits is the left pixel
+const FONT: [[u8; 7]; 95] = [
+    // ' '
+    [
+        0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000,
+    ],
+    // '!'
+    [
+        0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00000, 0b00100,
+    ],
+    // '"'
+    [
+        0b01010, 0b01010, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000,
+    ],
+    // '#'
+    [
+        0b01010, 0b01010, 0b11111, 0b01010, 0b11111, 0b01010, 0b01010,
+    ],
+    // '$'
+    [
+        0b00100, 0b01111, 0b10100, 0b01110, 0b00101, 0b11110, 0b00100,
+    ],
+    // '%'
+    [
+        0b11000, 0b11001, 0b00010, 0b00100, 0b01000, 0b10011, 0b00011,
+    ],
+    // '&'
+    [
+        0b01100, 0b10010, 0b10100, 0b01000, 0b10101, 0b10010, 0b01101,
+    ],
+    // '\''
+    [
+        0b00100, 0b00100, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000,
+    ],
+    // '('
+    [
+        0b00010, 0b00100, 0b01000, 0b01000, 0b01000, 0b00100, 0b00010,
+    ],
+    // ')'
+    [
+        0b01000, 0b00100, 0b00010, 0b00010, 0b00010, 0b00100, 0b01000,
+    ],
+    // '*'
+    [
+        0b00000, 0b00100, 0b10101, 0b01110, 0b10101, 0b00100, 0b00000,
+    ],
+    // '+'
+    [
+        0b00000, 0b00100, 0b00100, 0b11111, 0b00100, 0b00100, 0b00000,
+    ],
+    // ','
+    [
+        0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b00100, 0b01000,
+    ],
+    // '-'
+    [
+        0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000,
+    ],
+    // '.'
+    [
+        0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100,
+    ],
+    // '/'
+    [
+        0b00000, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b00000,
+    ],
+    // '0'
+    [
+        0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110,
+    ],
+    // '1'
+    [
+        0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110,
+    ],
+    // '2'
+    [
+        0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111,
+    ],
+    // '3'
+    [
+        0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110,
+    ],
+    // '4'
+    [
+        0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010,
+    ],
+    // '5'
+    [
+        0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110,
+    ],
+    // '6'
+    [
+        0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110,
+    ],
+    // '7'
+    [
+        0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000,
+    ],
+    // '8'
+    [
+        0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110,
+    ],
+    // '9'
+    [
+        0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100,
+    ],
+    // ':'
+    [
+        0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b01100, 0b00000,
+    ],
+    // ';'
+    [
+        0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b00100, 0b01000,
+    ],
+    // '<'
+    [
+        0b00010, 0b00100, 0b01000, 0b10000, 0b01000, 0b00100, 0b00010,
+    ],
+    // '='
+    [
+        0b00000, 0b00000, 0b11111, 0b00000, 0b11111, 0b00000, 0b00000,
+    ],
+    // '>'
+    [
+        0b01000, 0b00100, 0b00010, 0b00001, 0b00010, 0b00100, 0b01000,
+    ],
+    // '?'
+    [
+        0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b00000, 0b00100,
+    ],
+    // '@'
+    [
+        0b01110, 0b10001, 0b00001, 0b01101, 0b10101, 0b10101, 0b01110,
+    ],
+    // 'A'
+    [
+        0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001,
+    ],
+    // 'B'
+    [
+        0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110,
+    ],
+    // 'C'
+    [
+        0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110,
+    ],
+    // 'D'
+    [
+        0b11100, 0b10010, 0b10001, 0b10001, 0b10001, 0b10010, 0b11100,
+    ],
+    // 'E'
+    [
+        0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111,
+    ],
+    // 'F'
+    [
+        0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000,
+    ],
+    // 'G'
+    [
+        0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111,
+    ],
+    // 'H'
+    [
+        0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001,
+    ],
+    // 'I'
+    [
+        0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110,
+    ],
+    // 'J'
+    [
+        0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100,
+    ],
+    // 'K'
+    [
+        0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001,
+    ],
+    // 'L'
+    [
+        0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111,
+    ],
+    // 'M'
+    [
+        0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001,
+    ],
+    // 'N'
+    [
+        0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001,
+    ],
+    // 'O'
+    [
+        0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110,
+    ],
+    // 'P'
+    [
+        0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000,
+    ],
+    // 'Q'
+    [
+        0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101,
+    ],
+    // 'R'
+    [
+        0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001,
+    ],
+    // 'S'
+    [
+        0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110,
+    ],
+    // 'T'
+    [
+        0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100,
+    ],
+    // 'U'
+    [
+        0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110,
+    ],
+    // 'V'
+    [
+        0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100,
+    ],
+    // 'W'
+    [
+        0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010,
+    ],
+    // 'X'
+    [
+        0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001,
+    ],
+    // 'Y'
+    [
+        0b10001, 0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100,
+    ],
+    // 'Z'
+    [
+        0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111,
+    ],
+    // '['
+    [
+        0b01110, 0b01000, 0b01000, 0b01000, 0b01000, 0b01000, 0b01110,
+    ],
+    // '\\'
+    [
+        0b00000, 0b10000, 0b01000, 0b00100, 0b00010, 0b00001, 0b00000,
+    ],
+    // ']'
+    [
+        0b01110, 0b00010, 0b00010, 0b00010, 0b00010, 0b00010, 0b01110,
+    ],
+    // '^'
+    [
+        0b00100, 0b01010, 0b10001, 0b00000, 0b00000, 0b00000, 0b00000,
+    ],
+    // '_'
+    [
+        0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b11111,
+    ],
+    // '`'
+    [
+        0b01000, 0b00100, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000,
+    ],
+    // 'a'
+    [
+        0b00000, 0b00000, 0b01110, 0b00001, 0b01111, 0b10001, 0b01111,
+    ],
+    // 'b'
+    [
+        0b10000, 0b10000, 0b10110, 0b11001, 0b10001, 0b10001, 0b11110,
+    ],
+    // 'c'
+    [
+        0b00000, 0b00000, 0b01110, 0b10000, 0b10000, 0b10001, 0b01110,
+    ],
+    // 'd'
+    [
+        0b00001, 0b00001, 0b01101, 0b10011, 0b10001, 0b10001, 0b01111,
+    ],
+    // 'e'
+    [
+        0b00000, 0b00000, 0b01110, 0b10001, 0b11111, 0b10000, 0b01110,
+    ],
+    // 'f'
+    [
+        0b00110, 0b01001, 0b01000, 0b11100, 0b01000, 0b01000, 0b01000,
+    ],
+    // 'g'
+    [
+        0b00000, 0b01111, 0b10001, 0b10001, 0b01111, 0b00001, 0b01110,
+    ],
+    // 'h'
+    [
+        0b10000, 0b10000, 0b10110, 0b11001, 0b10001, 0b10001, 0b10001,
+    ],
+    // 'i'
+    [
+        0b00100, 0b00000, 0b01100, 0b00100, 0b00100, 0b00100, 0b01110,
+    ],
+    // 'j'
+    [
+        0b00010, 0b00000, 0b00110, 0b00010, 0b00010, 0b10010, 0b01100,
+    ],
+    // 'k'
+    [
+        0b10000, 0b10000, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010,
+    ],
+    // 'l'
+    [
+        0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110,
+    ],
+    // 'm'
+    [
+        0b00000, 0b00000, 0b11010, 0b10101, 0b10101, 0b10001, 0b10001,
+    ],
+    // 'n'
+    [
+        0b00000, 0b00000, 0b10110, 0b11001, 0b10001, 0b10001, 0b10001,
+    ],
+    // 'o'
+    [
+        0b00000, 0b00000, 0b01110, 0b10001, 0b10001, 0b10001, 0b01110,
+    ],
+    // 'p'
+    [
+        0b00000, 0b00000, 0b11110, 0b10001, 0b11110, 0b10000, 0b10000,
+    ],
+    // 'q'
+    [
+        0b00000, 0b00000, 0b01101, 0b10011, 0b01111, 0b00001, 0b00001,
+    ],
+    // 'r'
+    [
+        0b00000, 0b00000, 0b10110, 0b11001, 0b10000, 0b10000, 0b10000,
+    ],
+    // 's'
+    [
+        0b00000, 0b00000, 0b01110, 0b10000, 0b01110, 0b00001, 0b11110,
+    ],
+    // 't'
+    [
+        0b01000, 0b01000, 0b11100, 0b01000, 0b01000, 0b01001, 0b00110,
+    ],
+    // 'u'
+    [
+        0b00000, 0b00000, 0b10001, 0b10001, 0b10001, 0b10011, 0b01101,
+    ],
+    // 'v'
+    [
+        0b00000, 0b00000, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100,
+    ],
+    // 'w'
+    [
+        0b00000, 0b00000, 0b10001, 0b10001, 0b10101, 0b10101, 0b01010,
+    ],
+    // 'x'
+    [
+        0b00000, 0b00000, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001,
+    ],
+    // 'y'
+    [
+        0b00000, 0b00000, 0b10001, 0b10001, 0b01111, 0b00001, 0b01110,
+    ],
+    // 'z'
+    [
+        0b00000, 0b00000, 0b11111, 0b00010, 0b00100, 0b01000, 0b11111,
+    ],
+    // '{'
+    [
+        0b00010, 0b00100, 0b00100, 0b01000, 0b00100, 0b00100, 0b00010,
+    ],
+    // '|'
+    [
+        0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100,
+    ],
+    // '}'
+    [
+        0b01000, 0b00100, 0b00100, 0b00010, 0b00100, 0b00100, 0b01000,
+    ],
+    // '~'
+    [
+        0b00000, 0b00000, 0b01000, 0b10101, 0b00010, 0b00000, 0b00000,
+    ],
+];
+
+// characters other than printable ascii are drawn as `?`
+fn glyph_index(c: char) -> usize {
+    match c {
+        ' '..='~' => c as usize - ' ' as usize,
+        _ => '?' as usize - ' ' as usize,
+    }
+}
+
+/// How many characters fit into `width` with font pixels `pixel_width` wide.
+pub fn chars_fitting(width: f32, pixel_width: f32) -> usize {
+    ((width / pixel_width + 1.0) / ADVANCE as f32) as usize
+}
+
+/// Size of the atlas texture of the font in atlas pixels, see `atlas`.
+pub const ATLAS_SIZE: [usize; 2] = [
+    ATLAS_COLUMNS * CELL_SIZE,
+    FONT.len().div_ceil(ATLAS_COLUMNS) * CELL_SIZE,
+];
+// glyphs in a row of the atlas
+const ATLAS_COLUMNS: usize = 16;
+// each glyph is at the top left of a cell, the blank rest keeps the neighbours from bleeding in
+const CELL_SIZE: usize = 8;
+
+/// The font as one byte for each atlas pixel, rows from the top, 255 where it is lit.
+pub fn atlas() -> Vec<u8> {
+    let mut pixels = vec![0; ATLAS_SIZE[0] * ATLAS_SIZE[1]];
+    for (index, rows) in FONT.iter().enumerate() {
+        let [left, top] = cell_location(index);
+        for (row, bits) in rows.iter().enumerate() {
+            for column in 0..GLYPH_SIZE[0] {
+                if bits & (1 << (GLYPH_SIZE[0] - 1 - column)) != 0 {
+                    pixels[(top + row) * ATLAS_SIZE[0] + left + column] = 255;
+                }
+            }
+        }
+    }
+    pixels
+}
+
+/// Top left corner of the glyph of `c` in the atlas, in atlas pixels.
+pub fn atlas_location(c: char) -> [usize; 2] {
+    cell_location(glyph_index(c))
+}
+
+fn cell_location(index: usize) -> [usize; 2] {
+    [
+        index % ATLAS_COLUMNS * CELL_SIZE,
+        index / ATLAS_COLUMNS * CELL_SIZE,
+    ]
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Center,
+}
+
+/// A line of text drawn over the quads with the bundled font.
+#[derive(Debug, Clone)]
+pub struct Text {
+    pub text: String,
+    /// Bottom left corner of the text, or the middle of its bottom edge when centered.
+    pub location: [f32; 2],
+    /// Height of the characters. The width follows from the window size, so font pixels stay square.
+    pub size: f32,
+    pub color: [f32; 3],
+    pub align: Align,
+}
+
+impl Text {
+    pub fn new<T: Into<String>>(text: T, location: [f32; 2], size: f32, color: [f32; 3]) -> Self {
+        Self {
+            text: text.into(),
+            location,
+            size,
+            color,
+            align: Align::Left,
+        }
+    }
+
+    /// Centered in the box at `location` with `size`.
+    pub fn centered_in<T: Into<String>>(
+        text: T,
+        location: [f32; 2],
+        size: [f32; 2],
+        text_size: f32,
+        color: [f32; 3],
+    ) -> Self {
+        Self {
+            text: text.into(),
+            location: [
+                location[0] + size[0] * 0.5,
+                location[1] + (size[1] - text_size) * 0.5,
+            ],
+            size: text_size,
+            color,
+            align: Align::Center,
+        }
+    }
+
+    /// Bottom left corners of the characters, a font pixel being `pixel` large.
+    pub fn layout(&self, pixel: [f32; 2]) -> impl Iterator<Item = (char, [f32; 2])> + '_ {
+        let advance = ADVANCE as f32 * pixel[0];
+        let left = match self.align {
+            Align::Left => self.location[0],
+            Align::Center => {
+                let count = self.text.chars().count() as f32;
+                self.location[0] - (count * advance - pixel[0]) * 0.5
+            }
+        };
+        let bottom = self.location[1];
+        self.text
+            .chars()
+            .enumerate()
+            .map(move |(i, c)| (c, [left + i as f32 * advance, bottom]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn other_characters_fall_back_to_question_mark() {
+        assert_eq!(glyph_index(' '), 0);
+        assert_eq!(glyph_index('A'), 33);
+        assert_eq!(glyph_index('~'), FONT.len() - 1);
+        for c in ['é', '中', '\n', '\u{7f}'] {
+            assert_eq!(glyph_index(c), glyph_index('?'), "{:?}", c);
+            assert_eq!(atlas_location(c), atlas_location('?'), "{:?}", c);
+        }
+    }
+
+    #[test]
+    fn chars_fitting_leave_out_the_last_gap() {
+        // a glyph is 5 font pixels wide with 1 between glyphs
+        assert_eq!(chars_fitting(0.0, 1.0), 0);
+        assert_eq!(chars_fitting(4.0, 1.0), 0);
+        assert_eq!(chars_fitting(5.0, 1.0), 1);
+        assert_eq!(chars_fitting(10.5, 1.0), 1);
+        assert_eq!(chars_fitting(11.0, 1.0), 2);
+        assert_eq!(chars_fitting(22.0, 2.0), 2);
+    }
+
+    #[test]
+    fn layout_left_and_centered() {
+        let text = Text::new("ab", [1.0, 2.0], 0.1, [1.0; 3]);
+        let layout: Vec<_> = text.layout([1.0, 1.0]).collect();
+        assert_eq!(layout, [('a', [1.0, 2.0]), ('b', [7.0, 2.0])]);
+
+        // 11 font pixels wide from the first lit column to the last
+        let text = Text::centered_in("ab", [-2.0, 0.0], [4.0, 1.0], 0.5, [1.0; 3]);
+        assert_eq!(text.location, [0.0, 0.25]);
+        let layout: Vec<_> = text.layout([1.0, 1.0]).collect();
+        assert_eq!(layout, [('a', [-5.5, 0.25]), ('b', [0.5, 0.25])]);
+    }
+
+    #[test]
+    fn atlas_cells_hold_their_glyphs() {
+        assert_eq!(ATLAS_SIZE, [128, 48]);
+        let atlas = atlas();
+        assert_eq!(atlas.len(), ATLAS_SIZE[0] * ATLAS_SIZE[1]);
+        for c in ' '..='~' {
+            let [left, top] = atlas_location(c);
+            let rows = FONT[glyph_index(c)];
+            for row in 0..CELL_SIZE {
+                for column in 0..CELL_SIZE {
+                    let lit = row < GLYPH_SIZE[1]
+                        && column < GLYPH_SIZE[0]
+                        && rows[row] & (1 << (GLYPH_SIZE[0] - 1 - column)) != 0;
+                    let pixel = atlas[(top + row) * ATLAS_SIZE[0] + left + column];
+                    assert_eq!(pixel, if lit { 255 } else { 0 }, "{:?}", c);
+                }
+            }
+        }
+        assert_eq!(atlas_location('0'), [0, 8]);
+    }
+}
@@ -0,0 +1,253 @@
+use crate::buffer_player::{peaks::BLOCK_FRAMES, FrameBuffer, Peak};
+use std::ops::Range;
+
+// seconds across the view, from a few samples to a minute
+const MIN_SPAN: f32 = 0.002;
+const MAX_SPAN: f32 = 60.0;
+// the span shrinks by this for each line the wheel scrolls up
+const ZOOM_STEP: f32 = 1.25;
+// moving less than this part of the view between pressing and releasing is a click
+const CLICK_DISTANCE: f32 = 0.01;
+// frames read for a column zoomed out on a buffer without peaks, in short chunks
+// spread across the column, so peaks between them can be missed
+const MAX_COLUMN_FRAMES: usize = BLOCK_FRAMES;
+const COLUMN_CHUNKS: usize = 16;
+
+/// A zoomable view of the waveform around the playhead, which stays in the middle while
+/// playing. Dragging scrolls the view, clicking seeks to where it is clicked.
+pub struct WaveformView {
+    // seconds across the view
+    span: f32,
+    // seconds from the playhead to the middle of the view
+    offset: f32,
+    // where dragging started in 0.0 .. 1.0 of the width, and the offset then
+    drag_start: Option<(f32, f32)>,
+    dragged: bool,
+    seek_time: Option<f32>,
+}
+
+impl WaveformView {
+    pub fn new(span: f32) -> Self {
+        Self {
+            span: span.clamp(MIN_SPAN, MAX_SPAN),
+            offset: 0.0,
+            drag_start: None,
+            dragged: false,
+            seek_time: None,
+        }
+    }
+
+    pub fn get_span(&self) -> f32 {
+        self.span
+    }
+
+    /// Zooms in by `lines` scrolled by the wheel, out when they are negative.
+    pub fn zoom(&mut self, lines: f32) {
+        self.span = (self.span / ZOOM_STEP.powf(lines)).clamp(MIN_SPAN, MAX_SPAN);
+    }
+
+    /// The seconds in the view with the playhead at `time`.
+    pub fn get_visible(&self, time: f32) -> Range<f32> {
+        let middle = time + self.offset;
+        middle - self.span * 0.5..middle + self.span * 0.5
+    }
+
+    /// Starts dragging at `x`, from 0.0 on the left to 1.0 on the right.
+    pub fn press(&mut self, x: f32) {
+        self.drag_start = Some((x, self.offset));
+        self.dragged = false;
+    }
+
+    pub fn drag(&mut self, x: f32) {
+        if let Some((start_x, start_offset)) = self.drag_start {
+            if (x - start_x).abs() > CLICK_DISTANCE {
+                self.dragged = true;
+            }
+            if self.dragged {
+                self.offset = start_offset - (x - start_x) * self.span;
+            }
+        }
+    }
+
+    /// Stops dragging, seeking if it was a click. The playhead is at `time`.
+    pub fn release(&mut self, time: f32) {
+        if let (Some((x, _)), false) = (self.drag_start.take(), self.dragged) {
+            let visible = self.get_visible(time);
+            self.seek_time = Some(visible.start + x * self.span);
+            self.offset = 0.0;
+        }
+    }
+
+    /// Stops dragging without seeking, when the mouse leaves the view.
+    pub fn cancel(&mut self) {
+        self.drag_start = None;
+    }
+
+    /// Puts the playhead back in the middle unless the view is being dragged,
+    /// called while playing.
+    pub fn follow(&mut self) {
+        if self.drag_start.is_none() {
+            self.offset = 0.0;
+        }
+    }
+
+    /// Returns the time clicked since the last call.
+    pub fn take_seek_time(&mut self) -> Option<f32> {
+        self.seek_time.take()
+    }
+}
+
+/// The peaks of `columns` columns splitting `seconds` of `buffer`, `None` where there is
+/// no audio. Below a frame for each column a column shows the frame it starts in, up to
+/// the next frame when that starts in it too, so the samples are joined up.
+/// Columns longer than `MAX_COLUMN_FRAMES` of a buffer without peaks only read
+/// a sample of their frames.
+pub fn get_columns(
+    buffer: &dyn FrameBuffer<f32>,
+    seconds: Range<f32>,
+    columns: usize,
+) -> Vec<Option<Peak>> {
+    let sample_rate = buffer.sample_rate() as f64;
+    let start = seconds.start as f64 * sample_rate;
+    let column_frames = (seconds.end - seconds.start) as f64 * sample_rate / columns as f64;
+    if let Some(peaks) = buffer.peaks() {
+        if column_frames >= BLOCK_FRAMES as f64 {
+            return peaks.get_columns_from(start, column_frames, columns);
+        }
+    }
+    let channels = buffer.channels() as usize;
+    let frame_count = buffer.frame_count();
+    let mut samples = Vec::new();
+    (0..columns)
+        .map(|column| {
+            let column_start = start + column as f64 * column_frames;
+            let column_end = (column_start + column_frames).floor() + 1.0;
+            if column_end <= 0.0 {
+                return None;
+            }
+            let first = column_start.max(0.0) as usize;
+            let end = (column_end as usize).min(frame_count);
+            if first >= end {
+                return None;
+            }
+            let len = end - first;
+            let (chunks, chunk_len) = if len > MAX_COLUMN_FRAMES {
+                (COLUMN_CHUNKS, MAX_COLUMN_FRAMES / COLUMN_CHUNKS)
+            } else {
+                (1, len)
+            };
+            // the samples that are not decoded are left as they are
+            samples.clear();
+            samples.resize(chunks * chunk_len * channels, f32::NAN);
+            for (chunk, chunk_samples) in samples.chunks_mut(chunk_len * channels).enumerate() {
+                let chunk_start = first + (len - chunk_len) * chunk / (chunks - 1).max(1);
+                buffer.read_frames(chunk_start, chunk_samples);
+            }
+            Peak::of(samples.iter().copied().filter(|sample| !sample.is_nan()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer_player::SamplesBuffer;
+
+    #[test]
+    fn columns_at_sample_level() {
+        let samples: Vec<f32> = (0..10).map(|i| i as f32 / 10.0).collect();
+        let buffer = SamplesBuffer::new(1, 10, samples.clone());
+        let peak = |first: usize, last: usize| {
+            Some(Peak {
+                min: samples[first],
+                max: samples[last],
+            })
+        };
+
+        // a frame for each column, from half a second before the start to after the end
+        let columns = get_columns(&buffer, -0.5..1.5, 20);
+        assert!(columns[..4].iter().all(Option::is_none));
+        assert_eq!(columns[5], peak(0, 1));
+        assert_eq!(columns[14], peak(9, 9));
+        assert!(columns[15..].iter().all(Option::is_none));
+
+        // four columns for each frame, the last of them joins the frame to the next
+        let columns = get_columns(&buffer, 0.0..0.4, 16);
+        assert_eq!(columns[0], peak(0, 0));
+        assert_eq!(columns[3], peak(0, 1));
+        assert_eq!(columns[4], peak(1, 1));
+    }
+
+    #[test]
+    fn long_columns_without_peaks_are_sampled_across() {
+        let frames = MAX_COLUMN_FRAMES * 100;
+        let samples: Vec<f32> = (0..frames).map(|i| i as f32 / frames as f32).collect();
+        let buffer = SamplesBuffer::new(1, 1000, samples);
+        let seconds = frames as f32 / 1000.0;
+        let columns = get_columns(&buffer, 0.0..seconds, 1);
+        let peak = columns[0].unwrap();
+        assert_eq!(peak.min, 0.0);
+        // the end of the column is read as well as its start
+        assert!(peak.max > 0.99, "{}", peak.max);
+    }
+
+    #[test]
+    fn span_is_clamped() {
+        assert_eq!(WaveformView::new(0.0).get_span(), MIN_SPAN);
+        assert_eq!(WaveformView::new(1000.0).get_span(), MAX_SPAN);
+
+        let mut view = WaveformView::new(2.5);
+        view.zoom(1.0);
+        assert_eq!(view.get_span(), 2.0);
+        view.zoom(-1.0);
+        assert_eq!(view.get_span(), 2.5);
+        view.zoom(100.0);
+        assert_eq!(view.get_span(), MIN_SPAN);
+        view.zoom(-100.0);
+        assert_eq!(view.get_span(), MAX_SPAN);
+    }
+
+    #[test]
+    fn dragging_scrolls_without_seeking() {
+        let mut view = WaveformView::new(4.0);
+        assert_eq!(view.get_visible(10.0), 8.0..12.0);
+
+        view.press(0.5);
+        // a little shake is still a click
+        view.drag(0.505);
+        assert_eq!(view.get_visible(10.0), 8.0..12.0);
+        // dragging right shows what is before
+        view.drag(0.75);
+        assert_eq!(view.get_visible(10.0), 7.0..11.0);
+        // the playhead is not followed while dragging
+        view.follow();
+        assert_eq!(view.get_visible(10.0), 7.0..11.0);
+        view.release(10.0);
+        assert_eq!(view.take_seek_time(), None);
+        assert_eq!(view.get_visible(10.0), 7.0..11.0);
+
+        view.follow();
+        assert_eq!(view.get_visible(10.0), 8.0..12.0);
+    }
+
+    #[test]
+    fn clicking_seeks_in_the_scrolled_view() {
+        let mut view = WaveformView::new(4.0);
+        view.press(0.5);
+        view.drag(0.75);
+        view.release(10.0);
+
+        // the view shows 7.0 .. 11.0 now
+        view.press(0.25);
+        view.release(10.0);
+        assert_eq!(view.take_seek_time(), Some(8.0));
+        assert_eq!(view.take_seek_time(), None);
+        assert_eq!(view.get_visible(8.0), 6.0..10.0);
+
+        // leaving the view does not seek
+        view.press(0.25);
+        view.cancel();
+        view.release(10.0);
+        assert_eq!(view.take_seek_time(), None);
+    }
+}
@@ -0,0 +1,329 @@
+use crate::{
+    buffer_player::{
+        AudioController, ExportOptions, LoadError, PlayerEvent, SamplesBuffer, SharedBuffer,
+        WavExporter,
+    },
+    cli::CommandLine,
+    entity::{
+        playlist::{self, Playlist, Track},
+        resource::Setting,
+    },
+    function::{execute_or_relative_path, start_loading},
+};
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+const POLL_GAP: Duration = Duration::from_millis(100);
+
+/// Plays or exports the music files from the command line without winit or wgpu.
+pub fn run(command_line: &CommandLine, setting: &Setting) -> Result<(), String> {
+    let files = match command_line.files.is_empty() {
+        true => vec![setting.music_path.clone()],
+        false => command_line.files.clone(),
+    };
+    let tracks = playlist::file::expand(&files)?;
+    if let Some(path) = &command_line.save_playlist {
+        playlist::file::save(path, &tracks)?;
+        log::info!("saved {} tracks to {:?}", tracks.len(), path);
+        return Ok(());
+    }
+    let first = tracks.first().ok_or("no music file to play")?;
+    match &command_line.export {
+        // a playlist file can hold more than one
+        Some(_) if tracks.len() > 1 => Err(format!(
+            "--export takes only one music file, got {} tracks",
+            tracks.len()
+        )),
+        Some(target) => export(
+            command_line,
+            execute_or_relative_path(&first.path)?,
+            target.clone(),
+            setting,
+        ),
+        None => play(command_line, tracks, setting),
+    }
+}
+
+fn export(
+    command_line: &CommandLine,
+    source: PathBuf,
+    target: PathBuf,
+    setting: &Setting,
+) -> Result<(), String> {
+    let speed = command_line.signed_speed().unwrap_or(1.0);
+    // from `--start` to the end, or back to the beginning when reversed
+    let region = command_line.start.map(|start| match speed < 0.0 {
+        true => 0.0..start,
+        false => start..f32::MAX,
+    });
+    let options = ExportOptions {
+        speed,
+        volume: command_line.volume.unwrap_or(1.0),
+        loop_region: region,
+        interpolation: setting.interpolation,
+        time_stretch: setting.preserve_pitch,
+        pitch: setting.pitch,
+        crossfade_time: setting.crossfade_ms / 1000.0,
+    };
+
+    log::info!("exporting {:?} to {:?}", source, target);
+    let mut exporter = WavExporter::export(source, target, options);
+    let mut reported = 0;
+    loop {
+        if let Some(result) = exporter.try_get_value() {
+            return result
+                .map(|path| log::info!("exported to {:?}", path))
+                .map_err(String::from);
+        }
+        let percent = (exporter.get_progress() * 100.0) as u32 / 10 * 10;
+        if percent > reported {
+            log::info!("exporting {}%", percent);
+            reported = percent;
+        }
+        std::thread::sleep(POLL_GAP);
+    }
+}
+
+// the files play one after another as the playlist mode in the setting says
+fn play(command_line: &CommandLine, tracks: Vec<Track>, setting: &Setting) -> Result<(), String> {
+    let empty_buffer: SharedBuffer<f32> = Arc::new(SamplesBuffer::new(1, 48000, Vec::<f32>::new()));
+    let mut controller = AudioController::new_with_buffer(
+        command_line.output.as_ref().unwrap_or(&setting.output),
+        empty_buffer,
+    );
+    controller.set_volume(command_line.volume.unwrap_or(1.0));
+    controller.set_interpolation(setting.interpolation);
+    controller.set_time_stretch(setting.preserve_pitch);
+    controller.set_pitch(setting.pitch);
+    controller.set_crossfade_time(setting.crossfade_ms / 1000.0);
+    controller.set_track_fade(
+        setting.track_crossfade_secs.max(0.0),
+        setting.track_crossfade_curve,
+    );
+    controller.set_loop_mode(command_line.loop_mode);
+
+    let mut playlist = Playlist::new(tracks, setting.playlist_mode);
+    let mut path = playlist.get_current().map(str::to_string);
+    // not when playing already went on into the preloaded track
+    let mut load = true;
+    // tracks that failed to load one after another, all of them failing stops playing
+    let mut failed = 0;
+    while let Some(file) = path {
+        if load {
+            let played = execute_or_relative_path(&file)
+                .and_then(|source| play_file(command_line, source, &mut controller, setting));
+            if let Err(e) = played {
+                log::error!("skipping {:?}: {}", file, e);
+                failed += 1;
+                if failed >= playlist.get_tracks().len() {
+                    return Err("no track could be loaded".to_string());
+                }
+                path = playlist.advance().map(str::to_string);
+                continue;
+            }
+            failed = 0;
+        }
+        load = !wait_for_end(&mut controller, &mut playlist, setting);
+        path = playlist.advance().map(str::to_string);
+    }
+    Ok(())
+}
+
+fn play_file(
+    command_line: &CommandLine,
+    source: PathBuf,
+    controller: &mut AudioController<f32>,
+    setting: &Setting,
+) -> Result<(), String> {
+    controller.set_speed(0.0);
+    log::info!("loading {:?}", source);
+    let mut loader = start_loading(source, setting);
+    let speed = command_line.signed_speed().unwrap_or(1.0);
+    // reversed playback starts from the end and `--start` may be past
+    // what is loaded, both are only known once loading is done
+    let start_time = |duration: f32, done: bool| match (speed < 0.0, command_line.start) {
+        (true, start) if done => Some(start.unwrap_or(duration).min(duration)),
+        (false, Some(start)) if start < duration || done => Some(start.min(duration)),
+        (false, None) => Some(0.0),
+        _ => None,
+    };
+    let start = |controller: &mut AudioController<f32>, time: f32| {
+        controller.change_time(time);
+        controller.set_speed(speed);
+    };
+    let mut partial = None;
+    let mut started = false;
+    loop {
+        if let Some(buffer) = loader.try_get_partial() {
+            controller.set_target_buffer(Arc::clone(&buffer));
+            partial = Some(buffer);
+        }
+        if let Some(value) = loader.try_get_value() {
+            let buffer = match (value, &partial) {
+                (Ok(buffer), _) => buffer,
+                // what was decoded before the corrupt part still plays
+                (Err(e @ LoadError::CorruptStream { .. }), Some(buffer)) => {
+                    log::error!("error loading audio: {}", e);
+                    Arc::clone(buffer)
+                }
+                (Err(e), _) => return Err(format!("error loading audio: {}", e)),
+            };
+            log::info!(
+                "load success, audio length: {}s",
+                buffer.get_duration().as_secs_f32()
+            );
+            // the partial buffer grows into the same buffer
+            if !Arc::ptr_eq(controller.get_target_buffer(), &buffer) {
+                controller.set_target_buffer(Arc::clone(&buffer));
+            }
+            if !started {
+                if let Some(time) = start_time(buffer.get_duration().as_secs_f32(), true) {
+                    start(controller, time);
+                }
+            }
+            break;
+        }
+        if let (false, Some(buffer)) = (started, &partial) {
+            if let Some(time) = start_time(buffer.get_duration().as_secs_f32(), false) {
+                start(controller, time);
+                started = true;
+            }
+        }
+        std::thread::sleep(POLL_GAP);
+    }
+    Ok(())
+}
+
+// preloads the track after the current one, returns true when playing went on into it
+// without a gap, and false when playing stopped at the end
+fn wait_for_end(
+    controller: &mut AudioController<f32>,
+    playlist: &mut Playlist,
+    setting: &Setting,
+) -> bool {
+    match playlist.peek_advance().map(str::to_string) {
+        Some(path) => match execute_or_relative_path(&path) {
+            Ok(source) => {
+                log::info!("preloading {:?}", source);
+                controller.preload_next(path, start_loading(source, setting));
+            }
+            // it is skipped when it is loaded again after the current track
+            Err(e) => {
+                log::error!("error preloading {:?}: {}", path, e);
+                controller.clear_next();
+            }
+        },
+        None => controller.clear_next(),
+    }
+
+    // looping plays until the process is stopped
+    let mut ended = false;
+    loop {
+        std::thread::sleep(POLL_GAP);
+        controller.check_output();
+        controller.update_next();
+        if let Some(e) = controller.take_buffer_error() {
+            log::error!("error decoding audio: {}", e);
+        }
+        if let Some(path) = controller.take_started_next() {
+            log::info!("playing {}", path);
+            return true;
+        }
+        for event in controller.take_events() {
+            match event {
+                PlayerEvent::TrackEnded => ended = true,
+                PlayerEvent::Underrun => log::warn!("playing faster than decoding"),
+                _ => {}
+            }
+        }
+        // the preloaded track still goes on after a gap once it is loaded
+        if ended && !controller.has_next() {
+            log::info!("playback finished");
+            return false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer_player::Output;
+
+    const EXAMPLE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/asset/music/example.ogg");
+
+    fn temp_path(name: &str, extension: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "yee_player_{}_{}.{}",
+            name,
+            std::process::id(),
+            extension
+        ))
+    }
+
+    #[test]
+    fn save_playlist() {
+        let target = temp_path("headless_list", "m3u8");
+        let command_line = CommandLine {
+            files: vec![EXAMPLE.to_string()],
+            save_playlist: Some(target.clone()),
+            ..CommandLine::default()
+        };
+        run(&command_line, &Setting::default()).unwrap();
+        let tracks = playlist::file::load(&target).unwrap();
+        std::fs::remove_file(target).unwrap();
+        assert_eq!(tracks.len(), 1);
+        assert!(tracks[0].path.ends_with("example.ogg"));
+    }
+
+    #[test]
+    fn export_at_double_speed() {
+        let target = temp_path("headless_export", "wav");
+        let export = |speed| {
+            let command_line = CommandLine {
+                files: vec![EXAMPLE.to_string()],
+                speed: Some(speed),
+                export: Some(target.clone()),
+                ..CommandLine::default()
+            };
+            run(&command_line, &Setting::default()).unwrap();
+            hound::WavReader::open(&target).unwrap().duration()
+        };
+        let normal = export(1.0);
+        let double = export(2.0);
+        std::fs::remove_file(&target).unwrap();
+        assert!(normal > 0);
+        assert_eq!(double, normal / 2);
+    }
+
+    #[test]
+    fn tracks_that_fail_to_load_are_skipped() {
+        let short = temp_path("headless_short", "wav");
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 1000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&short, spec).unwrap();
+        for _ in 0..100 {
+            writer.write_sample(0i16).unwrap();
+        }
+        writer.finalize().unwrap();
+        let missing = temp_path("headless_missing", "wav");
+
+        let mut command_line = CommandLine {
+            files: vec![
+                missing.to_string_lossy().into_owned(),
+                short.to_string_lossy().into_owned(),
+            ],
+            output: Some(Output::Null),
+            ..CommandLine::default()
+        };
+        let played = run(&command_line, &Setting::default());
+        // nothing to play at all is still an error
+        command_line.files.pop();
+        let failed = run(&command_line, &Setting::default());
+        std::fs::remove_file(short).unwrap();
+        played.unwrap();
+        assert!(failed.is_err());
+    }
+}
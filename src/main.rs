@@ -5,18 +5,25 @@ use std::{path::PathBuf, sync::Arc};
 pub mod entity;
 use entity::{
     button::{ButtonColors, ButtonResponse, ButtonState, StateButton},
+    notification::{Level, Notifications},
+    playlist::{self, Playlist, Track},
     render::Transform,
     resource::{
-        audio::{AudioController, AudioLoader, AudioLoaderRes},
-        ButtonFunctions, ControlledSliders, Input, MusicFileMetaData, PlayingSpeed, Setting,
-        SettingPath,
+        audio::{
+            AudioController, AudioExporter, AudioExporterRes, AudioLoader, AudioLoaderRes,
+            WavExporter,
+        },
+        ButtonFunctions, ControlledButtons, ControlledSliders, DraggingLoopMarker, Input,
+        LoopMarker, MusicFileMetaData, PlayerEvents, PlayingSpeed, Setting, SettingPath, StartTime,
     },
     slider::{Slider, SliderColors},
+    text::{self, Text, GLYPH_SIZE},
+    waveform::{self, WaveformView},
     ButtonFn, TargetValue,
 };
 
 pub mod buffer_player;
-use buffer_player::{AudioBufferLoader, SamplesBuffer};
+use buffer_player::{AudioBufferLoader, LoadError, Peak, PlayerEvent, SamplesBuffer, SharedBuffer};
 
 pub mod renderer;
 use renderer::{PiplineSetting, Renderer};
@@ -24,13 +31,43 @@ use renderer::{PiplineSetting, Renderer};
 mod icon;
 use icon::create_icon_data;
 
+mod cli;
+use cli::Command;
+
+mod headless;
+
 use legion::{
     query::{IntoQuery, Read, Write},
     world::EntityStore,
     Entity, Resources, Schedule, SystemBuilder, World,
 };
 // MARK: consts
-const FRAME_GAP: std::time::Duration = std::time::Duration::from_nanos(0_016_666_667);
+const FRAME_GAP: std::time::Duration = std::time::Duration::from_nanos(16_666_667);
+// eight buttons on the top row
+const TOP_BUTTON_WIDTH: f32 = 2.0 / 8.0;
+const TOP_BUTTON_HEIGHT: f32 = 0.4;
+const TOP_BUTTON_BOTTOM: f32 = 1.0 - TOP_BUTTON_HEIGHT;
+// range of the pitch slider in semitones
+const MAX_PITCH: f32 = 12.0;
+// longer crossfades would smear the loop points
+const MAX_CROSSFADE_MS: f32 = 50.0;
+const LOOP_MARKER_WIDTH: f32 = 0.01;
+// how close to a loop marker the time slider has to be pressed to drag the marker
+const LOOP_MARKER_GRAB_WIDTH: f32 = 0.03;
+// screen pixels of a font pixel of the notifications
+const NOTIFICATION_FONT_SCALE: f32 = 2.0;
+// height of the characters on the buttons and the sliders
+const BUTTON_LABEL_SIZE: f32 = 0.05;
+const SLIDER_LABEL_SIZE: f32 = 0.08;
+const LABEL_COLOR: [f32; 3] = [0.95, 0.95, 0.95];
+// the waveform not played yet, the played part is in the color of the sliders
+const WAVEFORM_COLOR: [f32; 3] = [0.3, 0.55, 0.75];
+// part of the height of the waveforms the loudest samples reach
+const WAVEFORM_HEIGHT: f32 = 0.9;
+// seconds across the waveform view at the start
+const WAVEFORM_VIEW_SPAN: f32 = 4.0;
+// touchpads scroll by pixels, about this many make a line of a mouse wheel
+const WHEEL_LINE_PIXELS: f32 = 40.0;
 
 pub const NORMAL_BUTTON_COLOR: ButtonColors = ButtonColors {
     base_color: [0.0, 0.27, 0.5],
@@ -47,11 +84,21 @@ pub const LOOP_BUTTON_COLOR: ButtonColors = ButtonColors {
     hover_color: [0.9, 0.8, 0.5],
     press_color: [0.7, 0.4, 0.0],
 };
+pub const STRETCH_BUTTON_COLOR: ButtonColors = ButtonColors {
+    base_color: [0.8, 0.5, 0.0],
+    hover_color: [0.9, 0.8, 0.5],
+    press_color: [0.7, 0.4, 0.0],
+};
 pub const LOADING_BUTTON_COLOR: ButtonColors = ButtonColors {
     base_color: [0.0, 0.05, 0.1],
     hover_color: [0.0, 0.04, 0.15],
     press_color: [0.0, 0.03, 0.05],
 };
+pub const LOAD_FAILED_BUTTON_COLOR: ButtonColors = ButtonColors {
+    base_color: [0.6, 0.1, 0.1],
+    hover_color: [0.9, 0.5, 0.5],
+    press_color: [0.3, 0.0, 0.0],
+};
 
 // MARK: main
 fn main() -> Result<(), String> {
@@ -60,31 +107,56 @@ fn main() -> Result<(), String> {
         .filter_module("yee_player", log::LevelFilter::Trace)
         .init();
 
-    // use another thread to create the OutputStream of rodio. avoid winit conflict.
-    let (sender, receiver) = std::sync::mpsc::channel();
-    let (sender_end, receiver_end) = std::sync::mpsc::channel();
-    let _audio_stream_thread = std::thread::spawn(move || {
-        let (_stream, stream_handle) = match rodio::OutputStream::try_default() {
-            Err(e) => {
-                let error_msg = format!("error getting default OutputStream: {:?}", e);
-                sender.send(Err(error_msg)).unwrap();
-                return;
+    let args: Vec<String> = std::env::args().collect();
+    let command_line = match cli::parse(&args) {
+        Ok(Command::Run(command_line)) => command_line,
+        Ok(Command::Help) => {
+            println!("{}", cli::USAGE);
+            return Ok(());
+        }
+        Ok(Command::Version) => {
+            println!("yee_player {}", env!("CARGO_PKG_VERSION"));
+            return Ok(());
+        }
+        Ok(Command::ListDevices) => {
+            for name in buffer_player::backend::output_devices() {
+                println!("{}", name);
             }
-            Ok(output) => output,
-        };
-        sender.send(Ok(stream_handle)).unwrap();
-        drop(sender);
-        receiver_end.recv().unwrap();
-        log::info!("audio_stream_thread end");
-    });
+            return Ok(());
+        }
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
 
-    let stream_handle = receiver.recv().unwrap()?;
-    drop(receiver);
+    let setting_path = SettingPath(function::execute_or_relative_path(
+        command_line
+            .setting_path
+            .as_deref()
+            .unwrap_or("./asset/setting/setting.ron"),
+    )?);
+    let notifications = Notifications::default();
+    // a setting file given on the command line has to be there
+    let setting = match command_line.setting_path {
+        Some(_) => Setting::load(&setting_path.0)?,
+        None => Setting::load(&setting_path.0).unwrap_or_else(|e| {
+            notifications.error(e.to_string());
+            Setting::default()
+        }),
+    };
 
-    let shader_path: PathBuf = function::execute_or_relative_path("./asset/shader/shader.wgsl")?;
-    let setting_path =
-        SettingPath(function::execute_or_relative_path("./asset/setting/setting.ron").unwrap());
-    let setting = Setting::load(&setting_path.0).unwrap_or_default();
+    // play or export without opening the window
+    if command_line.is_headless() {
+        return headless::run(&command_line, &setting);
+    }
+
+    let shader_path: PathBuf = function::execute_or_relative_path(
+        command_line
+            .shader_path
+            .as_deref()
+            .unwrap_or("./asset/shader/shader.wgsl"),
+    )?;
 
     let event_loop = winit::event_loop::EventLoop::new();
 
@@ -132,21 +204,26 @@ fn main() -> Result<(), String> {
 
         let play_fn: ButtonFn = Arc::new(
             |_world: &mut World, res: &mut Resources, _self_entity: Entity| {
-                let controller = res.get::<AudioController<i16>>().unwrap();
+                let controller = res.get::<AudioController<f32>>().unwrap();
+                let setting = res.get::<Setting>().unwrap();
                 let speed = controller.get_speed();
-                if speed == 0.0 {
-                    let value = res.get::<PlayingSpeed>().unwrap().0;
-                    controller.set_speed(value);
+                let new_speed = if speed == 0.0 {
+                    res.get::<PlayingSpeed>().unwrap().0
                 } else {
                     res.get_mut::<PlayingSpeed>().unwrap().0 = speed;
-                    controller.set_speed(0.0);
-                }
+                    0.0
+                };
+                controller.set_speed_with_ramp(
+                    new_speed,
+                    setting.speed_ramp_time,
+                    setting.speed_ramp_curve,
+                );
             },
         );
 
         let loop_fn: ButtonFn = Arc::new(
             |world: &mut World, res: &mut Resources, self_entity: Entity| {
-                let controller = res.get::<AudioController<i16>>().unwrap();
+                let controller = res.get::<AudioController<f32>>().unwrap();
                 controller.set_loop_mode(true);
 
                 let unloop_fn: &ButtonFn = &res.get::<ButtonFunctions>().unwrap().unloop_fn;
@@ -163,7 +240,7 @@ fn main() -> Result<(), String> {
 
         let unloop_fn: ButtonFn = Arc::new(
             |world: &mut World, res: &mut Resources, self_entity: Entity| {
-                let controller = res.get::<AudioController<i16>>().unwrap();
+                let controller = res.get::<AudioController<f32>>().unwrap();
                 controller.set_loop_mode(false);
 
                 let loop_fn: &ButtonFn = &res.get::<ButtonFunctions>().unwrap().loop_fn;
@@ -178,11 +255,27 @@ fn main() -> Result<(), String> {
             },
         );
 
+        let stretch_fn: ButtonFn = Arc::new(
+            |world: &mut World, res: &mut Resources, self_entity: Entity| {
+                function::set_time_stretch(world, res, self_entity, true);
+            },
+        );
+
+        let unstretch_fn: ButtonFn = Arc::new(
+            |world: &mut World, res: &mut Resources, self_entity: Entity| {
+                function::set_time_stretch(world, res, self_entity, false);
+            },
+        );
+
         let load_fn: ButtonFn = Arc::new(
             |world: &mut World, res: &mut Resources, self_entity: Entity| {
                 let setting_path = res.get::<SettingPath>().unwrap();
                 let mut setting = res.get_mut::<Setting>().unwrap();
-                let new_setting = Setting::load(&setting_path.0).unwrap_or_default();
+                let notifications = res.get::<Notifications>().unwrap();
+                let new_setting = Setting::load(&setting_path.0).unwrap_or_else(|e| {
+                    notifications.error(e.to_string());
+                    Setting::default()
+                });
                 if new_setting.window_width != setting.window_width
                     || new_setting.window_height != setting.window_height
                 {
@@ -207,11 +300,77 @@ fn main() -> Result<(), String> {
                     setting.min_speed = new_setting.min_speed;
                 }
 
+                if new_setting.interpolation != setting.interpolation {
+                    let controller = res.get::<AudioController<f32>>().unwrap();
+                    controller.set_interpolation(new_setting.interpolation);
+                    setting.interpolation = new_setting.interpolation;
+                }
+
+                if new_setting.pitch != setting.pitch {
+                    let controller = res.get::<AudioController<f32>>().unwrap();
+                    controller.set_pitch(new_setting.pitch.clamp(-MAX_PITCH, MAX_PITCH));
+                    setting.pitch = new_setting.pitch;
+                }
+
+                if new_setting.playlist_mode != setting.playlist_mode {
+                    res.get_mut::<Playlist>()
+                        .unwrap()
+                        .set_mode(new_setting.playlist_mode);
+                    setting.playlist_mode = new_setting.playlist_mode;
+                }
+
+                setting.speed_ramp_time = new_setting.speed_ramp_time;
+                setting.speed_ramp_curve = new_setting.speed_ramp_curve;
+
+                if new_setting.crossfade_ms != setting.crossfade_ms {
+                    let controller = res.get::<AudioController<f32>>().unwrap();
+                    controller.set_crossfade_time(
+                        new_setting.crossfade_ms.clamp(0.0, MAX_CROSSFADE_MS) / 1000.0,
+                    );
+                    setting.crossfade_ms = new_setting.crossfade_ms;
+                }
+
+                if new_setting.track_crossfade_secs != setting.track_crossfade_secs
+                    || new_setting.track_crossfade_curve != setting.track_crossfade_curve
+                {
+                    let controller = res.get::<AudioController<f32>>().unwrap();
+                    controller.set_track_fade(
+                        new_setting.track_crossfade_secs.max(0.0),
+                        new_setting.track_crossfade_curve,
+                    );
+                    setting.track_crossfade_secs = new_setting.track_crossfade_secs;
+                    setting.track_crossfade_curve = new_setting.track_crossfade_curve;
+                }
+
+                if new_setting.output != setting.output {
+                    let mut controller = res.get_mut::<AudioController<f32>>().unwrap();
+                    controller.set_output(&new_setting.output);
+                    setting.output = new_setting.output.clone();
+                }
+
+                if new_setting.preserve_pitch != setting.preserve_pitch {
+                    let stretch_button = res.get::<ControlledButtons>().unwrap().stretch_button;
+                    function::set_time_stretch(
+                        world,
+                        res,
+                        stretch_button,
+                        new_setting.preserve_pitch,
+                    );
+                    setting.preserve_pitch = new_setting.preserve_pitch;
+                }
+
+                let storage_changed = new_setting.streaming != setting.streaming
+                    || new_setting.sample_storage != setting.sample_storage;
+                setting.streaming = new_setting.streaming;
+                setting.sample_storage = new_setting.sample_storage;
+
                 let new_music_path = function::execute_or_relative_path(&new_setting.music_path);
                 match new_music_path {
-                    Err(e) => log::error!("error getting music path: {}", e),
+                    Err(e) => notifications.error(format!("error getting music path: {}", e)),
                     Ok(path) => match std::fs::metadata(&path) {
-                        Err(e) => log::error!("error getting music metadata: {}", e),
+                        Err(e) => {
+                            notifications.error(format!("error getting music metadata: {}", e))
+                        }
                         Ok(meta) => {
                             let mut should_load = true;
                             if let Some(old_meta) = res.get::<MusicFileMetaData>().unwrap().as_ref()
@@ -221,17 +380,25 @@ fn main() -> Result<(), String> {
                                 {
                                     if old_date == date
                                         && setting.music_path == new_setting.music_path
+                                        && !storage_changed
                                     {
                                         should_load = false;
                                     }
                                 }
                             }
                             if should_load {
+                                // another music file in the setting starts a new playlist
+                                if setting.music_path != new_setting.music_path {
+                                    res.get_mut::<Playlist>().unwrap().set_tracks(vec![
+                                        Track::new(new_setting.music_path.clone()),
+                                    ]);
+                                }
                                 function::load_music(
                                     world,
                                     res,
                                     self_entity,
                                     &new_setting.music_path,
+                                    &setting,
                                 );
                             }
                         }
@@ -258,30 +425,121 @@ fn main() -> Result<(), String> {
             },
         ) as ButtonFn;
 
+        let export_fn: ButtonFn = Arc::new(
+            |world: &mut World, res: &mut Resources, self_entity: Entity| {
+                let setting = res.get::<Setting>().unwrap();
+                let notifications = res.get::<Notifications>().unwrap();
+                let source = match function::execute_or_relative_path(&setting.music_path) {
+                    Err(e) => {
+                        notifications.error(format!("error getting music path: {}", e));
+                        return;
+                    }
+                    Ok(source) => source,
+                };
+                let target = function::export_path(&source);
+                let mut options = res
+                    .get::<AudioController<f32>>()
+                    .unwrap()
+                    .get_export_options();
+                // export at the speed it would play at when paused
+                if options.speed == 0.0 {
+                    options.speed = res.get::<PlayingSpeed>().unwrap().0;
+                }
+                notifications.info(format!("exporting to {}", target.display()));
+                *res.get_mut::<AudioExporterRes>().unwrap() = Some(AudioExporter {
+                    exporter: WavExporter::export(source, target, options),
+                    export_button_entity: self_entity,
+                });
+
+                let stop_export_fn = &res.get::<ButtonFunctions>().unwrap().stop_export_fn;
+                if let Some(mut entry) = world.entry(self_entity) {
+                    if let Ok(self_fn) = entry.get_component_mut::<ButtonFn>() {
+                        *self_fn = Arc::clone(stop_export_fn);
+                    }
+                    if let Ok(colors) = entry.get_component_mut::<ButtonColors>() {
+                        *colors = LOADING_BUTTON_COLOR;
+                    }
+                    if let Ok(slider) = entry.get_component_mut::<Slider>() {
+                        slider.set_value(0.0);
+                    }
+                    if let Ok(target_value) = entry.get_component_mut::<TargetValue>() {
+                        target_value.0 = 0.0;
+                    }
+                }
+            },
+        );
+
+        let stop_export_fn: ButtonFn = Arc::new(
+            |world: &mut World, res: &mut Resources, self_entity: Entity| {
+                if let Some(exporter) = res.get::<AudioExporterRes>().unwrap().as_ref() {
+                    exporter.exporter.stop();
+                }
+
+                let export_fn: &ButtonFn = &res.get::<ButtonFunctions>().unwrap().export_fn;
+                if let Some(mut entry) = world.entry(self_entity) {
+                    if let Ok(self_fn) = entry.get_component_mut::<ButtonFn>() {
+                        *self_fn = Arc::clone(export_fn);
+                    }
+                    if let Ok(colors) = entry.get_component_mut::<ButtonColors>() {
+                        *colors = NORMAL_BUTTON_COLOR;
+                    }
+                }
+            },
+        );
+
         // MARK: entity
         world.extend(vec![
             (
                 StateButton::new(),
                 NORMAL_BUTTON_COLOR,
                 Transform {
-                    location: [-1.0, 0.5],
-                    size: [0.5, 0.5],
+                    location: [-1.0, TOP_BUTTON_BOTTOM],
+                    size: [TOP_BUTTON_WIDTH, TOP_BUTTON_HEIGHT],
                     color: NORMAL_BUTTON_COLOR.base_color,
                 },
+                top_button_label("play", 0),
                 Arc::clone(&play_fn),
             ),
             (
                 StateButton::new(),
                 NORMAL_BUTTON_COLOR,
                 Transform {
-                    location: [-0.5, 0.5],
-                    size: [0.5, 0.5],
+                    location: [-1.0 + TOP_BUTTON_WIDTH, TOP_BUTTON_BOTTOM],
+                    size: [TOP_BUTTON_WIDTH, TOP_BUTTON_HEIGHT],
                     color: NORMAL_BUTTON_COLOR.base_color,
                 },
+                top_button_label("rev", 1),
                 Arc::new(
                     |_world: &mut World, res: &mut Resources, _self_entity: Entity| {
-                        let controller = res.get::<AudioController<i16>>().unwrap();
-                        controller.set_speed(-controller.get_speed());
+                        let controller = res.get::<AudioController<f32>>().unwrap();
+                        let setting = res.get::<Setting>().unwrap();
+                        controller.set_speed_with_ramp(
+                            -controller.get_speed(),
+                            setting.speed_ramp_time,
+                            setting.speed_ramp_curve,
+                        );
+                    },
+                ),
+            ),
+            (
+                StateButton::new(),
+                NORMAL_BUTTON_COLOR,
+                Transform {
+                    location: [-1.0 + TOP_BUTTON_WIDTH * 2.0, TOP_BUTTON_BOTTOM],
+                    size: [TOP_BUTTON_WIDTH, TOP_BUTTON_HEIGHT],
+                    color: NORMAL_BUTTON_COLOR.base_color,
+                },
+                top_button_label("prev", 2),
+                Arc::new(
+                    |world: &mut World, res: &mut Resources, _self_entity: Entity| {
+                        let path = res
+                            .get_mut::<Playlist>()
+                            .unwrap()
+                            .skip_previous()
+                            .map(str::to_string);
+                        if let Some(path) = path {
+                            function::load_playlist_track(world, res, &path);
+                        }
                     },
                 ),
             ),
@@ -289,13 +547,47 @@ fn main() -> Result<(), String> {
                 StateButton::new(),
                 NORMAL_BUTTON_COLOR,
                 Transform {
-                    location: [0.0, 0.5],
-                    size: [0.5, 0.5],
+                    location: [-1.0 + TOP_BUTTON_WIDTH * 3.0, TOP_BUTTON_BOTTOM],
+                    size: [TOP_BUTTON_WIDTH, TOP_BUTTON_HEIGHT],
                     color: NORMAL_BUTTON_COLOR.base_color,
                 },
-                Arc::clone(&loop_fn),
+                top_button_label("next", 3),
+                Arc::new(
+                    |world: &mut World, res: &mut Resources, _self_entity: Entity| {
+                        let path = res
+                            .get_mut::<Playlist>()
+                            .unwrap()
+                            .skip_next()
+                            .map(str::to_string);
+                        if let Some(path) = path {
+                            function::load_playlist_track(world, res, &path);
+                        }
+                    },
+                ),
             ),
         ]);
+        let loop_button_entity = world.push((
+            StateButton::new(),
+            NORMAL_BUTTON_COLOR,
+            Transform {
+                location: [-1.0 + TOP_BUTTON_WIDTH * 4.0, TOP_BUTTON_BOTTOM],
+                size: [TOP_BUTTON_WIDTH, TOP_BUTTON_HEIGHT],
+                color: NORMAL_BUTTON_COLOR.base_color,
+            },
+            top_button_label("loop", 4),
+            Arc::clone(&loop_fn),
+        ));
+        let stretch_button_entity = world.push((
+            StateButton::new(),
+            NORMAL_BUTTON_COLOR,
+            Transform {
+                location: [-1.0 + TOP_BUTTON_WIDTH * 5.0, TOP_BUTTON_BOTTOM],
+                size: [TOP_BUTTON_WIDTH, TOP_BUTTON_HEIGHT],
+                color: NORMAL_BUTTON_COLOR.base_color,
+            },
+            top_button_label("tempo", 5),
+            Arc::clone(&stretch_fn),
+        ));
         let load_button_entity = world.push((
             StateButton::new(),
             Slider::new(0.0, 0.0..1.0),
@@ -306,12 +598,41 @@ fn main() -> Result<(), String> {
                 state_colors: NORMAL_BUTTON_COLOR,
             },
             Transform {
-                location: [0.5, 0.5],
-                size: [0.5, 0.5],
+                location: [-1.0 + TOP_BUTTON_WIDTH * 7.0, TOP_BUTTON_BOTTOM],
+                size: [TOP_BUTTON_WIDTH, TOP_BUTTON_HEIGHT],
                 color: LOADING_BUTTON_COLOR.base_color,
             },
+            top_button_label("conf", 7),
             Arc::clone(&stop_load_fn),
         ));
+        world.push((
+            StateButton::new(),
+            Slider::new(0.0, 0.0..1.0),
+            TargetValue(0.0),
+            NORMAL_BUTTON_COLOR,
+            SliderColors {
+                current_color: NORMAL_BUTTON_COLOR.base_color,
+                state_colors: NORMAL_BUTTON_COLOR,
+            },
+            Transform {
+                location: [-1.0 + TOP_BUTTON_WIDTH * 6.0, TOP_BUTTON_BOTTOM],
+                size: [TOP_BUTTON_WIDTH, TOP_BUTTON_HEIGHT],
+                color: NORMAL_BUTTON_COLOR.base_color,
+            },
+            top_button_label("wav", 6),
+            Arc::clone(&export_fn),
+        ));
+
+        world.push((
+            StateButton::new(),
+            WaveformView::new(WAVEFORM_VIEW_SPAN),
+            Transform {
+                location: [-1.0, 0.0],
+                size: [2.0, 0.6],
+                color: NORMAL_BUTTON_COLOR.press_color,
+            },
+            Text::new("", [-0.98, 0.02], BUTTON_LABEL_SIZE, LABEL_COLOR),
+        ));
 
         let slider_entities = world.extend(vec![
             (
@@ -323,10 +644,11 @@ fn main() -> Result<(), String> {
                     state_colors: SLIDER_COLOR,
                 },
                 Transform {
-                    location: [-1.0, 0.0],
-                    size: [2.0, 0.5],
+                    location: [-1.0, -0.4],
+                    size: [2.0, 0.4],
                     color: NORMAL_BUTTON_COLOR.base_color,
                 },
+                Text::centered_in("", [-1.0, -0.4], [2.0, 0.4], SLIDER_LABEL_SIZE, LABEL_COLOR),
             ),
             (
                 StateButton::new(),
@@ -337,10 +659,11 @@ fn main() -> Result<(), String> {
                     state_colors: SLIDER_COLOR,
                 },
                 Transform {
-                    location: [-1.0, -0.5],
-                    size: [2.0, 0.5],
+                    location: [-1.0, -0.7],
+                    size: [1.0, 0.3],
                     color: NORMAL_BUTTON_COLOR.base_color,
                 },
+                Text::centered_in("", [-1.0, -0.7], [1.0, 0.3], SLIDER_LABEL_SIZE, LABEL_COLOR),
             ),
             (
                 StateButton::new(),
@@ -352,16 +675,33 @@ fn main() -> Result<(), String> {
                 },
                 Transform {
                     location: [-1.0, -1.0],
-                    size: [2.0, 0.5],
+                    size: [2.0, 0.3],
                     color: NORMAL_BUTTON_COLOR.base_color,
                 },
+                Text::centered_in("", [-1.0, -1.0], [2.0, 0.3], SLIDER_LABEL_SIZE, LABEL_COLOR),
+            ),
+            (
+                StateButton::new(),
+                Slider::new(setting.pitch, -MAX_PITCH..MAX_PITCH).with_snap_step(1.0),
+                NORMAL_BUTTON_COLOR,
+                SliderColors {
+                    current_color: SLIDER_COLOR.base_color,
+                    state_colors: SLIDER_COLOR,
+                },
+                Transform {
+                    location: [0.0, -0.7],
+                    size: [1.0, 0.3],
+                    color: NORMAL_BUTTON_COLOR.base_color,
+                },
+                Text::centered_in("", [0.0, -0.7], [1.0, 0.3], SLIDER_LABEL_SIZE, LABEL_COLOR),
             ),
         ]);
 
         // Resources
         let mut resources = Resources::default();
         resources.insert(Input::default());
-        let empty_buffer = Arc::new(SamplesBuffer::new(1, 48000, Vec::<i16>::new()));
+        let empty_buffer: SharedBuffer<f32> =
+            Arc::new(SamplesBuffer::new(1, 48000, Vec::<f32>::new()));
         resources.insert(Arc::clone(&empty_buffer));
         resources.insert(window);
         resources.insert(renderer);
@@ -369,50 +709,91 @@ fn main() -> Result<(), String> {
             play_fn,
             loop_fn,
             unloop_fn,
+            stretch_fn,
+            unstretch_fn,
             load_fn,
             stop_load_fn,
+            export_fn,
+            stop_export_fn,
         });
+        // controller
+        let controller = AudioController::new_with_buffer(
+            command_line.output.as_ref().unwrap_or(&setting.output),
+            empty_buffer,
+        );
+        controller.set_interpolation(setting.interpolation);
+        controller.set_pitch(setting.pitch.clamp(-MAX_PITCH, MAX_PITCH));
+        controller.set_crossfade_time(setting.crossfade_ms.clamp(0.0, MAX_CROSSFADE_MS) / 1000.0);
+        controller.set_track_fade(
+            setting.track_crossfade_secs.max(0.0),
+            setting.track_crossfade_curve,
+        );
+        resources.insert(controller);
         // setting
         resources.insert(setting_path);
         resources.insert(setting);
         resources.insert::<MusicFileMetaData>(None);
-        // controller
-        resources.insert(AudioController::new_with_buffer(
-            &stream_handle,
-            empty_buffer,
-        ));
         let controlled_sliders = ControlledSliders {
             time_slider: slider_entities[0],
             speed_slider: slider_entities[1],
             volume_slider: slider_entities[2],
+            pitch_slider: slider_entities[3],
         };
         resources.insert(controlled_sliders);
-        resources.insert(PlayingSpeed(1.0));
+        resources.insert(ControlledButtons {
+            stretch_button: stretch_button_entity,
+            load_button: load_button_entity,
+        });
+        resources.insert(PlayingSpeed(command_line.speed.unwrap_or(1.0)));
+        resources.insert(StartTime(command_line.start));
+        resources.insert(PlayerEvents(Vec::new()));
+        resources.insert(notifications);
+        resources.insert(DraggingLoopMarker(None));
         // buffer loader
         resources.insert::<AudioLoaderRes>(None);
+        resources.insert::<AudioExporterRes>(None);
+
+        if resources.get::<Setting>().unwrap().preserve_pitch {
+            function::set_time_stretch(&mut world, &resources, stretch_button_entity, true);
+        }
 
+        // command line support
         {
-            // command line support
-            let args: Vec<String> = std::env::args().collect();
-            if let Some(path) = args.get(1) {
-                function::load_music(&mut world, &resources, load_button_entity, path);
-            } else {
-                let setting = resources.get::<Setting>().unwrap();
-                function::load_music(
-                    &mut world,
-                    &resources,
-                    load_button_entity,
-                    &setting.music_path,
-                );
+            let controller = resources.get::<AudioController<f32>>().unwrap();
+            if let Some(speed) = command_line.signed_speed() {
+                controller.set_speed(speed);
+            }
+            if let Some(volume) = command_line.volume {
+                controller.set_volume(volume);
             }
         }
+        if command_line.loop_mode {
+            let loop_fn = Arc::clone(&resources.get::<ButtonFunctions>().unwrap().loop_fn);
+            loop_fn(&mut world, &mut resources, loop_button_entity);
+        }
+        {
+            let setting = resources.get::<Setting>().unwrap();
+            let paths = match command_line.files.is_empty() {
+                true => vec![setting.music_path.clone()],
+                false => command_line.files.clone(),
+            };
+            let tracks = playlist::file::expand(&paths).unwrap_or_else(|e| {
+                resources.get::<Notifications>().unwrap().error(e);
+                Vec::new()
+            });
+            let playlist = Playlist::new(tracks, setting.playlist_mode);
+            let path = playlist.get_current().unwrap_or_default().to_string();
+            drop(setting);
+            resources.insert(playlist);
+            function::load_playlist_track(&mut world, &resources, &path);
+        }
 
         // MARK: systems
         let update_button_and_slider_color = SystemBuilder::new("update_button_and_slider_color")
             .with_query(<(Write<SliderColors>, Read<StateButton>)>::query())
             .with_query(<(Read<StateButton>, Read<ButtonColors>, Write<Transform>)>::query())
             .build(|_, world, _, (slider_query, button_query)| {
-                button_query.for_each_mut(world, |(button, colors, mut transform)| {
+                button_query.for_each_mut(world, |(button, colors, transform)| {
                     match button.get_state() {
                         ButtonState::Unhover => {
                             let speed = 0.17;
@@ -438,7 +819,7 @@ fn main() -> Result<(), String> {
                     }
                 });
 
-                slider_query.for_each_mut(world, |(mut color, button)| match button.get_state() {
+                slider_query.for_each_mut(world, |(color, button)| match button.get_state() {
                     ButtonState::Unhover => {
                         let speed = 0.17;
                         let r = smooth_to(
@@ -499,40 +880,75 @@ fn main() -> Result<(), String> {
                 });
             });
 
+        // one more resource would be more than a system can take
+        let notifications = resources.get::<Notifications>().unwrap().clone();
         let check_loader = SystemBuilder::new("check_loader")
             .write_component::<ButtonFn>()
             .write_component::<ButtonColors>()
             .write_component::<TargetValue>()
             .write_component::<Slider>()
             .read_resource::<ButtonFunctions>()
-            .write_resource::<Arc<SamplesBuffer<i16>>>()
-            .write_resource::<AudioController<i16>>()
+            .write_resource::<SharedBuffer<f32>>()
+            .write_resource::<AudioController<f32>>()
             .read_resource::<ControlledSliders>()
             .write_resource::<Setting>()
             .write_resource::<MusicFileMetaData>()
             .write_resource::<AudioLoaderRes>()
+            .write_resource::<StartTime>()
             .build(
-                |_,
-                 world,
-                 (funcs, audio_buffer, controller, sliders, setting, meta_data, loader),
-                 _| {
+                move |_,
+                      world,
+                      (
+                    funcs,
+                    audio_buffer,
+                    controller,
+                    sliders,
+                    setting,
+                    meta_data,
+                    loader,
+                    start_time,
+                ),
+                      _| {
                     // query.for_each_mut(&mut query_world, |(entity, buffer_loader, caller)| {
                     let mut audio_buffer_loaded = false;
                     let mut drop_loader = false;
                     if let Some(loader) = loader.as_mut() {
+                        // start playing the beginning while the rest is still loading
+                        if let Some(partial) = loader.loader.try_get_partial() {
+                            **audio_buffer = partial;
+                            audio_buffer_loaded = true;
+                            loader.partial_loaded = true;
+                            setting.music_path = loader.path.clone();
+                            **meta_data = std::fs::metadata(&setting.music_path).ok();
+                        }
                         if let Some(value) = loader.loader.try_get_value() {
                             drop_loader = true;
+                            // a failed load stays red until the next one
+                            let mut colors = NORMAL_BUTTON_COLOR;
                             let value = match value {
-                                Err(e) => {
-                                    log::error!("error loading audio: {}", e);
+                                Err(e) if e.is_cancelled() => {
+                                    notifications.info(e.to_string());
                                     0.0
                                 }
+                                // what was decoded before the corrupt part is playing
+                                Err(e @ LoadError::CorruptStream { .. })
+                                    if loader.partial_loaded =>
+                                {
+                                    notifications.error(format!("error loading audio: {}", e));
+                                    1.0
+                                }
+                                Err(e) => {
+                                    notifications.error(format!("error loading audio: {}", e));
+                                    colors = LOAD_FAILED_BUTTON_COLOR;
+                                    1.0
+                                }
                                 Ok(value) => {
-                                    log::info!(
-                                        "load success, audio length: {}s",
-                                        value.get_duration().as_secs_f32()
-                                    );
-                                    **audio_buffer = Arc::new(value);
+                                    notifications.info(format!(
+                                        "loaded {}, {}",
+                                        function::file_name(&loader.path),
+                                        function::format_time(value.get_duration().as_secs_f32())
+                                    ));
+                                    **audio_buffer = value;
                                     audio_buffer_loaded = true;
                                     setting.music_path = loader.path.clone();
                                     **meta_data = std::fs::metadata(&setting.music_path).ok();
@@ -544,8 +960,9 @@ fn main() -> Result<(), String> {
                                 if let Ok(target_value) = entry.get_component_mut::<TargetValue>() {
                                     target_value.0 = value;
                                 }
-                                if let Ok(colors) = entry.get_component_mut::<ButtonColors>() {
-                                    *colors = NORMAL_BUTTON_COLOR;
+                                if let Ok(button_colors) = entry.get_component_mut::<ButtonColors>()
+                                {
+                                    *button_colors = colors;
                                 }
                                 if let Ok(caller_fn) = entry.get_component_mut::<ButtonFn>() {
                                     *caller_fn = Arc::clone(&funcs.load_fn);
@@ -560,35 +977,119 @@ fn main() -> Result<(), String> {
                     if drop_loader {
                         **loader = None;
                     }
-                    if audio_buffer_loaded {
-                        if !Arc::ptr_eq(controller.get_target_buffer(), audio_buffer) {
-                            controller.set_target_buffer(Arc::clone(audio_buffer));
-                            let buffer_duartion =
-                                controller.get_target_buffer().get_duration().as_secs_f32();
-
-                            if let Ok(mut entry) = world.entry_mut(sliders.time_slider) {
-                                if let Ok(time_slider) = entry.get_component_mut::<Slider>() {
-                                    time_slider.set_range(0.0..buffer_duartion);
-                                }
-                            }
+                    if audio_buffer_loaded
+                        && !Arc::ptr_eq(controller.get_target_buffer(), audio_buffer)
+                    {
+                        controller.set_target_buffer(Arc::clone(audio_buffer));
+                        controller.set_loop_region(None);
+                        let buffer_duartion =
+                            controller.get_target_buffer().get_duration().as_secs_f32();
 
-                            if controller.get_speed() < 0.0 {
-                                controller.change_time(buffer_duartion);
-                            } else {
-                                controller.change_time(0.0);
+                        if let Ok(mut entry) = world.entry_mut(sliders.time_slider) {
+                            if let Ok(time_slider) = entry.get_component_mut::<Slider>() {
+                                time_slider.set_range(0.0..buffer_duartion);
                             }
                         }
+
+                        if controller.get_speed() < 0.0 {
+                            controller.change_time(buffer_duartion);
+                        } else {
+                            controller.change_time(0.0);
+                        }
+                    }
+                    if let Some(time) = start_time.0 {
+                        // the start may be past the part that is loaded
+                        let buffer_duartion =
+                            controller.get_target_buffer().get_duration().as_secs_f32();
+                        if time < buffer_duartion || loader.is_none() {
+                            controller.change_time(time.min(buffer_duartion));
+                            start_time.0 = None;
+                        }
                     }
                 },
             );
 
+        let check_player_events = SystemBuilder::new("check_player_events")
+            .write_resource::<AudioController<f32>>()
+            .write_resource::<PlayerEvents>()
+            .read_resource::<Notifications>()
+            .build(|_, _, (controller, events, notifications), _| {
+                controller.check_output();
+                if let Some(e) = controller.take_output_error() {
+                    notifications.error(e);
+                }
+                if let Some(e) = controller.take_buffer_error() {
+                    notifications.error(format!("error decoding audio: {}", e));
+                }
+                events.0 = controller.take_events();
+                for event in &events.0 {
+                    match event {
+                        PlayerEvent::Underrun => log::warn!("playing faster than decoding"),
+                        event => log::debug!("{:?}", event),
+                    }
+                }
+            });
+
+        let expire_notifications = SystemBuilder::new("expire_notifications")
+            .read_resource::<Notifications>()
+            .build(|_, _, notifications, _| notifications.remove_expired());
+
+        let check_exporter = SystemBuilder::new("check_exporter")
+            .write_component::<ButtonFn>()
+            .write_component::<ButtonColors>()
+            .write_component::<TargetValue>()
+            .read_resource::<ButtonFunctions>()
+            .write_resource::<AudioExporterRes>()
+            .read_resource::<Notifications>()
+            .build(|_, world, (funcs, exporter, notifications), _| {
+                let mut drop_exporter = false;
+                if let Some(exporter) = exporter.as_mut() {
+                    let entity = exporter.export_button_entity;
+                    if let Some(value) = exporter.exporter.try_get_value() {
+                        drop_exporter = true;
+                        let value = match value {
+                            Err(e) if e.is_cancelled() => {
+                                notifications.info(e.to_string());
+                                0.0
+                            }
+                            Err(e) => {
+                                notifications.error(format!("error exporting audio: {}", e));
+                                0.0
+                            }
+                            Ok(path) => {
+                                notifications.info(format!("exported to {}", path.display()));
+                                1.0
+                            }
+                        };
+                        if let Ok(mut entry) = world.entry_mut(entity) {
+                            if let Ok(target_value) = entry.get_component_mut::<TargetValue>() {
+                                target_value.0 = value;
+                            }
+                            if let Ok(colors) = entry.get_component_mut::<ButtonColors>() {
+                                *colors = NORMAL_BUTTON_COLOR;
+                            }
+                            if let Ok(caller_fn) = entry.get_component_mut::<ButtonFn>() {
+                                *caller_fn = Arc::clone(&funcs.export_fn);
+                            }
+                        }
+                    } else if let Ok(mut entry) = world.entry_mut(entity) {
+                        if let Ok(target_value) = entry.get_component_mut::<TargetValue>() {
+                            target_value.0 = exporter.exporter.get_progress();
+                        }
+                    }
+                }
+                if drop_exporter {
+                    **exporter = None;
+                }
+            });
+
         let update_button = SystemBuilder::new("update_button")
             .read_resource::<Input>()
             .with_query(<(Write<StateButton>, Read<Transform>)>::query())
             .build(|_commands, world, input, query| {
                 query.for_each_mut(world, |(button, transform)| {
                     let hover = if let Some(location) = &input.mouse_location {
-                        is_in_box(&transform, location)
+                        is_in_box(transform, location)
                     } else {
                         false
                     };
@@ -608,10 +1109,10 @@ fn main() -> Result<(), String> {
                         ButtonState::Press => {
                             if let Some(location) = mouse_location {
                                 let value = if transform.size[0].is_normal() {
-                                    let (x, _) = relative_to_box(&transform, location);
+                                    let (x, _) = relative_to_box(transform, location);
                                     let v = slider.map_value_back(x / transform.size[0]);
                                     if resource.ctrl_pressing {
-                                        (v * 20.0).round() / 20.0
+                                        slider.snap(v)
                                     } else {
                                         v
                                     }
@@ -633,14 +1134,76 @@ fn main() -> Result<(), String> {
                 }
             });
 
+        // drag the loop markers instead of seeking when pressing the time slider near them
+        let update_loop_marker = SystemBuilder::new("update_loop_marker")
+            .read_component::<StateButton>()
+            .read_component::<Transform>()
+            .write_component::<Slider>()
+            .read_resource::<Input>()
+            .read_resource::<AudioController<f32>>()
+            .read_resource::<ControlledSliders>()
+            .write_resource::<DraggingLoopMarker>()
+            .build(|_, world, (input, controller, sliders, dragging), _| {
+                if let Ok(mut entry) = world.entry_mut(sliders.time_slider) {
+                    let (state, response, transform) = match (
+                        entry.get_component::<StateButton>(),
+                        entry.get_component::<Transform>(),
+                    ) {
+                        (Ok(button), Ok(transform)) => {
+                            (*button.get_state(), button.get_response(), *transform)
+                        }
+                        _ => return,
+                    };
+                    let location = match (state, input.mouse_location) {
+                        (ButtonState::Press, Some(location)) => location,
+                        _ => {
+                            dragging.0 = None;
+                            return;
+                        }
+                    };
+                    let time_slider = match entry.get_component_mut::<Slider>() {
+                        Ok(time_slider) => time_slider,
+                        Err(_) => return,
+                    };
+                    let region = controller.get_loop_region();
+                    if let (Some(ButtonResponse::Press), Some(region)) = (response, &region) {
+                        let marker_x = |time: f32| {
+                            transform.location[0] + transform.size[0] * time_slider.map_value(time)
+                        };
+                        let start_distance = (marker_x(region.start) - location.0).abs();
+                        let end_distance = (marker_x(region.end) - location.0).abs();
+                        dragging.0 = if start_distance.min(end_distance) > LOOP_MARKER_GRAB_WIDTH {
+                            None
+                        } else if start_distance < end_distance {
+                            Some(LoopMarker::Start)
+                        } else {
+                            Some(LoopMarker::End)
+                        };
+                    }
+                    if let Some(marker) = dragging.0 {
+                        if let Some(time) = time_slider.take_input_value() {
+                            // the markers swap when one is dragged over the other
+                            let marker = function::set_loop_marker(controller, marker, time);
+                            dragging.0 = Some(marker);
+                        }
+                    }
+                }
+            });
+
         let update_controller = SystemBuilder::new("update_controller")
             .read_component::<Slider>()
             .write_component::<Slider>()
-            .write_resource::<AudioController<i16>>()
+            .write_resource::<AudioController<f32>>()
             .read_resource::<ControlledSliders>()
             .build(|_, world, (controller, sliders), _| {
                 if let Ok(mut entry) = world.entry_mut(sliders.time_slider) {
                     if let Ok(time_slider) = entry.get_component_mut::<Slider>() {
+                        // streaming buffers may find out their length after loading
+                        let buffer_duartion =
+                            controller.get_target_buffer().get_duration().as_secs_f32();
+                        if time_slider.get_range().end != buffer_duartion {
+                            time_slider.set_range(0.0..buffer_duartion);
+                        }
                         if let Some(v) = time_slider.take_input_value() {
                             controller.change_time(v);
                         }
@@ -653,7 +1216,7 @@ fn main() -> Result<(), String> {
                         if let Some(v) = speed_slider.take_input_value() {
                             controller.set_speed(v);
                         }
-                        speed_slider.set_value(controller.get_speed());
+                        speed_slider.set_value(controller.get_current_speed());
                     }
                 }
 
@@ -665,6 +1228,95 @@ fn main() -> Result<(), String> {
                         volume_slider.set_value(controller.get_volume());
                     }
                 }
+
+                if let Ok(mut entry) = world.entry_mut(sliders.pitch_slider) {
+                    if let Ok(pitch_slider) = entry.get_component_mut::<Slider>() {
+                        if let Some(v) = pitch_slider.take_input_value() {
+                            controller.set_pitch(v);
+                        }
+                        pitch_slider.set_value(controller.get_pitch());
+                    }
+                }
+            });
+
+        // the values of the sliders written on them
+        let update_slider_text = SystemBuilder::new("update_slider_text")
+            .read_component::<Slider>()
+            .write_component::<Text>()
+            .read_resource::<ControlledSliders>()
+            .build(|_, world, sliders, _| {
+                type SliderText = fn(&Slider) -> String;
+                let slider_texts: [(Entity, SliderText); 4] = [
+                    (sliders.time_slider, |slider| {
+                        format!(
+                            "{} / {}",
+                            function::format_time(slider.get_value()),
+                            function::format_time(slider.get_range().end)
+                        )
+                    }),
+                    (sliders.speed_slider, |slider| {
+                        format!("speed {:.2}x", slider.get_value())
+                    }),
+                    (sliders.pitch_slider, |slider| {
+                        format!("pitch {:+.1}", slider.get_value())
+                    }),
+                    (sliders.volume_slider, |slider| {
+                        format!("volume {:.0}%", slider.get_value() * 100.0)
+                    }),
+                ];
+                for (entity, slider_text) in slider_texts {
+                    if let Ok(mut entry) = world.entry_mut(entity) {
+                        let value = match entry.get_component::<Slider>() {
+                            Ok(slider) => slider_text(slider),
+                            Err(_) => continue,
+                        };
+                        if let Ok(text) = entry.get_component_mut::<Text>() {
+                            text.text = value;
+                        }
+                    }
+                }
+            });
+
+        // the wheel zooms the waveform view, dragging scrolls it and clicking seeks
+        let update_waveform_view = SystemBuilder::new("update_waveform_view")
+            .write_resource::<Input>()
+            .read_resource::<AudioController<f32>>()
+            .with_query(<(
+                Write<WaveformView>,
+                Write<Text>,
+                Read<StateButton>,
+                Read<Transform>,
+            )>::query())
+            .build(|_, world, (input, controller), query| {
+                let wheel = std::mem::take(&mut input.mouse_wheel);
+                let time = controller.get_time();
+                for (view, text, button, transform) in query.iter_mut(world) {
+                    let x = input
+                        .mouse_location
+                        .map(|location| (location.0 - transform.location[0]) / transform.size[0]);
+                    match (button.get_response(), x) {
+                        (Some(ButtonResponse::Press), Some(x)) => view.press(x),
+                        (Some(ButtonResponse::Release), _) => view.release(time),
+                        (Some(ButtonResponse::Unhover), _) => view.cancel(),
+                        _ => {}
+                    }
+                    match (button.get_state(), x) {
+                        (ButtonState::Press, Some(x)) => view.drag(x),
+                        (ButtonState::Hover, _) => view.zoom(wheel),
+                        _ => {}
+                    }
+                    if controller.get_current_speed() != 0.0 {
+                        view.follow();
+                    }
+                    if let Some(seek_time) = view.take_seek_time() {
+                        let duration = controller.get_target_buffer().get_duration();
+                        controller.change_time(seek_time.clamp(0.0, duration.as_secs_f32()));
+                    }
+                    text.text = match view.get_span() {
+                        span if span < 1.0 => format!("{:.0} ms", span * 1000.0),
+                        span => format!("{:.1} s", span),
+                    };
+                }
             });
 
         let check_file_hover = SystemBuilder::new("check_file_hover")
@@ -675,28 +1327,63 @@ fn main() -> Result<(), String> {
             .write_component::<TargetValue>()
             .write_resource::<Input>()
             .read_resource::<ButtonFunctions>()
+            .read_resource::<Setting>()
             .write_resource::<AudioLoaderRes>()
-            .build(move |_, world, (input, funcs, loader), _| {
-                // when there is no AudioBufferLoader exist, load the dropped file
-                if loader.is_none() {
-                    if let Ok(mut entry) = world.entry_mut(load_button_entity) {
-                        // highlight the load_button when hovering file
-                        if input.hover_file {
-                            if let Ok(button) = entry.get_component_mut::<StateButton>() {
-                                button.update_with_input(true, false);
+            .write_resource::<Playlist>()
+            .read_resource::<Notifications>()
+            .build(
+                move |_, world, (input, funcs, setting, loader, playlist, notifications), _| {
+                    // when there is no AudioBufferLoader exist, load the dropped file
+                    if loader.is_none() {
+                        if let Ok(mut entry) = world.entry_mut(load_button_entity) {
+                            // highlight the load_button when hovering file
+                            if input.hover_file {
+                                if let Ok(button) = entry.get_component_mut::<StateButton>() {
+                                    button.update_with_input(true, false);
+                                }
                             }
-                        }
-                        if let Some(path) = input.drop_file.take() {
-                            match path.into_os_string().into_string() {
+                            // the dropped files replace the playlist
+                            let paths: Vec<String> = input
+                                .drop_files
+                                .drain(..)
+                                .filter_map(|path| match path.into_os_string().into_string() {
+                                    Err(e) => {
+                                        notifications.error(format!(
+                                            "dropped file has invalid path: {:?}",
+                                            e
+                                        ));
+                                        None
+                                    }
+                                    Ok(path) => Some(path),
+                                })
+                                .collect();
+                            // playlist files are opened too
+                            let tracks = match playlist::file::expand(&paths) {
+                                Ok(tracks) => tracks,
                                 Err(e) => {
-                                    log::error!("dropped file has invalid path: {:?}", e);
+                                    notifications.error(e);
+                                    Vec::new()
                                 }
-                                Ok(path) => {
+                            };
+                            if !tracks.is_empty() {
+                                playlist.set_tracks(tracks);
+                                if let Some(path) = playlist.get_current().map(str::to_string) {
                                     log::info!("loading {:?}", path);
                                     **loader = Some(AudioLoader {
-                                        loader: AudioBufferLoader::load(path.clone()),
+                                        loader: if setting.streaming {
+                                            AudioBufferLoader::load_streaming(
+                                                path.clone(),
+                                                setting.sample_storage,
+                                            )
+                                        } else {
+                                            AudioBufferLoader::load(
+                                                path.clone(),
+                                                setting.sample_storage,
+                                            )
+                                        },
                                         path: path.clone(),
                                         load_button_entity,
+                                        partial_loaded: false,
                                     });
 
                                     if let Ok(self_fn) = entry.get_component_mut::<ButtonFn>() {
@@ -711,7 +1398,7 @@ fn main() -> Result<(), String> {
                                         slider.set_value(0.0);
                                     }
 
-                                    if let Ok(mut target_value) =
+                                    if let Ok(target_value) =
                                         entry.get_component_mut::<TargetValue>()
                                     {
                                         target_value.0 = 0.0;
@@ -719,11 +1406,12 @@ fn main() -> Result<(), String> {
                                 }
                             }
                         }
+                    } else if !input.drop_files.is_empty() {
+                        input.drop_files.clear();
+                        notifications.info("still loading, the dropped files are ignored");
                     }
-                } else {
-                    input.drop_file.take();
-                }
-            });
+                },
+            );
 
         let execute_button = Box::new(|world: &mut World, res: &mut Resources| {
             let mut query = <(Entity, Read<StateButton>, Read<ButtonFn>)>::query();
@@ -736,7 +1424,7 @@ fn main() -> Result<(), String> {
                         ButtonResponse::Unhover => {}
                         ButtonResponse::Press => {}
                         ButtonResponse::Release => {
-                            funcs_entities.push((Arc::clone(&func), *entity));
+                            funcs_entities.push((Arc::clone(func), *entity));
                         }
                     }
                 }
@@ -745,16 +1433,103 @@ fn main() -> Result<(), String> {
                 func(world, res, entity);
             }
         });
+        // the player reported the end of the track, the playlist moves on
+        // once nothing is queued to go on with
+        let mut track_ended = false;
+        let advance_playlist = move |world: &mut World, res: &mut Resources| {
+            for event in &res.get::<PlayerEvents>().unwrap().0 {
+                match event {
+                    PlayerEvent::TrackEnded => track_ended = true,
+                    PlayerEvent::BufferSwapped => track_ended = false,
+                    _ => {}
+                }
+            }
+            let loaded = res.get::<AudioLoaderRes>().unwrap().is_none();
+            if loaded {
+                // kept for saving the playlist
+                let duration = res
+                    .get::<AudioController<f32>>()
+                    .unwrap()
+                    .get_target_buffer()
+                    .get_duration();
+                let path = &res.get::<Setting>().unwrap().music_path;
+                res.get_mut::<Playlist>()
+                    .unwrap()
+                    .set_duration(path, duration.as_secs_f32());
+            }
+            if loaded {
+                function::preload_next_track(res);
+            }
+            let started_next = res
+                .get_mut::<AudioController<f32>>()
+                .unwrap()
+                .take_started_next();
+            if let Some(path) = started_next {
+                // playing already went on, the playlist and the rest follow
+                res.get_mut::<Playlist>().unwrap().advance();
+                let buffer = Arc::clone(
+                    res.get::<AudioController<f32>>()
+                        .unwrap()
+                        .get_target_buffer(),
+                );
+                *res.get_mut::<SharedBuffer<f32>>().unwrap() = buffer;
+                *res.get_mut::<MusicFileMetaData>().unwrap() = std::fs::metadata(&path).ok();
+                res.get_mut::<Setting>().unwrap().music_path = path;
+                res.get::<AudioController<f32>>()
+                    .unwrap()
+                    .set_loop_region(None);
+                track_ended = false;
+                return;
+            }
+            // a gap is only left when the next track could not be preloaded in time
+            if !track_ended || !loaded || res.get::<AudioController<f32>>().unwrap().has_next() {
+                return;
+            }
+            track_ended = false;
+            let (path, same_track) = {
+                let mut playlist = res.get_mut::<Playlist>().unwrap();
+                let index = playlist.get_current_index();
+                let path = playlist.advance().map(str::to_string);
+                (path, index == playlist.get_current_index())
+            };
+            let rewind = |controller: &AudioController<f32>| {
+                if controller.get_speed() < 0.0 {
+                    let duration = controller.get_target_buffer().get_duration();
+                    controller.change_time(duration.as_secs_f32());
+                } else {
+                    controller.change_time(0.0);
+                }
+            };
+            match path {
+                // no need to load it again
+                Some(_) if same_track => rewind(&res.get::<AudioController<f32>>().unwrap()),
+                Some(path) => function::load_playlist_track(world, res, &path),
+                // stop after the last track, the play button plays it again
+                None => {
+                    let controller = res.get::<AudioController<f32>>().unwrap();
+                    rewind(&controller);
+                    res.get_mut::<PlayingSpeed>().unwrap().0 = controller.get_speed();
+                    controller.set_speed(0.0);
+                }
+            }
+        };
         let schedule = Schedule::builder()
             .add_system(update_button_and_slider_color)
             .add_system(check_loader)
+            .add_system(check_player_events)
+            .add_system(expire_notifications)
+            .add_system(check_exporter)
             .add_system(update_button)
             .add_system(update_slider)
             .add_system(update_slider_with_target_value)
+            .add_system(update_loop_marker)
             .add_system(update_controller)
+            .add_system(update_slider_text)
+            .add_system(update_waveform_view)
             .add_system(check_file_hover)
             .flush()
             .add_thread_local_fn(execute_button)
+            .add_thread_local_fn(advance_playlist)
             .build();
         (world, resources, schedule)
     };
@@ -812,6 +1587,18 @@ fn main() -> Result<(), String> {
                     winit::event::ElementState::Released => *mouse_pressing = false,
                 }
             }
+            Event::WindowEvent {
+                window_id,
+                event: WindowEvent::MouseWheel { delta, .. },
+            } if window_id == resources.get::<winit::window::Window>().unwrap().id() => {
+                let lines = match delta {
+                    winit::event::MouseScrollDelta::LineDelta(_, y) => y,
+                    winit::event::MouseScrollDelta::PixelDelta(position) => {
+                        position.y as f32 / WHEEL_LINE_PIXELS
+                    }
+                };
+                resources.get_mut::<Input>().unwrap().mouse_wheel += lines;
+            }
             // KeyboardInput
             Event::WindowEvent {
                 window_id,
@@ -831,6 +1618,44 @@ fn main() -> Result<(), String> {
                     VirtualKeyCode::Escape => {
                         *control_flow = ControlFlow::Exit;
                     }
+                    // A-B loop points at the current time
+                    VirtualKeyCode::LBracket | VirtualKeyCode::RBracket
+                        if state == winit::event::ElementState::Pressed =>
+                    {
+                        let controller = resources.get::<AudioController<f32>>().unwrap();
+                        let marker = if keycode == VirtualKeyCode::LBracket {
+                            LoopMarker::Start
+                        } else {
+                            LoopMarker::End
+                        };
+                        function::set_loop_marker(&controller, marker, controller.get_time());
+                    }
+                    VirtualKeyCode::Backslash if state == winit::event::ElementState::Pressed => {
+                        let controller = resources.get::<AudioController<f32>>().unwrap();
+                        controller.set_loop_region(None);
+                    }
+                    // save the playlist next to its first track
+                    VirtualKeyCode::S
+                        if state == winit::event::ElementState::Pressed
+                            && resources.get::<Input>().unwrap().ctrl_pressing =>
+                    {
+                        let playlist = resources.get::<Playlist>().unwrap();
+                        if let Some(first) = playlist.get_tracks().first() {
+                            let result = function::execute_or_relative_path(&first.path)
+                                .map(|path| function::playlist_path(&path))
+                                .and_then(|path| {
+                                    playlist::file::save(&path, playlist.get_tracks()).map(|_| path)
+                                });
+                            let notifications = resources.get::<Notifications>().unwrap();
+                            match result {
+                                Ok(path) => notifications
+                                    .info(format!("saved the playlist to {}", path.display())),
+                                Err(e) => {
+                                    notifications.error(format!("error saving the playlist: {}", e))
+                                }
+                            }
+                        }
+                    }
                     VirtualKeyCode::LControl | VirtualKeyCode::RControl => {
                         let ctrl_pressing =
                             &mut resources.get_mut::<Input>().unwrap().ctrl_pressing;
@@ -859,16 +1684,16 @@ fn main() -> Result<(), String> {
                 event: WindowEvent::DroppedFile(path),
             } if window_id == resources.get::<winit::window::Window>().unwrap().id() => {
                 let mut input = resources.get_mut::<Input>().unwrap();
-                input.drop_file = Some(path);
+                input.drop_files.push(path);
                 input.hover_file = false;
             }
             Event::WindowEvent {
                 window_id,
                 event: WindowEvent::Resized(size),
-            } if window_id == resources.get::<winit::window::Window>().unwrap().id() => {
-                if size.width & size.height != 0 {
-                    resources.get_mut::<Renderer>().unwrap().resize(size);
-                }
+            } if window_id == resources.get::<winit::window::Window>().unwrap().id()
+                && size.width & size.height != 0 =>
+            {
+                resources.get_mut::<Renderer>().unwrap().resize(size);
             }
             Event::MainEventsCleared if should_tick => {
                 schedule.execute(&mut world, &mut resources);
@@ -896,10 +1721,33 @@ fn main() -> Result<(), String> {
                         {
                             transforms.push(*transform);
                         }
-                        for (slider, slider_colors, transform) in
-                            <(Read<Slider>, Read<SliderColors>, Read<Transform>)>::query()
+                        let time_slider = resources.get::<ControlledSliders>().unwrap().time_slider;
+                        let buffer = Arc::clone(
+                            resources
+                                .get::<AudioController<f32>>()
+                                .unwrap()
+                                .get_target_buffer(),
+                        );
+                        for (entity, slider, slider_colors, transform) in
+                            <(Entity, Read<Slider>, Read<SliderColors>, Read<Transform>)>::query()
                                 .iter(&world)
                         {
+                            if *entity == time_slider {
+                                if let Some(peaks) = buffer.peaks() {
+                                    let columns = peaks.get_columns(
+                                        0..buffer.frame_count(),
+                                        pixel_columns(transform, inner_size),
+                                    );
+                                    transforms.extend(waveform_transforms(
+                                        transform,
+                                        &columns,
+                                        slider.get_value_mapped(),
+                                        slider_colors.current_color,
+                                        inner_size,
+                                    ));
+                                    continue;
+                                }
+                            }
                             let progress = Transform {
                                 location: [transform.location[0], transform.location[1]],
                                 size: [
@@ -910,13 +1758,72 @@ fn main() -> Result<(), String> {
                             };
                             transforms.push(progress);
                         }
+                        for (view, transform) in
+                            <(Read<WaveformView>, Read<Transform>)>::query().iter(&world)
+                        {
+                            let time = resources.get::<AudioController<f32>>().unwrap().get_time();
+                            let visible = view.get_visible(time);
+                            let columns = waveform::get_columns(
+                                &*buffer,
+                                visible.clone(),
+                                pixel_columns(transform, inner_size),
+                            );
+                            let playhead = (time - visible.start) / view.get_span();
+                            transforms.extend(waveform_transforms(
+                                transform,
+                                &columns,
+                                playhead,
+                                SLIDER_COLOR.base_color,
+                                inner_size,
+                            ));
+                            if (0.0..1.0).contains(&playhead) {
+                                let width = 2.0 / inner_size.width as f32;
+                                transforms.push(Transform {
+                                    location: [
+                                        transform.location[0] + transform.size[0] * playhead
+                                            - width * 0.5,
+                                        transform.location[1],
+                                    ],
+                                    size: [width, transform.size[1]],
+                                    color: LABEL_COLOR,
+                                });
+                            }
+                        }
+                        if let Ok(entry) = world.entry_ref(time_slider) {
+                            if let (Ok(slider), Ok(transform)) = (
+                                entry.get_component::<Slider>(),
+                                entry.get_component::<Transform>(),
+                            ) {
+                                let controller = resources.get::<AudioController<f32>>().unwrap();
+                                if let Some(region) = controller.get_loop_region() {
+                                    transforms.extend(loop_region_transforms(
+                                        transform,
+                                        slider.map_value(region.start)
+                                            ..slider.map_value(region.end),
+                                        controller.get_loop_mode(),
+                                    ));
+                                }
+                            }
+                        }
                     }
-                    if let Err(e) = resources
-                        .get_mut::<Renderer>()
-                        .unwrap()
-                        .render(&transforms, &render_pipeline)
+                    let mut texts: Vec<Text> =
+                        <Read<Text>>::query().iter(&world).cloned().collect();
                     {
-                        log::error!("render error: {}", e);
+                        let notifications = resources.get::<Notifications>().unwrap();
+                        let (bands, notification_texts) =
+                            notification_drawing(&notifications, inner_size);
+                        transforms.extend(bands);
+                        texts.extend(notification_texts);
+                    }
+                    if let Err(e) = resources.get_mut::<Renderer>().unwrap().render(
+                        &transforms,
+                        &texts,
+                        &render_pipeline,
+                    ) {
+                        resources
+                            .get::<Notifications>()
+                            .unwrap()
+                            .error(format!("render error: {}", e));
                     }
                 }
             }
@@ -931,7 +1838,8 @@ fn main() -> Result<(), String> {
                 ..
             } => *control_flow = ControlFlow::Exit,
             Event::LoopDestroyed => {
-                sender_end.send(()).unwrap();
+                // stops the audio output
+                resources.remove::<AudioController<f32>>();
                 log::info!("exit");
             }
             _ => {}
@@ -953,59 +1861,209 @@ fn relative_to_box(transform: &Transform, position: &(f32, f32)) -> (f32, f32) {
     (position.0 - left, position.1 - top)
 }
 
+// a column for each screen pixel across `transform`
+fn pixel_columns(transform: &Transform, window_size: winit::dpi::PhysicalSize<u32>) -> usize {
+    (transform.size[0] * 0.5 * window_size.width as f32).round() as usize
+}
+
+// the peaks of `columns` filling `area` from left to right,
+// the part before `played` (0.0 .. 1.0) in `played_color`
+fn waveform_transforms(
+    area: &Transform,
+    columns: &[Option<Peak>],
+    played: f32,
+    played_color: [f32; 3],
+    window_size: winit::dpi::PhysicalSize<u32>,
+) -> Vec<Transform> {
+    let column_width = area.size[0] / columns.len().max(1) as f32;
+    // silence is still a line a pixel high
+    let min_height = 2.0 / window_size.height as f32;
+    let middle = area.location[1] + area.size[1] * 0.5;
+    let half_height = area.size[1] * 0.5 * WAVEFORM_HEIGHT;
+    let played_columns = played.clamp(0.0, 1.0) * columns.len() as f32;
+    columns
+        .iter()
+        .enumerate()
+        .filter_map(|(column, peak)| {
+            let peak = (*peak)?;
+            let bottom = middle + peak.min.clamp(-1.0, 1.0) * half_height;
+            let top = middle + peak.max.clamp(-1.0, 1.0) * half_height;
+            Some(Transform {
+                location: [
+                    area.location[0] + column as f32 * column_width,
+                    bottom.min(middle - min_height * 0.5),
+                ],
+                size: [column_width, (top - bottom).max(min_height)],
+                color: if (column as f32) < played_columns {
+                    played_color
+                } else {
+                    WAVEFORM_COLOR
+                },
+            })
+        })
+        .collect()
+}
+
+// the name of the top button at `index`, in its middle
+fn top_button_label(label: &str, index: usize) -> Text {
+    Text::centered_in(
+        label,
+        [-1.0 + TOP_BUTTON_WIDTH * index as f32, TOP_BUTTON_BOTTOM],
+        [TOP_BUTTON_WIDTH, TOP_BUTTON_HEIGHT],
+        BUTTON_LABEL_SIZE,
+        LABEL_COLOR,
+    )
+}
+
+// a band along the bottom of the time slider with a marker at each end,
+// `region` is mapped to 0.0 .. 1.0 of the slider
+fn loop_region_transforms(
+    slider_transform: &Transform,
+    region: std::ops::Range<f32>,
+    active: bool,
+) -> [Transform; 3] {
+    let colors = if active {
+        LOOP_BUTTON_COLOR
+    } else {
+        NORMAL_BUTTON_COLOR
+    };
+    let x = |value: f32| {
+        slider_transform.location[0] + slider_transform.size[0] * value.clamp(0.0, 1.0)
+    };
+    let (start, end) = (x(region.start), x(region.end));
+    let marker = |x: f32| Transform {
+        location: [x - LOOP_MARKER_WIDTH * 0.5, slider_transform.location[1]],
+        size: [LOOP_MARKER_WIDTH, slider_transform.size[1]],
+        color: colors.press_color,
+    };
+    [
+        Transform {
+            location: [start, slider_transform.location[1]],
+            size: [end - start, slider_transform.size[1] * 0.15],
+            color: colors.hover_color,
+        },
+        marker(start),
+        marker(end),
+    ]
+}
+
+// the newest message at the bottom of the window, the older ones above it,
+// each on a band the color of its level
+fn notification_drawing(
+    notifications: &Notifications,
+    window_size: winit::dpi::PhysicalSize<u32>,
+) -> (Vec<Transform>, Vec<Text>) {
+    let pixel = [
+        2.0 * NOTIFICATION_FONT_SCALE / window_size.width as f32,
+        2.0 * NOTIFICATION_FONT_SCALE / window_size.height as f32,
+    ];
+    let line_height = (GLYPH_SIZE[1] + 2) as f32 * pixel[1];
+    let max_chars = text::chars_fitting(2.0 - 2.0 * pixel[0], pixel[0]);
+    let mut bands = Vec::new();
+    let mut texts = Vec::new();
+    for (line, notification) in notifications.get_shown().iter().rev().enumerate() {
+        let bottom = -1.0 + line as f32 * line_height;
+        let (band_color, text_color) = match notification.level {
+            Level::Info => (NORMAL_BUTTON_COLOR.press_color, [0.8, 0.9, 1.0]),
+            Level::Error => (LOAD_FAILED_BUTTON_COLOR.press_color, [1.0, 0.8, 0.8]),
+        };
+        bands.push(Transform {
+            location: [-1.0, bottom],
+            size: [2.0, line_height],
+            color: band_color,
+        });
+        let text: String = notification.text.chars().take(max_chars).collect();
+        texts.push(Text::new(
+            text,
+            [-1.0 + pixel[0], bottom + pixel[1]],
+            GLYPH_SIZE[1] as f32 * pixel[1],
+            text_color,
+        ));
+    }
+    (bands, texts)
+}
+
 fn smooth_to(current_value: f32, target_value: f32, change_speed: f32) -> f32 {
     current_value + (target_value - current_value) * change_speed
 }
 
 mod function {
-    use super::LOADING_BUTTON_COLOR;
+    use super::{LOADING_BUTTON_COLOR, NORMAL_BUTTON_COLOR, STRETCH_BUTTON_COLOR};
     use legion::{Entity, Resources, World};
-    use std::{path::PathBuf, str::FromStr};
+    use std::{
+        path::{Path, PathBuf},
+        str::FromStr,
+    };
 
     use super::entity::{
         button::ButtonColors,
-        resource::{audio::AudioBufferLoader, ButtonFunctions},
+        notification::Notifications,
+        playlist::Playlist,
+        resource::{
+            audio::{AudioBufferLoader, AudioController},
+            ButtonFunctions, ControlledButtons, LoopMarker, Setting,
+        },
         slider::Slider,
         ButtonFn, TargetValue,
     };
     use crate::entity::resource::audio::{AudioLoader, AudioLoaderRes};
 
+    /// The last part of the path, for showing it.
+    pub fn file_name(path: &str) -> &str {
+        Path::new(path)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(path)
+    }
+
+    /// `minutes:seconds`, or `hours:minutes:seconds` from an hour on.
+    pub fn format_time(seconds: f32) -> String {
+        let seconds = seconds.max(0.0).round() as u64;
+        let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+        if hours > 0 {
+            format!("{}:{:02}:{:02}", hours, minutes, seconds)
+        } else {
+            format!("{}:{:02}", minutes, seconds)
+        }
+    }
+
     pub fn execute_or_relative_path(path: &str) -> Result<PathBuf, String> {
-        if let Ok(relative_path) = PathBuf::from_str(path) {
-            if relative_path.is_absolute() {
-                return Ok(relative_path);
-            }
-            if let Ok(exe) = std::env::current_exe() {
-                if let Some(exe_path) = exe.parent() {
-                    let exe_path = exe_path.join(relative_path.clone());
-                    if exe_path.exists() {
-                        return Ok(exe_path);
-                    }
+        let relative_path = PathBuf::from_str(path).map_err(|_| "not a valid path".to_string())?;
+        if relative_path.is_absolute() {
+            return Ok(relative_path);
+        }
+        if let Ok(exe) = std::env::current_exe() {
+            if let Some(exe_path) = exe.parent() {
+                let exe_path = exe_path.join(relative_path.clone());
+                if exe_path.exists() {
+                    return Ok(exe_path);
                 }
             }
-            Ok(relative_path)
-        } else {
-            Err("not a valid path".to_string())
         }
+        Ok(relative_path)
     }
     pub fn load_music(
         world: &mut World,
         res: &Resources,
         load_button_entity: Entity,
-        path: &String,
+        path: &str,
+        setting: &Setting,
     ) {
         let path_buf = execute_or_relative_path(path);
         match path_buf {
             Err(e) => {
-                log::error!("error on getting path {}", e);
+                res.get::<Notifications>()
+                    .unwrap()
+                    .error(format!("error on getting path {}", e));
             }
             Ok(path_buf) => {
                 log::info!("loading {:?}", path_buf);
                 let mut loader = res.get_mut::<AudioLoaderRes>().unwrap();
                 *loader = Some(AudioLoader {
-                    loader: AudioBufferLoader::load(path_buf),
-                    path: path.clone(),
+                    loader: start_loading(path_buf, setting),
+                    path: path.to_string(),
                     load_button_entity,
+                    partial_loaded: false,
                 });
                 let stop_load_fn = &res.get::<ButtonFunctions>().unwrap().stop_load_fn;
                 if let Some(mut entry) = world.entry(load_button_entity) {
@@ -1021,11 +2079,117 @@ mod function {
                         slider.set_value(0.0);
                     }
 
-                    if let Ok(mut target_value) = entry.get_component_mut::<TargetValue>() {
+                    if let Ok(target_value) = entry.get_component_mut::<TargetValue>() {
                         target_value.0 = 0.0;
                     }
                 }
             }
         }
     }
+
+    /// Decodes the whole file or streams it, as the setting says.
+    pub fn start_loading(path_buf: PathBuf, setting: &Setting) -> AudioBufferLoader<f32> {
+        if setting.streaming {
+            AudioBufferLoader::load_streaming(path_buf, setting.sample_storage)
+        } else {
+            AudioBufferLoader::load(path_buf, setting.sample_storage)
+        }
+    }
+
+    /// Preloads the track the playlist moves on to, so it follows the current one
+    /// without a gap.
+    pub fn preload_next_track(res: &Resources) {
+        let path = res
+            .get_mut::<Playlist>()
+            .unwrap()
+            .peek_advance()
+            .map(str::to_string);
+        let mut controller = res.get_mut::<AudioController<f32>>().unwrap();
+        if controller.get_next_path() != path.as_deref() {
+            match path.map(|path| (execute_or_relative_path(&path), path)) {
+                Some((Ok(path_buf), path)) => {
+                    log::info!("preloading {:?}", path_buf);
+                    let setting = res.get::<Setting>().unwrap();
+                    controller.preload_next(path, start_loading(path_buf, &setting));
+                }
+                Some((Err(e), _)) => res
+                    .get::<Notifications>()
+                    .unwrap()
+                    .error(format!("error on getting path {}", e)),
+                None => controller.clear_next(),
+            }
+        }
+        controller.update_next();
+    }
+
+    /// Loads a track picked from the playlist, the load button shows the progress.
+    pub fn load_playlist_track(world: &mut World, res: &Resources, path: &str) {
+        let load_button_entity = res.get::<ControlledButtons>().unwrap().load_button;
+        let setting = res.get::<Setting>().unwrap();
+        load_music(world, res, load_button_entity, path, &setting);
+    }
+
+    // keep the pitch when changing speed, the button switches between turning it on and off
+    pub fn set_time_stretch(world: &mut World, res: &Resources, button_entity: Entity, on: bool) {
+        let controller = res.get::<AudioController<f32>>().unwrap();
+        controller.set_time_stretch(on);
+
+        let funcs = res.get::<ButtonFunctions>().unwrap();
+        if let Some(mut entry) = world.entry(button_entity) {
+            if let Ok(self_fn) = entry.get_component_mut::<ButtonFn>() {
+                *self_fn = std::sync::Arc::clone(if on {
+                    &funcs.unstretch_fn
+                } else {
+                    &funcs.stretch_fn
+                });
+            }
+            if let Ok(colors) = entry.get_component_mut::<ButtonColors>() {
+                *colors = if on {
+                    STRETCH_BUTTON_COLOR
+                } else {
+                    NORMAL_BUTTON_COLOR
+                };
+            }
+        }
+    }
+
+    /// Moves one point of the A-B loop region to `time`, the other point defaults to
+    /// the start or end of the track. Returns which point `time` became, since the
+    /// points swap when they cross.
+    pub fn set_loop_marker(
+        controller: &AudioController<f32>,
+        marker: LoopMarker,
+        time: f32,
+    ) -> LoopMarker {
+        let duration = controller.get_target_buffer().get_duration().as_secs_f32();
+        let region = controller.get_loop_region().unwrap_or(0.0..duration);
+        let other = match marker {
+            LoopMarker::Start => region.end,
+            LoopMarker::End => region.start,
+        };
+        controller.set_loop_region(Some(time..other));
+        if time <= other {
+            LoopMarker::Start
+        } else {
+            LoopMarker::End
+        }
+    }
+
+    /// The playlist starting with `track` is saved to `playlist.m3u8` next to it,
+    /// or to `playlist (2).m3u8` and so on when that file is already there.
+    pub fn playlist_path(track: &Path) -> PathBuf {
+        std::iter::once(track.with_file_name("playlist.m3u8"))
+            .chain((2..).map(|number| track.with_file_name(format!("playlist ({}).m3u8", number))))
+            .find(|path| !path.exists())
+            .unwrap()
+    }
+
+    /// `song.ogg` is exported to `song_export.wav` next to it.
+    pub fn export_path(source: &Path) -> PathBuf {
+        let stem = source
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        source.with_file_name(format!("{}_export.wav", stem))
+    }
 }
@@ -45,7 +45,19 @@ impl Default for Transform {
         }
     }
 }
-use std::path::PathBuf;
+// one character of a `Text`, drawn from the font atlas
+#[derive(Debug, Clone, Copy, AsBytes, FromBytes)]
+#[repr(C)]
+struct Glyph {
+    location: [f32; 2],
+    size: [f32; 2],
+    uv_location: [f32; 2],
+    uv_size: [f32; 2],
+    color: [f32; 3],
+}
+
+use crate::entity::text::{self, Text, GLYPH_SIZE};
+use std::{num::NonZeroU32, path::PathBuf};
 use wgpu::util::DeviceExt;
 
 pub struct PiplineSetting {
@@ -62,6 +74,8 @@ pub struct Renderer {
     index_buffer: wgpu::Buffer,
     bind_group: wgpu::BindGroup,
     bind_group_layout: wgpu::BindGroupLayout,
+    text_pipeline: wgpu::RenderPipeline,
+    text_bind_group: wgpu::BindGroup,
 }
 
 impl Renderer {
@@ -124,6 +138,9 @@ impl Renderer {
             (vb, ib)
         };
 
+        let (text_pipeline, text_bind_group) =
+            create_text_pipeline(&device, &queue, surface_config.format);
+
         Ok(Renderer {
             surface,
             _adapter: adapter,
@@ -134,6 +151,8 @@ impl Renderer {
             index_buffer,
             bind_group,
             bind_group_layout,
+            text_pipeline,
+            text_bind_group,
         })
     }
 
@@ -226,9 +245,11 @@ impl Renderer {
             }))
     }
 
+    /// Draws `transforms` with `render_pipeline`, then `texts` over them.
     pub fn render(
         &mut self,
         transforms: &[Transform],
+        texts: &[Text],
         render_pipeline: &wgpu::RenderPipeline,
     ) -> Result<(), String> {
         let frame = self
@@ -246,6 +267,14 @@ impl Renderer {
                 contents: transforms.as_bytes(),
                 usage: wgpu::BufferUsages::VERTEX,
             });
+        let glyphs = self.layout_texts(texts);
+        let glyph_buffer = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("glyphs buffer"),
+                contents: glyphs.as_bytes(),
+                usage: wgpu::BufferUsages::VERTEX,
+            });
 
         {
             let view = frame
@@ -269,9 +298,182 @@ impl Renderer {
             render_pass.set_vertex_buffer(1, transform_buffer.slice(..));
             render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
             render_pass.draw_indexed(0..INDECES.len() as u32, 0, 0..transforms.len() as u32);
+            if !glyphs.is_empty() {
+                render_pass.set_pipeline(&self.text_pipeline);
+                render_pass.set_bind_group(0, &self.text_bind_group, &[]);
+                render_pass.set_vertex_buffer(1, glyph_buffer.slice(..));
+                render_pass.draw_indexed(0..INDECES.len() as u32, 0, 0..glyphs.len() as u32);
+            }
         }
         self.queue.submit(Some(encoder.finish()));
         frame.present();
         Ok(())
     }
+
+    fn layout_texts(&self, texts: &[Text]) -> Vec<Glyph> {
+        let width = self.surface_config.width as f32;
+        let height = self.surface_config.height as f32;
+        // on the edges of the screen pixels, so the font pixels are not cut unevenly
+        let snap =
+            |value: f32, pixels: f32| ((value + 1.0) * pixels * 0.5).round() / pixels * 2.0 - 1.0;
+        let uv_size = [
+            GLYPH_SIZE[0] as f32 / text::ATLAS_SIZE[0] as f32,
+            GLYPH_SIZE[1] as f32 / text::ATLAS_SIZE[1] as f32,
+        ];
+        let mut glyphs = Vec::new();
+        for text in texts {
+            // a whole number of screen pixels for each font pixel keeps the glyphs sharp
+            let scale = (text.size * height * 0.5 / GLYPH_SIZE[1] as f32)
+                .round()
+                .max(1.0);
+            let pixel = [scale * 2.0 / width, scale * 2.0 / height];
+            for (c, location) in text.layout(pixel) {
+                if c == ' ' {
+                    continue;
+                }
+                let [u, v] = text::atlas_location(c);
+                glyphs.push(Glyph {
+                    location: [snap(location[0], width), snap(location[1], height)],
+                    size: [
+                        GLYPH_SIZE[0] as f32 * pixel[0],
+                        GLYPH_SIZE[1] as f32 * pixel[1],
+                    ],
+                    uv_location: [
+                        u as f32 / text::ATLAS_SIZE[0] as f32,
+                        v as f32 / text::ATLAS_SIZE[1] as f32,
+                    ],
+                    uv_size,
+                    color: text.color,
+                });
+            }
+        }
+        glyphs
+    }
+}
+
+// the pipeline drawing `Glyph`s, with the font atlas and the shader bundled into the program
+fn create_text_pipeline(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    format: wgpu::TextureFormat,
+) -> (wgpu::RenderPipeline, wgpu::BindGroup) {
+    let atlas_size = wgpu::Extent3d {
+        width: text::ATLAS_SIZE[0] as u32,
+        height: text::ATLAS_SIZE[1] as u32,
+        depth_or_array_layers: 1,
+    };
+    let atlas = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("font atlas"),
+        size: atlas_size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::R8Unorm,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+    });
+    queue.write_texture(
+        atlas.as_image_copy(),
+        &text::atlas(),
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: NonZeroU32::new(atlas_size.width),
+            rows_per_image: NonZeroU32::new(atlas_size.height),
+        },
+        atlas_size,
+    );
+    let atlas_view = atlas.create_view(&wgpu::TextureViewDescriptor::default());
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("font atlas sampler"),
+        mag_filter: wgpu::FilterMode::Nearest,
+        min_filter: wgpu::FilterMode::Nearest,
+        ..Default::default()
+    });
+
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("text bind group layout"),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+        ],
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("text bind group"),
+        layout: &bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&atlas_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&sampler),
+            },
+        ],
+    });
+
+    let shader_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+        label: Some("text shader"),
+        source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Borrowed(include_str!(
+            "../asset/shader/text.wgsl"
+        ))),
+    });
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: None,
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+    });
+    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("text pipeline"),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: &shader_module,
+            entry_point: "vs_main",
+            buffers: &[
+                wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2],
+                },
+                wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<Glyph>() as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    attributes: &wgpu::vertex_attr_array![
+                        2 => Float32x2,
+                        3 => Float32x2,
+                        4 => Float32x2,
+                        5 => Float32x2,
+                        6 => Float32x3,
+                    ],
+                },
+            ],
+        },
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        fragment: Some(wgpu::FragmentState {
+            module: &shader_module,
+            entry_point: "fs_main",
+            targets: &[wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            }],
+        }),
+        multiview: None,
+    });
+    (pipeline, bind_group)
 }